@@ -0,0 +1,84 @@
+//! Compares two dumps made by `publish::folder_dump`, e.g., to check that runs on different
+//! machines or with different versions of the tool give the same result.
+
+use crate::relative_files::RelativeFiles;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// One way in which two dumps differ. Paths are relative to the dump folders. For a merge missing
+/// from one dump, only its folder is reported rather than every file in it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DumpDifference {
+    /// Merge folder or file only present in the first dump
+    OnlyInLeft(PathBuf),
+    /// Merge folder or file only present in the second dump
+    OnlyInRight(PathBuf),
+    /// File present in both dumps, but with different content
+    Differs(PathBuf),
+}
+
+impl std::fmt::Display for DumpDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpDifference::OnlyInLeft(path) => write!(f, "only in left: {}", path.display()),
+            DumpDifference::OnlyInRight(path) => write!(f, "only in right: {}", path.display()),
+            DumpDifference::Differs(path) => write!(f, "differs: {}", path.display()),
+        }
+    }
+}
+
+/// Lists every difference between the dumps in `left` and `right`, sorted by kind and then path.
+/// Empty if they are the same.
+pub fn compare_dumps<P, Q>(left: P, right: Q) -> std::io::Result<Vec<DumpDifference>>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (left, right) = (left.as_ref(), right.as_ref());
+    let left_merges = merges(left);
+    let right_merges = merges(right);
+    let mut differences = BTreeSet::new();
+
+    for (merge, left_files) in &left_merges {
+        let Some(right_files) = right_merges.get(merge) else {
+            differences.insert(DumpDifference::OnlyInLeft(merge.clone()));
+            continue;
+        };
+        for file in left_files.difference(right_files) {
+            differences.insert(DumpDifference::OnlyInLeft(file.clone()));
+        }
+        for file in right_files.difference(left_files) {
+            differences.insert(DumpDifference::OnlyInRight(file.clone()));
+        }
+        for file in left_files.intersection(right_files) {
+            if std::fs::read(left.join(file))? != std::fs::read(right.join(file))? {
+                differences.insert(DumpDifference::Differs(file.clone()));
+            }
+        }
+    }
+    for merge in right_merges.keys() {
+        if !left_merges.contains_key(merge) {
+            differences.insert(DumpDifference::OnlyInRight(merge.clone()));
+        }
+    }
+
+    Ok(differences.into_iter().collect())
+}
+
+/// The files of a dump, grouped by the merge folder (first path component) they are in. Files at
+/// the root, such as `publish::RUN_META_FILE`, belong to no merge and are left out.
+fn merges(dump: &Path) -> BTreeMap<PathBuf, BTreeSet<PathBuf>> {
+    let mut merges: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+    for file in RelativeFiles::open(dump) {
+        if file.components().count() < 2 {
+            continue;
+        }
+        if let Some(merge) = file.components().next() {
+            merges
+                .entry(PathBuf::from(merge.as_os_str()))
+                .or_default()
+                .insert(file);
+        }
+    }
+    merges
+}
@@ -66,4 +66,3 @@ fn _commit_printing(repo: git2::Repository, revwalk: git2::Revwalk) {
         }
     }
 }
-
@@ -4,6 +4,7 @@
 //! effect. Original SZZ did it with cvs annotate (~ git blame)
 
 use regex::Regex;
+use std::borrow::Cow;
 use std::collections::HashSet;
 
 /// Doing this by means of the text in the summary. There are some methods available. Leaning
@@ -77,16 +78,100 @@ fn potential_bug_fix_summary(summary: &str) -> bool {
             // Added this one
             Regex::new("(?i)conflict").unwrap(),
         ];
-        static ref MERGE_MATCHER: Regex = Regex::new("(?i)merge").unwrap();
     }
     MY_MATCHERS.iter().any(|matcher| matcher.is_match(summary))
 }
 
+/// The summary of the commit, with bytes that are not valid UTF-8 replaced by U+FFFD. Messages in
+/// legacy encodings, e.g., Latin-1, thus still match the keywords around their accented words,
+/// where `git2::Commit::summary` would give up on them entirely.
+fn lossy_summary<'c>(commit: &'c git2::Commit) -> Cow<'c, str> {
+    commit
+        .summary_bytes()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default()
+}
+
+lazy_static! {
+    /// An issue reference, e.g., `#123` or `PROJ-123`. The number is the one group that matches.
+    static ref ISSUE_MATCHER: Regex = Regex::new(r"#(\d+)|\b[A-Z][A-Z0-9]+-(\d+)\b").unwrap();
+}
+
+/// The numbers of the issues the message references, e.g., 123 for both `#123` and `PROJ-123`.
+/// Numbers too large for a u32 are left out.
+pub fn issue_numbers(message: &str) -> HashSet<u32> {
+    ISSUE_MATCHER
+        .captures_iter(message)
+        .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .filter_map(|number| number.as_str().parse().ok())
+        .collect()
+}
+
+/// How likely it is that the commit is a bug fix for a merge, from 0.0 to 1.0: the sum of the
+/// `default_scorers`, i.e.,
+///
+/// * 0.4 if the summary contains one of the bug fix keywords, see `potential_bug_fix_summary`
+/// * 0.2 if the message references an issue, e.g., `#123` or `PROJ-123`
+/// * 0.2 for a small change, shrinking once more than 10 lines are changed
+/// * 0.2 if it changes one of `merge_files`, typically the files changed from O to M
+///
+/// Merge commits only get the message parts, as they have no single change to measure. See
+/// `score_with` to weigh other signals.
+pub fn fix_score(repo: &git2::Repository, fix: &git2::Oid, merge_files: &HashSet<String>) -> f64 {
+    score_with(repo, fix, &default_scorers(merge_files.clone()))
+}
+
+/// The scorers making up `fix_score`, with their weights.
+pub fn default_scorers(merge_files: HashSet<String>) -> Vec<Box<dyn FixScorer>> {
+    vec![
+        Box::new(Weighted {
+            weight: 0.4,
+            classifier: MessageClassifier { required: &[] },
+        }),
+        Box::new(Weighted {
+            weight: 0.2,
+            classifier: IssueReference,
+        }),
+        Box::new(SmallChange {
+            weight: 0.2,
+            lines: 10,
+        }),
+        Box::new(Weighted {
+            weight: 0.2,
+            classifier: ChangesOneOf { files: merge_files },
+        }),
+    ]
+}
+
+/// The sum of what the scorers give the commit, 0.0 if there is no such commit.
+pub fn score_with(
+    repo: &git2::Repository,
+    fix: &git2::Oid,
+    scorers: &[Box<dyn FixScorer + '_>],
+) -> f64 {
+    let Ok(commit) = repo.find_commit(*fix) else {
+        return 0.0;
+    };
+    scorers
+        .iter()
+        .map(|scorer| scorer.score(repo, &commit))
+        .sum()
+}
+
+/// Whether the commit is a merge, or at least claims to be one in its summary ("Merge branch
+/// ..."). Such commits match keywords like "conflict" without fixing anything themselves.
+fn looks_like_merge(commit: &git2::Commit) -> bool {
+    lazy_static! {
+        static ref MERGE_MATCHER: Regex = Regex::new("(?i)merge").unwrap();
+    }
+    commit.parent_count() > 1 || MERGE_MATCHER.is_match(&lossy_summary(commit))
+}
+
 fn _print_oids(repo: &git2::Repository, oids: &[git2::Oid]) {
     for descendant in oids {
         if let Ok(commit) = repo.find_commit(*descendant) {
-            let summary = commit.summary().unwrap_or("");
-            let is_bug_fix = potential_bug_fix_summary(summary);
+            let summary = lossy_summary(&commit);
+            let is_bug_fix = potential_bug_fix_summary(&summary);
             let time = commit.time().seconds();
             println!(
                 "[{}] {}: {} (fix? {})",
@@ -163,6 +248,60 @@ fn _print_blame(blame: &git2::BlameHunk) {
     );
 }
 
+/// Whether the fix was authored by the same person who committed the merge. People are compared
+/// by email, ignoring case. None if either email is missing or not valid UTF-8.
+pub fn same_author(repo: &git2::Repository, merge: &git2::Oid, fix: &git2::Oid) -> Option<bool> {
+    let merge = repo.find_commit(*merge).ok()?;
+    let fix = repo.find_commit(*fix).ok()?;
+    let merge_committer = merge.committer();
+    let fix_author = fix.author();
+    let merge_email = merge_committer.email().filter(|email| !email.is_empty())?;
+    let fix_email = fix_author.email().filter(|email| !email.is_empty())?;
+    Some(merge_email.eq_ignore_ascii_case(fix_email))
+}
+
+/// Which files of the merge a bug fix has to touch, see `BugFixFinder::changed_files`.
+#[derive(Clone, Copy, Default)]
+pub enum FixFileScope {
+    /// Files changed in O→A and in O→B
+    #[default]
+    Both,
+    /// Files changed in O→A and in O→B that were also changed in O→M, i.e., the change survived
+    /// into the merge
+    Om,
+    /// As `Both`, but only counting files changed by the non-merge commits on each branch's
+    /// first-parent chain. Slower, as it diffs every commit on both branches.
+    BothIgnoringMerges,
+    /// No restriction on the files
+    All,
+}
+
+impl FixFileScope {
+    /// The files a fix has to touch one of. None if there is no restriction.
+    pub fn files(
+        &self,
+        repo: &git2::Repository,
+        twm: &crate::merge::ThreeWayMerge,
+    ) -> Option<HashSet<String>> {
+        match self {
+            FixFileScope::Both => Some(twm.files_changed_in_both_branches(repo)),
+            FixFileScope::Om => {
+                let o_to_m = twm.files_to_consider(repo);
+                Some(
+                    twm.files_changed_in_both_branches(repo)
+                        .into_iter()
+                        .filter(|file| o_to_m.contains(file))
+                        .collect(),
+                )
+            }
+            FixFileScope::BothIgnoringMerges => {
+                Some(twm.files_changed_in_both_branches_ignoring_merges(repo))
+            }
+            FixFileScope::All => None,
+        }
+    }
+}
+
 /// Alternative approach: first set all options, _then_ start looking for potential fixes. Might
 /// avoid creating Vecs all the time.
 pub struct BugFixFinder<'a> {
@@ -176,52 +315,113 @@ impl<'a> BugFixFinder<'a> {
     pub fn find(repo: &'a git2::Repository, ancestor_str: &str) -> Result<Self, git2::Error> {
         let ancestor_oid = git2::Oid::from_str(ancestor_str)?;
 
-        let descendants = crate::git_utils::get_descendants(repo, ancestor_oid)?;
+        let descendants =
+            crate::git_utils::get_descendants_bounded_until(repo, ancestor_oid, u32::MAX, None)?;
+        Ok(Self {
+            fixes: descendants,
+            repo,
+        })
+    }
+
+    /// Finds descendants starting from a certain commit, but only up to `max_gen` generations away.
+    /// Much faster than `find` followed by `within_n_generations` when the distance is small.
+    pub fn find_within(
+        repo: &'a git2::Repository,
+        ancestor_str: &str,
+        max_gen: u32,
+    ) -> Result<Self, git2::Error> {
+        let ancestor_oid = git2::Oid::from_str(ancestor_str)?;
+
+        let descendants = crate::git_utils::get_descendants_bounded(repo, ancestor_oid, max_gen)?;
         Ok(Self {
             fixes: descendants,
             repo,
         })
     }
 
-    /// Consume self to get a Vec of potential fixes
+    /// Same as `find_within`, but if `max_time` (epoch seconds) is given, commits made after it
+    /// are not considered either.
+    pub fn find_within_until(
+        repo: &'a git2::Repository,
+        ancestor_str: &str,
+        max_gen: u32,
+        max_time: Option<i64>,
+    ) -> Result<Self, git2::Error> {
+        let ancestor_oid = git2::Oid::from_str(ancestor_str)?;
+
+        let descendants =
+            crate::git_utils::get_descendants_bounded_until(repo, ancestor_oid, max_gen, max_time)?;
+        Ok(Self {
+            fixes: descendants,
+            repo,
+        })
+    }
+
+    /// Same as `find_within_until`, but looks the descendants up in `children` rather than walking
+    /// history again. For finding the fixes of many merges in one go.
+    pub fn find_in(
+        repo: &'a git2::Repository,
+        children: &crate::git_utils::ChildMap,
+        ancestor: git2::Oid,
+        max_gen: u32,
+        max_time: Option<i64>,
+    ) -> Result<Self, git2::Error> {
+        let descendants = children.descendants_bounded_until(repo, ancestor, max_gen, max_time)?;
+        Ok(Self {
+            fixes: descendants,
+            repo,
+        })
+    }
+
+    /// Consume self to get a Vec of potential fixes. Nearest first: by generation, then commit
+    /// time, then hash, see `git_utils::get_descendants_bounded`. The order is thus the same on
+    /// every run, so is the choice of the first few fixes.
     pub fn collect(self) -> Vec<git2::Oid> {
         self.fixes
     }
 
     /// Filters out fixes whose commit msg does not match.
     ///
-    /// TODO: matchers not actually used at the moment.
-    pub fn msg_contains(&mut self, _matchers: Vec<Regex>) {
-        // Need to collect into a Vec, otherwise all the iterators retain the immutable borrow on
-        // descendants for too long.
-        let not_a_fix: Vec<_> = self
-            .fixes
-            .iter()
-            .enumerate()
-            .filter(
-                |(_ctr, descendant)| match self.repo.find_commit(**descendant) {
-                    Ok(commit) => {
-                        let summary = commit.summary().unwrap_or("");
-                        !potential_bug_fix_summary(summary) // TODO: Use matchers
-                    }
-                    Err(e) => {
-                        eprintln!(
-                    "Failed to find commit for descendant {} ??? This should not happen. Error: {}",
-                    descendant, e
-                );
-                        true
-                    }
-                },
-            )
-            .map(|(ctr, _)| ctr)
-            // Rev is important! If we remove from the front, then the indices that come after are no
-            // longer valid
-            .rev()
-            .collect();
-
-        for idx in not_a_fix {
-            self.fixes.remove(idx);
-        }
+    /// Without `required` groups, the summary has to contain one of the usual bug fix keywords. See
+    /// `potential_bug_fix_summary`. Otherwise the full message has to match every one of the
+    /// groups. Alternatives within a group are written as a regex alternation, so `fix|bug` and
+    /// `#\d+` together mean "(fix OR bug) AND an issue reference".
+    pub fn msg_contains(&mut self, required: &[Regex]) {
+        self.classify(&MessageClassifier { required });
+    }
+
+    /// Filters out fixes the classifier does not take for one. `msg_contains` is this with a
+    /// `MessageClassifier`.
+    pub fn classify(&mut self, classifier: &dyn FixClassifier) {
+        let repo = self.repo;
+        self.fixes.retain(|fix| classified(repo, fix, classifier));
+    }
+
+    /// Filters out fixes whose `fix_score` is below the threshold.
+    pub fn min_score(&mut self, threshold: f64, merge_files: &HashSet<String>) {
+        self.min_score_with(threshold, &default_scorers(merge_files.clone()));
+    }
+
+    /// Filters out fixes the scorers together give less than the threshold, see `score_with`.
+    pub fn min_score_with(&mut self, threshold: f64, scorers: &[Box<dyn FixScorer + '_>]) {
+        let repo = self.repo;
+        self.fixes
+            .retain(|fix| score_with(repo, fix, scorers) >= threshold);
+    }
+
+    /// Filters out fixes that are merge commits or whose summary mentions a merge. See
+    /// `looks_like_merge`.
+    pub fn exclude_merges(&mut self) {
+        let repo = self.repo;
+        self.fixes.retain(|fix| not_a_merge(repo, fix));
+    }
+
+    /// Filters out fixes whose message does not reference one of `issues`, e.g., the issues an
+    /// issue tracker confirms to be bugs. See `issue_numbers`.
+    pub fn references_bug(&mut self, issues: &HashSet<u32>) {
+        let repo = self.repo;
+        self.fixes
+            .retain(|fix| references_one_of(repo, fix, issues));
     }
 
     /// Keep the fix if it is within a certain number of generations from the given commit. (fix is
@@ -232,57 +432,332 @@ impl<'a> BugFixFinder<'a> {
         commit: &git2::Oid,
         fix_distance: u32,
     ) {
-        self.fixes = self
-            .fixes
-            .iter()
-            .filter(|child| {
-                crate::git_utils::within_n_generations(repo, &commit, child, fix_distance)
-            })
-            .map(|child| *child)
-            .collect();
+        self.fixes.retain(|child| {
+            crate::git_utils::within_n_generations(repo, commit, child, fix_distance)
+        });
     }
 
     /// Keep the fix only if it changes at least one of the files given in `merge_changes`
     pub fn changed_files(&mut self, repo: &git2::Repository, merge_changes: HashSet<String>) {
-        self.fixes = self
-            .fixes
-            .iter()
-            .filter(|child| {
-                let child_commit = repo.find_commit(**child).unwrap();
-                if child_commit.parent_count() != 1 {
-                    return false;
-                }
-                let bfc_parent = child_commit.parent_id(0).unwrap();
-
-                // Keep bugfixing commit if changed file was also changed in O→A AND in O→B
-                let bugfix_changes = crate::git_utils::changed_filenames(repo, &bfc_parent, child);
-                merge_changes.intersection(&bugfix_changes).next().is_some()
-            })
-            .map(|child| *child)
-            .collect();
+        self.fixes
+            .retain(|child| changes_one_of(repo, child, &merge_changes));
     }
 
     /// Keep the fix only if it changes the same line as one that was changed from O→M _and_ that
-    /// change was in a file ending in one of the extensions.
+    /// change was in a file ending in one of the extensions. With `context_lines`, lines that
+    /// close to a change of the fix count as well, see `git_utils::OverlapLines`.
+    ///
+    /// With `explain`, prints to STDERR for every kept fix which line triggered the overlap and
+    /// which commit blame attributed it to.
     pub fn changed_same_line_in_ext(
         &mut self,
         repo: &git2::Repository,
         twm: &crate::merge::ThreeWayMerge,
         only_extensions: &[&str],
+        context_lines: u32,
+        explain: bool,
     ) {
-        self.fixes = self
-            .fixes
-            .iter()
-            .filter(|child| {
-                let child_commit = repo.find_commit(**child).unwrap();
-                if child_commit.parent_count() != 1 {
-                    return false;
-                }
-                let bfc_parent = child_commit.parent_id(0).unwrap();
-
-                crate::git_utils::changed_same_line(repo, &twm.o, &twm.m, &bfc_parent, child, only_extensions)
-            })
-            .map(|child| *child)
-            .collect();
+        let lines = crate::git_utils::OverlapLines {
+            only_extensions,
+            context_lines,
+        };
+        // All candidates are checked against the same O→M window, so blames can be shared.
+        let mut blames = crate::git_utils::BlameCache::new();
+        self.fixes
+            .retain(|child| changes_same_line(repo, twm, child, &lines, explain, &mut blames));
+    }
+
+    /// Turns this into a `LazyFixes`, which only checks as many candidates as needed.
+    pub fn lazy(self) -> LazyFixes<'a> {
+        LazyFixes {
+            fixes: Box::new(self.fixes.into_iter()),
+            repo: self.repo,
+        }
+    }
+}
+
+/// Lazy counterpart of `BugFixFinder`. The same filters are chained as iterator adapters over the
+/// candidates, nearest first, so a candidate is only checked once it is asked for. Taking just the
+/// first few fixes thus skips the diffs and blames of all candidates after them:
+///
+/// ```no_run
+/// # use three_way_merge_finder::find_bug_fix::BugFixFinder;
+/// # let repo = git2::Repository::open(".").unwrap();
+/// # let merge = "0000000000000000000000000000000000000000";
+/// let fixes: Vec<_> = BugFixFinder::find_within(&repo, merge, 10)
+///     .unwrap()
+///     .lazy()
+///     .msg_contains(&[])
+///     .exclude_merges()
+///     .take(3)
+///     .collect();
+/// ```
+///
+/// Yields the same fixes, in the same order, as the eager filters of `BugFixFinder`.
+pub struct LazyFixes<'a> {
+    fixes: Box<dyn Iterator<Item = git2::Oid> + 'a>,
+    repo: &'a git2::Repository,
+}
+
+impl<'a> LazyFixes<'a> {
+    /// Lazy `BugFixFinder::msg_contains`.
+    pub fn msg_contains(self, required: &'a [Regex]) -> Self {
+        self.classify(Box::new(MessageClassifier { required }))
+    }
+
+    /// Lazy `BugFixFinder::classify`.
+    pub fn classify(self, classifier: Box<dyn FixClassifier + 'a>) -> Self {
+        let repo = self.repo;
+        self.adapt(move |fix| classified(repo, fix, classifier.as_ref()))
+    }
+
+    /// Lazy `BugFixFinder::min_score`.
+    pub fn min_score(self, threshold: f64, merge_files: HashSet<String>) -> Self {
+        self.min_score_with(threshold, default_scorers(merge_files))
+    }
+
+    /// Lazy `BugFixFinder::min_score_with`.
+    pub fn min_score_with(self, threshold: f64, scorers: Vec<Box<dyn FixScorer + 'a>>) -> Self {
+        let repo = self.repo;
+        self.adapt(move |fix| score_with(repo, fix, &scorers) >= threshold)
+    }
+
+    /// Lazy `BugFixFinder::exclude_merges`.
+    pub fn exclude_merges(self) -> Self {
+        let repo = self.repo;
+        self.adapt(move |fix| not_a_merge(repo, fix))
+    }
+
+    /// Lazy `BugFixFinder::references_bug`.
+    pub fn references_bug(self, issues: &'a HashSet<u32>) -> Self {
+        let repo = self.repo;
+        self.adapt(move |fix| references_one_of(repo, fix, issues))
+    }
+
+    /// Lazy `BugFixFinder::within_n_generations`.
+    pub fn within_n_generations(self, commit: git2::Oid, fix_distance: u32) -> Self {
+        let repo = self.repo;
+        self.adapt(move |child| {
+            crate::git_utils::within_n_generations(repo, &commit, child, fix_distance)
+        })
+    }
+
+    /// Lazy `BugFixFinder::changed_files`.
+    pub fn changed_files(self, merge_changes: HashSet<String>) -> Self {
+        let repo = self.repo;
+        self.adapt(move |child| changes_one_of(repo, child, &merge_changes))
+    }
+
+    /// Lazy `BugFixFinder::changed_same_line_in_ext`.
+    pub fn changed_same_line_in_ext(
+        self,
+        twm: &'a crate::merge::ThreeWayMerge,
+        only_extensions: &'a [&'a str],
+        context_lines: u32,
+        explain: bool,
+    ) -> Self {
+        let repo = self.repo;
+        let lines = crate::git_utils::OverlapLines {
+            only_extensions,
+            context_lines,
+        };
+        let mut blames = crate::git_utils::BlameCache::new();
+        self.adapt(move |child| changes_same_line(repo, twm, child, &lines, explain, &mut blames))
+    }
+
+    fn adapt<P>(self, mut keep: P) -> Self
+    where
+        P: FnMut(&git2::Oid) -> bool + 'a,
+    {
+        Self {
+            fixes: Box::new(self.fixes.filter(move |fix| keep(fix))),
+            repo: self.repo,
+        }
+    }
+}
+
+impl Iterator for LazyFixes<'_> {
+    type Item = git2::Oid;
+
+    fn next(&mut self) -> Option<git2::Oid> {
+        self.fixes.next()
+    }
+}
+
+/// Decides whether a commit is a bug fix, for `BugFixFinder::classify`. Implement this to plug in
+/// another heuristic, e.g., a trained model, instead of the keywords of `MessageClassifier`.
+pub trait FixClassifier {
+    fn is_fix(&self, repo: &git2::Repository, commit: &git2::Commit) -> bool;
+}
+
+/// The default heuristic, see `BugFixFinder::msg_contains`.
+pub struct MessageClassifier<'r> {
+    /// Patterns the full message has to match all of. The usual keywords in the summary if empty.
+    pub required: &'r [Regex],
+}
+
+impl FixClassifier for MessageClassifier<'_> {
+    fn is_fix(&self, _repo: &git2::Repository, commit: &git2::Commit) -> bool {
+        if self.required.is_empty() {
+            potential_bug_fix_summary(&lossy_summary(commit))
+        } else {
+            let message = String::from_utf8_lossy(commit.message_bytes());
+            self.required.iter().all(|group| group.is_match(&message))
+        }
+    }
+}
+
+/// Adds to the score of a commit, see `score_with`. Implement this to weigh in another signal
+/// next to those of `default_scorers`.
+pub trait FixScorer {
+    fn score(&self, repo: &git2::Repository, commit: &git2::Commit) -> f64;
+}
+
+/// Gives `weight` to the commits the classifier takes for a fix, nothing to others.
+pub struct Weighted<C> {
+    pub weight: f64,
+    pub classifier: C,
+}
+
+impl<C: FixClassifier> FixScorer for Weighted<C> {
+    fn score(&self, repo: &git2::Repository, commit: &git2::Commit) -> f64 {
+        if self.classifier.is_fix(repo, commit) {
+            self.weight
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Takes commits whose message references an issue for fixes, see `issue_numbers`.
+pub struct IssueReference;
+
+impl FixClassifier for IssueReference {
+    fn is_fix(&self, _repo: &git2::Repository, commit: &git2::Commit) -> bool {
+        ISSUE_MATCHER.is_match(&String::from_utf8_lossy(commit.message_bytes()))
+    }
+}
+
+/// Takes commits that change one of `files` for fixes. Merge commits never are.
+pub struct ChangesOneOf {
+    pub files: HashSet<String>,
+}
+
+impl FixClassifier for ChangesOneOf {
+    fn is_fix(&self, repo: &git2::Repository, commit: &git2::Commit) -> bool {
+        commit.parent_count() == 1
+            && !crate::git_utils::changed_filenames(
+                repo,
+                &commit.parent_id(0).unwrap(),
+                &commit.id(),
+            )
+            .is_disjoint(&self.files)
+    }
+}
+
+/// Gives the full `weight` to commits changing up to `lines` lines, and less the more lines a
+/// commit changes beyond that. Merge commits get nothing.
+pub struct SmallChange {
+    pub weight: f64,
+    pub lines: usize,
+}
+
+impl FixScorer for SmallChange {
+    fn score(&self, repo: &git2::Repository, commit: &git2::Commit) -> f64 {
+        if commit.parent_count() != 1 {
+            return 0.0;
+        }
+        let changed =
+            crate::git_utils::changed_line_count(repo, &commit.parent_id(0).unwrap(), &commit.id());
+        self.weight * (self.lines as f64 / changed.max(self.lines) as f64)
+    }
+}
+
+/// See `BugFixFinder::classify`.
+fn classified(repo: &git2::Repository, fix: &git2::Oid, classifier: &dyn FixClassifier) -> bool {
+    match repo.find_commit(*fix) {
+        Ok(commit) => classifier.is_fix(repo, &commit),
+        Err(e) => {
+            recoverable_error!(
+                "Failed to find commit for descendant {} ??? This should not happen. Error: {}",
+                fix,
+                e
+            );
+            false
+        }
+    }
+}
+
+/// See `BugFixFinder::references_bug`.
+fn references_one_of(repo: &git2::Repository, fix: &git2::Oid, issues: &HashSet<u32>) -> bool {
+    match repo.find_commit(*fix) {
+        Ok(commit) => {
+            !issue_numbers(&String::from_utf8_lossy(commit.message_bytes())).is_disjoint(issues)
+        }
+        Err(_) => false,
+    }
+}
+
+/// See `BugFixFinder::exclude_merges`.
+fn not_a_merge(repo: &git2::Repository, fix: &git2::Oid) -> bool {
+    match repo.find_commit(*fix) {
+        Ok(commit) => !looks_like_merge(&commit),
+        Err(_) => false,
+    }
+}
+
+/// See `BugFixFinder::changed_files`.
+fn changes_one_of(
+    repo: &git2::Repository,
+    child: &git2::Oid,
+    merge_changes: &HashSet<String>,
+) -> bool {
+    let child_commit = repo.find_commit(*child).unwrap();
+    if child_commit.parent_count() != 1 {
+        return false;
+    }
+    let bfc_parent = child_commit.parent_id(0).unwrap();
+
+    // Keep bugfixing commit if changed file was also changed in O→A AND in O→B
+    let bugfix_changes = crate::git_utils::changed_filenames(repo, &bfc_parent, child);
+    merge_changes.intersection(&bugfix_changes).next().is_some()
+}
+
+/// See `BugFixFinder::changed_same_line_in_ext`.
+fn changes_same_line<'repo>(
+    repo: &'repo git2::Repository,
+    twm: &crate::merge::ThreeWayMerge,
+    child: &git2::Oid,
+    lines: &crate::git_utils::OverlapLines,
+    explain: bool,
+    blames: &mut crate::git_utils::BlameCache<'repo>,
+) -> bool {
+    let child_commit = repo.find_commit(*child).unwrap();
+    if child_commit.parent_count() != 1 {
+        return false;
+    }
+    let bfc_parent = child_commit.parent_id(0).unwrap();
+
+    let overlap = crate::git_utils::same_line_overlap(
+        repo,
+        &twm.o,
+        &twm.m,
+        &bfc_parent,
+        child,
+        lines,
+        blames,
+    );
+    if explain {
+        if let Some(overlap) = &overlap {
+            eprintln!(
+                "Fix {} of merge {} changes {}:{}, last changed in {}",
+                child,
+                twm.m,
+                overlap.path.display(),
+                overlap.line,
+                overlap.blamed_commit
+            );
+        }
     }
+    overlap.is_some()
 }
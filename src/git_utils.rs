@@ -1,25 +1,201 @@
+use crate::sink::BlobSink;
 use git2::{Blame, BlameOptions, Diff, DiffLineType, DiffOptions, Oid, Repository, Revwalk};
-use std::collections::HashSet;
-use std::io::prelude::*;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-/// Creates a toplogical revwalk over a repository, starting at HEAD.
-pub fn create_revwalk(repo: &Repository) -> Result<Revwalk, git2::Error> {
+/// Creates a revwalk over a repository, starting at HEAD. The sorting decides in which order the
+/// commits (and thus the merges) are encountered. `git2::Sort::TOPOLOGICAL` is what the tool has
+/// always used.
+pub fn create_revwalk(repo: &Repository, sorting: git2::Sort) -> Result<Revwalk<'_>, git2::Error> {
+    create_revwalk_range(repo, sorting, None, None)
+}
+
+/// Like `create_revwalk`, but starts from `until` instead of HEAD if given, and leaves out
+/// everything reachable from `since` if given. Meant for walking the commits between two releases.
+pub fn create_revwalk_range(
+    repo: &Repository,
+    sorting: git2::Sort,
+    since: Option<Oid>,
+    until: Option<Oid>,
+) -> Result<Revwalk<'_>, git2::Error> {
     let mut revwalk = repo.revwalk()?;
     // Pushing marks a commit to start traversal from
-    revwalk.push_head()?;
-    revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+    match until {
+        Some(until) => revwalk.push(until)?,
+        None => push_head(&mut revwalk, repo)?,
+    }
+    if let Some(since) = since {
+        revwalk.hide(since)?;
+    }
+    revwalk.set_sorting(sorting)?;
     Ok(revwalk)
 }
 
+/// Creates a revwalk over the commits a git rev-spec selects, like `git log` would:
+///
+/// * `rev`: everything reachable from `rev`
+/// * `from..to`: reachable from `to`, but not from `from`
+/// * `from...to`: reachable from either, but not from both, i.e., leaving out their merge bases
+///   and everything before them
+///
+/// `to` defaults to HEAD when left out (`v1.0..`).
+pub fn create_revwalk_spec<'repo>(
+    repo: &'repo Repository,
+    sorting: git2::Sort,
+    spec: &str,
+) -> Result<Revwalk<'repo>, git2::Error> {
+    let revspec = repo.revparse(spec)?;
+    let from = revspec
+        .from()
+        .map(|object| object.peel_to_commit())
+        .transpose()?;
+    let to = revspec
+        .to()
+        .map(|object| object.peel_to_commit())
+        .transpose()?;
+    let mut revwalk = repo.revwalk()?;
+    if revspec.mode().contains(git2::RevparseMode::SINGLE) {
+        let from = from.ok_or_else(|| git2::Error::from_str("Empty rev-spec"))?;
+        revwalk.push(from.id())?;
+    } else {
+        let from = from.ok_or_else(|| git2::Error::from_str("Rev-spec has no start"))?;
+        match &to {
+            Some(to) => revwalk.push(to.id())?,
+            None => push_head(&mut revwalk, repo)?,
+        }
+        if revspec.mode().contains(git2::RevparseMode::MERGE_BASE) {
+            let to = match to {
+                Some(to) => to.id(),
+                None => repo.head()?.peel_to_commit()?.id(),
+            };
+            revwalk.push(from.id())?;
+            for base in repo.merge_bases(from.id(), to)?.iter() {
+                revwalk.hide(*base)?;
+            }
+        } else {
+            revwalk.hide(from.id())?;
+        }
+    }
+    revwalk.set_sorting(sorting)?;
+    Ok(revwalk)
+}
+
+/// Marks HEAD as a starting point of the revwalk. Should `push_head` fail, e.g., on an odd CI
+/// checkout with a detached HEAD, pushes the commit HEAD resolves to instead. Refs in packed-refs
+/// are resolved by git2 like any other.
+fn push_head(revwalk: &mut Revwalk, repo: &Repository) -> Result<(), git2::Error> {
+    if revwalk.push_head().is_ok() {
+        return Ok(());
+    }
+    let head = repo.head()?.peel_to_commit()?;
+    revwalk.push(head.id())
+}
+
+/// Find the commit a tag points to. Works for both lightweight and annotated tags.
+pub fn resolve_tag(repo: &Repository, tag: &str) -> Result<Oid, git2::Error> {
+    let reference = repo.find_reference(&format!("refs/tags/{}", tag))?;
+    Ok(reference.peel_to_commit()?.id())
+}
+
+/// The shortest prefix of `oid` (at least git's `core.abbrev`, 7 by default) that no other object
+/// in the repository starts with. Falls back to the full hash should git2 fail to shorten it.
+pub fn short_sha(repo: &Repository, oid: Oid) -> String {
+    repo.find_object(oid, None)
+        .and_then(|object| object.short_id())
+        .ok()
+        .and_then(|short| short.as_str().map(|short| short.to_owned()))
+        .unwrap_or_else(|| oid.to_string())
+}
+
+/// The commits on the first-parent chain of HEAD: HEAD, its first parent, that one's first parent,
+/// and so on. A merge on this chain was integrated into the current branch itself, rather than
+/// into some branch that was merged later on.
+pub fn first_parent_chain(repo: &Repository) -> Result<HashSet<Oid>, git2::Error> {
+    let mut chain = HashSet::new();
+    let mut commit = repo.head()?.peel_to_commit()?;
+    loop {
+        chain.insert(commit.id());
+        if commit.parent_count() == 0 {
+            break;
+        }
+        commit = commit.parent(0)?;
+    }
+    Ok(chain)
+}
+
+/// `path` and every name the file had before, following renames back from HEAD the way
+/// `git log --follow` does. Renames are detected by content similarity in each non-merge commit,
+/// so this diffs every commit in the history once.
+pub fn former_paths(repo: &Repository, path: &str) -> Result<HashSet<String>, git2::Error> {
+    let mut paths = HashSet::from([path.to_owned()]);
+    let mut revwalk = repo.revwalk()?;
+    push_head(&mut revwalk, repo)?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() != 1 {
+            continue;
+        }
+        let mut diff = repo.diff_tree_to_tree(
+            Some(&commit.parent(0)?.tree()?),
+            Some(&commit.tree()?),
+            None,
+        )?;
+        diff.find_similar(Some(git2::DiffFindOptions::new().renames(true)))?;
+        for delta in diff.deltas() {
+            if delta.status() != git2::Delta::Renamed {
+                continue;
+            }
+            let new_path = delta.new_file().path().and_then(|path| path.to_str());
+            let old_path = delta.old_file().path().and_then(|path| path.to_str());
+            if let (Some(new_path), Some(old_path)) = (new_path, old_path) {
+                if paths.contains(new_path) {
+                    paths.insert(old_path.to_owned());
+                }
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Time (epoch seconds) of the oldest root commit reachable from HEAD, i.e., when the project
+/// started.
+pub fn first_commit_time(repo: &Repository) -> Result<i64, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    push_head(&mut revwalk, repo)?;
+    let mut first = None;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() == 0 {
+            let time = commit.time().seconds();
+            first = Some(first.map_or(time, |first: i64| first.min(time)));
+        }
+    }
+    first.ok_or_else(|| git2::Error::from_str("No root commit reachable from HEAD"))
+}
+
 /// Given two Oids, finds the commits, their trees, diffs the trees.
 fn diff_commits<'a>(
     repo: &'a Repository,
     old: &'a Oid,
     new: &'a Oid,
+) -> Result<Diff<'a>, git2::Error> {
+    // Three lines of context is what git uses by default.
+    diff_commits_with_context(repo, old, new, 3)
+}
+
+/// Same as `diff_commits`, but with the given number of unchanged lines around each change.
+fn diff_commits_with_context<'a>(
+    repo: &'a Repository,
+    old: &'a Oid,
+    new: &'a Oid,
+    context_lines: u32,
 ) -> Result<Diff<'a>, git2::Error> {
     let mut diffoptions = DiffOptions::new();
-    diffoptions.minimal(true).ignore_whitespace(true);
+    diffoptions
+        .minimal(true)
+        .ignore_whitespace(true)
+        .context_lines(context_lines);
     let old = repo.find_commit(*old)?;
     let old_tree = old.tree()?;
     let new = repo.find_commit(*new)?;
@@ -54,6 +230,136 @@ pub fn changed_filenames(repo: &Repository, old: &Oid, new: &Oid) -> HashSet<Str
     paths
 }
 
+/// Like `changed_filenames` from `old` to `new`, but only counting the changes of the non-merge
+/// commits on the first-parent chain from `new` back to `old`. Files brought in by merges within
+/// the branch are left out. This diffs every commit on the chain against its parent, so it costs
+/// one diff per commit instead of a single tree diff.
+pub fn changed_filenames_ignoring_merges(
+    repo: &Repository,
+    old: &Oid,
+    new: &Oid,
+) -> Result<HashSet<String>, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.simplify_first_parent()?;
+    revwalk.push(*new)?;
+    revwalk.hide(*old)?;
+    let mut paths = HashSet::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() != 1 {
+            continue;
+        }
+        paths.extend(changed_filenames(repo, &commit.parent_id(0)?, &commit.id()));
+    }
+    Ok(paths)
+}
+
+/// Given two commits, does a diff and returns the number of lines added plus lines removed.
+pub fn changed_line_count(repo: &Repository, old: &Oid, new: &Oid) -> usize {
+    let (insertions, deletions) = line_stats(repo, old, new);
+    insertions + deletions
+}
+
+/// Given two commits, does a diff and returns the number of lines added and the number of lines
+/// removed.
+pub fn line_stats(repo: &Repository, old: &Oid, new: &Oid) -> (usize, usize) {
+    let stats = diff_commits(repo, old, new)
+        .and_then(|diff| diff.stats())
+        .expect("Should be able to diff old to new");
+    (stats.insertions(), stats.deletions())
+}
+
+/// Given two commits, does a diff and returns the number of hunks in it, with git's default of
+/// three lines of context. Changes closer together than that share a hunk. Cheaper than counting
+/// lines, and tells how scattered a change is.
+pub fn hunk_count(repo: &Repository, old: &Oid, new: &Oid) -> usize {
+    let diff = diff_commits(repo, old, new).expect("Should be able to diff old to new");
+    let mut hunks = 0;
+    diff.foreach(
+        &mut |_, _| true,
+        None,
+        Some(&mut |_, _| {
+            hunks += 1;
+            true
+        }),
+        None,
+    )
+    .expect("Should be able to go through the hunks of the diff");
+    hunks
+}
+
+/// Given two commits, does a diff and returns every added and removed line as its path, its origin
+/// (`'+'` or `'-'`), and its content. Where in the file the line sits does not matter, so the same
+/// edit made on two branches gives the same set even if the surrounding lines moved.
+pub fn changed_lines(repo: &Repository, old: &Oid, new: &Oid) -> HashSet<(PathBuf, char, Vec<u8>)> {
+    let diff = diff_commits(repo, old, new).expect("Should be able to diff old to new");
+    let mut lines = HashSet::new();
+    diff.foreach(
+        &mut |_, _| true,
+        None,
+        None,
+        Some(&mut |diff_delta, _, diff_line| {
+            let file = match diff_line.origin_value() {
+                DiffLineType::Addition => diff_delta.new_file(),
+                DiffLineType::Deletion => diff_delta.old_file(),
+                _ => return true,
+            };
+            if let Some(path) = file.path() {
+                lines.insert((
+                    path.to_path_buf(),
+                    diff_line.origin(),
+                    diff_line.content().to_vec(),
+                ));
+            }
+            true
+        }),
+    )
+    .expect("diff.foreach went oopsy");
+    lines
+}
+
+/// The patch of `fix` against its first parent, in `git diff` format, limited to the files of
+/// `paths` it changes. Empty if it changes none of them, or if it is a root commit.
+pub fn scoped_patch(
+    repo: &Repository,
+    fix: &Oid,
+    paths: &HashSet<String>,
+) -> Result<Vec<u8>, git2::Error> {
+    let commit = repo.find_commit(*fix)?;
+    if commit.parent_count() == 0 {
+        return Ok(vec![]);
+    }
+    let parent = commit.parent(0)?;
+    let mut scoped: Vec<String> = changed_filenames(repo, &parent.id(), fix)
+        .into_iter()
+        .filter(|path| paths.contains(path))
+        .collect();
+    if scoped.is_empty() {
+        return Ok(vec![]);
+    }
+    scoped.sort();
+
+    let mut diffoptions = DiffOptions::new();
+    diffoptions.disable_pathspec_match(true);
+    for path in &scoped {
+        diffoptions.pathspec(path);
+    }
+    let diff = repo.diff_tree_to_tree(
+        Some(&parent.tree()?),
+        Some(&commit.tree()?),
+        Some(&mut diffoptions),
+    )?;
+    let mut patch = vec![];
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        if let origin @ ('+' | '-' | ' ') = line.origin() {
+            patch.push(origin as u8);
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })?;
+    Ok(patch)
+}
+
 /// Given a path and two oids, looks for blames between the first and the second oid (inclusive).
 fn blame_between<'a>(
     repo: &'a Repository,
@@ -70,6 +376,31 @@ fn blame_between<'a>(
     Ok(blames)
 }
 
+/// The merge commit that last touched the given line (1-based) of a file in HEAD, if it was a
+/// merge. The reverse of finding fixes for a merge: connects a buggy line back to a merge.
+///
+/// Blame only follows first parents, so a line written on a branch is attributed to the merge
+/// that brought the branch in, rather than to the commit on the branch. `None` if the line was
+/// last changed by a regular commit on the first-parent chain, or if the line does not exist.
+pub fn blame_to_merge(
+    repo: &Repository,
+    path: &Path,
+    line: usize,
+) -> Result<Option<Oid>, git2::Error> {
+    let mut opts = BlameOptions::new();
+    opts.first_parent(true);
+    let blame = repo.blame_file(path, Some(&mut opts))?;
+    let Some(hunk) = blame.get_line(line) else {
+        return Ok(None);
+    };
+    let commit = repo.find_commit(hunk.final_commit_id())?;
+    Ok((commit.parent_count() == 2).then(|| commit.id()))
+}
+
+/// Blames of files, keyed by the (oldest, newest) window they were computed for and the path.
+/// None if the blame failed. See `changed_same_line_cached`.
+pub type BlameCache<'repo> = HashMap<(Oid, Oid, PathBuf), Option<Blame<'repo>>>;
+
 /// Attempt like this to have more precision when finding bugfixes for merge commits. Ensure that
 /// the bug fixing commit changes a line that was also changed in O->A, O->B, O->M. To keep things
 /// simple for now, maybe just check with O->M
@@ -81,12 +412,83 @@ pub fn changed_same_line(
     commit_new: &Oid,
     only_extensions: &[&str],
 ) -> bool {
-    let diff =
-        diff_commits(repo, commit_old, commit_new).expect("Should be able to diff old to new");
-    let mut changed_same_line = false;
-    // Don't cache for now, some moving out of closure issues.
-    // path->blame
-    // let mut blames = std::collections::HashMap::new();
+    let mut blames = HashMap::new();
+    let lines = OverlapLines {
+        only_extensions,
+        context_lines: 0,
+    };
+    changed_same_line_cached(
+        repo,
+        blame_oldest,
+        blame_newest,
+        commit_old,
+        commit_new,
+        &lines,
+        &mut blames,
+    )
+}
+
+/// Same as `changed_same_line`, but keeps the blames it computes in the given cache. When checking
+/// several candidate fixes of the same merge, the blame of a file is then only computed once.
+pub fn changed_same_line_cached<'repo>(
+    repo: &'repo Repository,
+    blame_oldest: &Oid,
+    blame_newest: &Oid,
+    commit_old: &Oid,
+    commit_new: &Oid,
+    lines: &OverlapLines,
+    blames: &mut BlameCache<'repo>,
+) -> bool {
+    same_line_overlap(
+        repo,
+        blame_oldest,
+        blame_newest,
+        commit_old,
+        commit_new,
+        lines,
+        blames,
+    )
+    .is_some()
+}
+
+/// Which lines of the diff `same_line_overlap` checks against the blame.
+#[derive(Clone, Copy)]
+pub struct OverlapLines<'e> {
+    /// Only files ending in one of these extensions are checked
+    pub only_extensions: &'e [&'e str],
+    /// Unchanged lines around each change that are checked as well. With 0, only the lines the
+    /// commit changes or removes count, which is the strictest. With more, a commit changing a line
+    /// right next to one changed in the blamed window overlaps too, as in SZZ variants that look
+    /// at the surroundings of a fix. Added lines have no old line to blame, so they can only
+    /// overlap through the context around them.
+    pub context_lines: u32,
+}
+
+/// A line changed by a commit that was also changed in the blamed window, see
+/// `same_line_overlap`.
+pub struct LineOverlap {
+    /// File the line is in, as of the old side of the commit
+    pub path: PathBuf,
+    /// Line number on the old side of the commit
+    pub line: u32,
+    /// Commit that blame attributes the line to
+    pub blamed_commit: Oid,
+}
+
+/// The first line found that `changed_same_line_cached` bases its answer on, or None if there is
+/// no overlap. Tells why a fix was deemed to overlap.
+pub fn same_line_overlap<'repo>(
+    repo: &'repo Repository,
+    blame_oldest: &Oid,
+    blame_newest: &Oid,
+    commit_old: &Oid,
+    commit_new: &Oid,
+    lines: &OverlapLines,
+    blames: &mut BlameCache<'repo>,
+) -> Option<LineOverlap> {
+    let diff = diff_commits_with_context(repo, commit_old, commit_new, lines.context_lines)
+        .expect("Should be able to diff old to new");
+    let mut overlap = None;
     // println!(
     //     "Foreach in O {}, M {}, bugfix {}",
     //     blame_oldest, blame_newest, commit_new
@@ -98,15 +500,15 @@ pub fn changed_same_line(
         Some(&mut |diff_delta, _some_diff_hunk, diff_line| {
             // If we already found an overlap, don't go through all the work. Cannot return false
             // to end the iteration, because that makes the result of the foreach an error.
-            if changed_same_line {
+            if overlap.is_some() {
                 return true;
             }
 
-            // Much like in a git diff, there can be context and other stuff we are not interested
-            // in. Abort this foreach check early enough if that is the case.
+            // Much like in a git diff, there can be other stuff we are not interested in. Abort
+            // this foreach check early enough if that is the case. Context lines are only there
+            // if asked for, and then checked like changed lines.
             match diff_line.origin_value() {
-                DiffLineType::Context
-                | DiffLineType::Binary
+                DiffLineType::Binary
                 | DiffLineType::AddEOFNL
                 | DiffLineType::DeleteEOFNL
                 | DiffLineType::ContextEOFNL => return true,
@@ -117,13 +519,17 @@ pub fn changed_same_line(
 
             if let Some(path) = diff_delta.old_file().path() {
                 if let Some(ext) = path.extension() {
-                    if only_extensions
+                    if lines
+                        .only_extensions
                         .iter()
                         .any(|only| ext.eq_ignore_ascii_case(only))
                     {
-                        if let Ok(path_blames) =
-                            blame_between(repo, blame_oldest, blame_newest, path)
-                        {
+                        let path_blames = blames
+                            .entry((*blame_oldest, *blame_newest, path.to_path_buf()))
+                            .or_insert_with(|| {
+                                blame_between(repo, blame_oldest, blame_newest, path).ok()
+                            });
+                        if let Some(path_blames) = path_blames {
                             if let Some(old_lineno) = diff_line.old_lineno() {
                                 // I assume that if it was changed before, then it will return a hunk,
                                 // otherwise not.
@@ -136,7 +542,11 @@ pub fn changed_same_line(
 
                                     if !is_boundary {
                                         // println!("{:?} {} {}", path, old_lineno, is_boundary);
-                                        changed_same_line = true;
+                                        overlap = Some(LineOverlap {
+                                            path: path.to_path_buf(),
+                                            line: old_lineno,
+                                            blamed_commit: blame_hunk.final_commit_id(),
+                                        });
                                         return true;
                                     }
                                 }
@@ -150,7 +560,7 @@ pub fn changed_same_line(
     )
     .expect("diff.foreach went oopsy");
 
-    changed_same_line
+    overlap
 }
 
 /// Checks whether Δ1 and Δ2 have at least one file they both changed. You may provide a list of
@@ -177,6 +587,41 @@ pub fn changed_same_file(
     !commit1_files.is_disjoint(&commit2_files)
 }
 
+/// Number of commits reachable from `include` but not from `exclude`, like
+/// `git rev-list --count exclude..include`.
+pub fn count_unique(repo: &Repository, include: Oid, exclude: Oid) -> Result<usize, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(include)?;
+    revwalk.hide(exclude)?;
+    let mut count = 0;
+    for oid in revwalk {
+        oid?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Number of merge commits reachable from one of `include` but not from `exclude`, like
+/// `git rev-list --merges --count include... ^exclude`.
+pub fn count_merges(
+    repo: &Repository,
+    include: &[Oid],
+    exclude: Oid,
+) -> Result<usize, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    for oid in include {
+        revwalk.push(*oid)?;
+    }
+    revwalk.hide(exclude)?;
+    let mut count = 0;
+    for oid in revwalk {
+        if repo.find_commit(oid?)?.parent_count() > 1 {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
 /// Since we are not keeping track of the parent relation when getting descendants, we need to
 /// essentially redo that check. Given a commit, take the parents up to n time and see if any
 /// equals the given root. An `n` of 1 here means the direct child.
@@ -214,7 +659,7 @@ pub fn get_descendants(repo: &Repository, ancestor: Oid) -> Result<Vec<Oid>, git
     descendants.push(ancestor);
 
     let mut revwalk = repo.revwalk()?;
-    revwalk.push_head()?;
+    push_head(&mut revwalk, repo)?;
     let mut sorting = git2::Sort::TOPOLOGICAL;
     sorting.insert(git2::Sort::REVERSE);
     revwalk.set_sorting(sorting)?;
@@ -238,6 +683,228 @@ pub fn get_descendants(repo: &Repository, ancestor: Oid) -> Result<Vec<Oid>, git
     Ok(descendants)
 }
 
+/// Like `get_descendants`, but only returns the descendants that are at most `max_gen` generations
+/// away from the ancestor (1 meaning a direct child).
+///
+/// Unlike `get_descendants`, the result is ordered nearest first: by generation, then by commit
+/// time, then by hash. Reverse topological order says nothing about how commits on different
+/// branches relate, so taking the first few of it would be arbitrary.
+///
+/// Git only records the parents of a commit, so the children are collected first, see
+/// `ChildMap::descending_from`. That only reads the commits not reachable from the ancestor, none
+/// of which need sorting. The children are then followed breadth first, stopping at `max_gen`:
+/// commits further away are never looked at.
+pub fn get_descendants_bounded(
+    repo: &Repository,
+    ancestor: Oid,
+    max_gen: u32,
+) -> Result<Vec<Oid>, git2::Error> {
+    get_descendants_bounded_until(repo, ancestor, max_gen, None)
+}
+
+/// Like `get_descendants`, but only returns descendants made at or before `max_time` (epoch
+/// seconds). Newer commits are skipped without being followed, so a commit only made before the
+/// horizon because of clock skew is not found through them either.
+pub fn get_descendants_until(
+    repo: &Repository,
+    ancestor: Oid,
+    max_time: i64,
+) -> Result<Vec<Oid>, git2::Error> {
+    get_descendants_bounded_until(repo, ancestor, u32::MAX, Some(max_time))
+}
+
+/// `get_descendants_bounded` and `get_descendants_until` in one: at most `max_gen` generations
+/// away, and if given, made at or before `max_time`. Ordered like `get_descendants_bounded`.
+pub fn get_descendants_bounded_until(
+    repo: &Repository,
+    ancestor: Oid,
+    max_gen: u32,
+    max_time: Option<i64>,
+) -> Result<Vec<Oid>, git2::Error> {
+    ChildMap::descending_from(repo, ancestor)?
+        .descendants_bounded_until(repo, ancestor, max_gen, max_time)
+}
+
+/// The children of every commit reachable from HEAD. Walking history once to build this makes
+/// looking up the descendants of many commits cheap, where `get_descendants_bounded_until` walks
+/// from HEAD again for each of them.
+pub struct ChildMap {
+    children: HashMap<Oid, Vec<Oid>>,
+}
+
+impl ChildMap {
+    /// Walks all commits reachable from HEAD.
+    pub fn from_head(repo: &Repository) -> Result<Self, git2::Error> {
+        Self::walk(repo, None)
+    }
+
+    /// Like `from_head`, but leaves out the commits reachable from `ancestor`, as none of them
+    /// descends from it. Only good for looking up the descendants of `ancestor`, but for a recent
+    /// one, that is a small part of history.
+    pub fn descending_from(repo: &Repository, ancestor: Oid) -> Result<Self, git2::Error> {
+        Self::walk(repo, Some(ancestor))
+    }
+
+    fn walk(repo: &Repository, hide: Option<Oid>) -> Result<Self, git2::Error> {
+        let mut children: HashMap<Oid, Vec<Oid>> = HashMap::new();
+        let mut revwalk = repo.revwalk()?;
+        push_head(&mut revwalk, repo)?;
+        if let Some(hide) = hide {
+            revwalk.hide(hide)?;
+        }
+        for oid in revwalk {
+            let oid = oid?;
+            for parent in repo.find_commit(oid)?.parent_ids() {
+                children.entry(parent).or_default().push(oid);
+            }
+        }
+        Ok(Self { children })
+    }
+
+    /// The descendants of `ancestor` as returned by `get_descendants_bounded_until`, found by
+    /// following the children breadth first. The first time a commit is reached is thus through
+    /// its closest parent, and the search ends after generation `max_gen`.
+    pub fn descendants_bounded_until(
+        &self,
+        repo: &Repository,
+        ancestor: Oid,
+        max_gen: u32,
+        max_time: Option<i64>,
+    ) -> Result<Vec<Oid>, git2::Error> {
+        self.descendants_counting_visits(repo, ancestor, max_gen, max_time)
+            .map(|(descendants, _)| descendants)
+    }
+
+    /// Like `descendants_bounded_until`, along with how many commits the search looked at.
+    /// Commits beyond `max_gen` are not counted, as they are never reached.
+    pub fn descendants_counting_visits(
+        &self,
+        repo: &Repository,
+        ancestor: Oid,
+        max_gen: u32,
+        max_time: Option<i64>,
+    ) -> Result<(Vec<Oid>, usize), git2::Error> {
+        let mut seen: HashSet<Oid> = HashSet::from([ancestor]);
+        let mut visited = 0;
+        let mut descendants: Vec<(u32, i64, Oid)> = Vec::new();
+        let mut frontier = vec![ancestor];
+        for generation in 1..=max_gen {
+            let mut next = Vec::new();
+            for parent in frontier {
+                for &child in self.children.get(&parent).into_iter().flatten() {
+                    if !seen.insert(child) {
+                        continue;
+                    }
+                    visited += 1;
+                    let time = repo.find_commit(child)?.time().seconds();
+                    if max_time.is_some_and(|max_time| time > max_time) {
+                        continue;
+                    }
+                    descendants.push((generation, time, child));
+                    next.push(child);
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+
+        descendants.sort_unstable();
+        let descendants = descendants.into_iter().map(|(_, _, oid)| oid).collect();
+        Ok((descendants, visited))
+    }
+}
+
+/// The first commit that descends from both `a` and `b`, without going through `exclude`. The dual
+/// of the merge base. Passing the merge commit M of A and B as `exclude` gives the next point
+/// where the two histories were integrated independently of M, e.g., a re-merge. Commits that only
+/// reach A or B through `exclude` (such as M's children) do not count. `None` if there is no such
+/// commit in the history of HEAD.
+///
+/// Same approach as `get_descendants`: walk from HEAD in reverse topological order, so when a
+/// commit is encountered, all its parents have been handled. The first commit found thus has no
+/// other common descendant among its ancestors.
+pub fn nearest_common_descendant(
+    repo: &Repository,
+    a: Oid,
+    b: Oid,
+    exclude: Oid,
+) -> Result<Option<Oid>, git2::Error> {
+    let mut from_a: HashSet<Oid> = HashSet::from([a]);
+    let mut from_b: HashSet<Oid> = HashSet::from([b]);
+
+    let mut revwalk = repo.revwalk()?;
+    push_head(&mut revwalk, repo)?;
+    // Nothing reachable from the merge base can descend from A or B.
+    if let Ok(base) = repo.merge_base(a, b) {
+        if base != a && base != b {
+            revwalk.hide(base)?;
+        }
+    }
+    let mut sorting = git2::Sort::TOPOLOGICAL;
+    sorting.insert(git2::Sort::REVERSE);
+    revwalk.set_sorting(sorting)?;
+
+    for oid in revwalk {
+        let oid = oid?;
+        if oid == exclude {
+            continue;
+        }
+        let commit = repo.find_commit(oid)?;
+        if commit.parent_ids().any(|parent| from_a.contains(&parent)) {
+            from_a.insert(oid);
+        }
+        if commit.parent_ids().any(|parent| from_b.contains(&parent)) {
+            from_b.insert(oid);
+        }
+        if oid != a && oid != b && from_a.contains(&oid) && from_b.contains(&oid) {
+            return Ok(Some(oid));
+        }
+    }
+
+    Ok(None)
+}
+
+/// All commits at most `n` generations back from `start`, `start` itself included.
+fn ancestors_within(repo: &Repository, start: Oid, n: u32) -> Result<HashSet<Oid>, git2::Error> {
+    let mut ancestors = HashSet::from([start]);
+    let mut generation = vec![start];
+    for _ in 0..n {
+        let mut parents = vec![];
+        for oid in generation {
+            for parent in repo.find_commit(oid)?.parent_ids() {
+                if ancestors.insert(parent) {
+                    parents.push(parent);
+                }
+            }
+        }
+        generation = parents;
+    }
+    Ok(ancestors)
+}
+
+/// The oldest (by commit time) commit that is both among the first `n` generations of ancestors
+/// of `a` and of `b`. Ties are broken by Oid so the answer does not depend on traversal order.
+/// `None` if the two do not meet within `n` generations.
+pub fn oldest_common_ancestor_within(
+    repo: &Repository,
+    a: Oid,
+    b: Oid,
+    n: u32,
+) -> Result<Option<Oid>, git2::Error> {
+    let from_a = ancestors_within(repo, a, n)?;
+    let from_b = ancestors_within(repo, b, n)?;
+    let mut oldest: Option<(i64, Oid)> = None;
+    for oid in from_a.intersection(&from_b) {
+        let candidate = (repo.find_commit(*oid)?.time().seconds(), *oid);
+        if oldest.is_none_or(|oldest| candidate < oldest) {
+            oldest = Some(candidate);
+        }
+    }
+    Ok(oldest.map(|(_, oid)| oid))
+}
+
 /// Recursive monstrosity to find all the paths in a commit's tree. Maybe I'm missing something
 /// obvious, but did not see another "easy" way.
 pub fn get_all_paths(
@@ -245,59 +912,152 @@ pub fn get_all_paths(
     current_path: &str,
     repo: &git2::Repository,
 ) -> std::collections::HashSet<String> {
-    let mut result = std::collections::HashSet::new();
+    let mut cache = HashMap::new();
+    get_all_paths_cached(tree, current_path, repo, &mut cache)
+}
+
+/// Same as `get_all_paths`, but remembers the paths found below every (sub)tree by its Oid. When
+/// the same cache is used for several commits, the subtrees they share are only walked once. For
+/// the O, A, B, and M of a merge that is usually the vast majority of the tree.
+pub fn get_all_paths_cached(
+    tree: &git2::Tree,
+    current_path: &str,
+    repo: &git2::Repository,
+    cache: &mut HashMap<Oid, HashSet<String>>,
+) -> HashSet<String> {
+    let relative_paths = relative_tree_paths(tree, repo, cache);
+    if current_path.is_empty() {
+        relative_paths
+    } else {
+        relative_paths
+            .into_iter()
+            .map(|path| format!("{}/{}", current_path, path))
+            .collect()
+    }
+}
+
+/// Paths of all the blobs below the given tree, relative to that tree. Looks in the cache first,
+/// and fills it in otherwise.
+fn relative_tree_paths(
+    tree: &git2::Tree,
+    repo: &git2::Repository,
+    cache: &mut HashMap<Oid, HashSet<String>>,
+) -> HashSet<String> {
+    if let Some(paths) = cache.get(&tree.id()) {
+        return paths.clone();
+    }
+    let mut result = HashSet::new();
     for tree_entry in tree.iter() {
+        let tree_name = tree_entry.name().unwrap();
         match tree_entry.kind() {
             Some(git2::ObjectType::Tree) => {
-                let tree_name = tree_entry.name().unwrap();
-                let new_path = if current_path.is_empty() {
-                    tree_name.to_owned()
-                } else {
-                    format!("{}/{}", current_path, tree_name)
-                };
                 let tree_object = tree_entry.to_object(repo).unwrap();
                 let new_tree = tree_object.as_tree().unwrap();
-                let mut rec_paths = get_all_paths(new_tree, &new_path, repo);
-                // drain instead of iter to move ownership
-                for p in rec_paths.drain() {
-                    result.insert(p);
+                for p in relative_tree_paths(new_tree, repo, cache) {
+                    result.insert(format!("{}/{}", tree_name, p));
                 }
             }
             Some(git2::ObjectType::Blob) => {
-                let tree_name = tree_entry.name().unwrap();
-                let final_path = if current_path.is_empty() {
-                    tree_name.to_owned()
-                } else {
-                    format!("{}/{}", current_path, tree_name)
-                };
-                result.insert(final_path);
+                result.insert(tree_name.to_owned());
             }
             _ => {
                 unreachable!("Should not be able to get here when walking through a commit");
             }
         }
     }
+    cache.insert(tree.id(), result.clone());
     result
 }
 
+/// Line endings to rewrite text files to when writing them out of git.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+impl LineEnding {
+    /// Rewrites every `\r\n` and `\n` in `content` to this line ending. A lone `\r` is kept.
+    pub fn normalize(&self, content: &[u8]) -> Vec<u8> {
+        let mut normalized = Vec::with_capacity(content.len());
+        let mut bytes = content.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            let newline = match byte {
+                b'\n' => true,
+                b'\r' if bytes.peek() == Some(&&b'\n') => {
+                    bytes.next();
+                    true
+                }
+                _ => false,
+            };
+            if !newline {
+                normalized.push(byte);
+            } else if *self == LineEnding::Crlf {
+                normalized.extend_from_slice(b"\r\n");
+            } else {
+                normalized.push(b'\n');
+            }
+        }
+        normalized
+    }
+}
+
+/// How `write_files_from_commit` treats the blobs it writes. The default writes every blob as git
+/// stores it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlobOptions {
+    /// Rewrite the line endings of text files, so the files written no longer match the bytes
+    /// stored in git. Binary files, as git judges them, are always written as they are.
+    pub eol: Option<LineEnding>,
+    /// Skip blobs larger than this many bytes, e.g., generated files or data
+    pub max_bytes: Option<usize>,
+}
+
 /// For a given list of files, locates them in the given commit and writes them into the provided
-/// folder. The files are placed in subfolders mimicking their folders in the commit.
+/// folder. The files are placed in subfolders mimicking their folders in the commit. See
+/// `BlobOptions` for rewriting line endings and leaving out large files.
 pub fn write_files_from_commit_to_disk<P: AsRef<std::path::Path>>(
     folder: P,
     commit: git2::Oid,
     repo: &git2::Repository,
     changed_files: &std::collections::HashSet<String>,
     commit_description: &str,
+    blob_options: BlobOptions,
+) {
+    let mut sink = crate::sink::FolderSink::new(folder);
+    write_files_from_commit(
+        &mut sink,
+        Path::new(""),
+        commit,
+        repo,
+        changed_files,
+        commit_description,
+        blob_options,
+    );
+}
+
+/// Like `write_files_from_commit_to_disk`, but writes to any sink. The files are placed below
+/// `prefix`, in subfolders mimicking their folders in the commit.
+pub fn write_files_from_commit(
+    sink: &mut dyn BlobSink,
+    prefix: &Path,
+    commit: git2::Oid,
+    repo: &git2::Repository,
+    changed_files: &std::collections::HashSet<String>,
+    commit_description: &str,
+    blob_options: BlobOptions,
 ) {
-    let folder = folder.as_ref();
     let commit = repo.find_commit(commit).unwrap();
     let tree = commit.tree().unwrap();
     for file in changed_files {
         let tree_entry = tree.get_path(std::path::Path::new(&file));
         if tree_entry.is_err() {
-            eprintln!(
+            warning!(
                 "File {} not present in {}. Skipping.",
-                &file, commit_description
+                &file,
+                commit_description
             );
             continue;
         }
@@ -305,7 +1065,7 @@ pub fn write_files_from_commit_to_disk<P: AsRef<std::path::Path>>(
         let obj = match tree_entry.to_object(repo) {
             Ok(obj) => obj,
             Err(err) => {
-                eprintln!(
+                recoverable_error!(
                     "ERR: '{}' when looking for file {} in commit {}. File had tree entry id: {}",
                     err,
                     file,
@@ -316,16 +1076,30 @@ pub fn write_files_from_commit_to_disk<P: AsRef<std::path::Path>>(
             }
         };
         let blob = obj.as_blob().unwrap();
-        let fullfilepath = folder.join(file);
-        if let Some(filefolder) = fullfilepath.as_path().parent() {
-            std::fs::create_dir_all(filefolder).unwrap_or_else(|err| {
-                panic!("Failed to create necessary folders to save file from git to disk. File: {:?}, Err: {}",
-                    fullfilepath,
-                    err);
-            });
+        if let Some(max_bytes) = blob_options.max_bytes.filter(|max| blob.size() > *max) {
+            warning!(
+                "File {} in {} has {} bytes, more than {}. Skipping.",
+                &file,
+                commit_description,
+                blob.size(),
+                max_bytes
+            );
+            continue;
         }
-        let mut writer = std::fs::File::create(&fullfilepath)
-            .unwrap_or_else(|_| panic!("Failed to open file for writing {:?}", &fullfilepath));
-        writer.write_all(blob.content()).unwrap();
+        let fullfilepath = prefix.join(file);
+        let executable = tree_entry.filemode() == i32::from(git2::FileMode::BlobExecutable);
+        let content = match blob_options.eol {
+            Some(eol) if !blob.is_binary() => {
+                std::borrow::Cow::Owned(eol.normalize(blob.content()))
+            }
+            _ => std::borrow::Cow::Borrowed(blob.content()),
+        };
+        sink.put(&fullfilepath, &content, executable)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Failed to save file from git. File: {:?}, Err: {}",
+                    fullfilepath, err
+                )
+            });
     }
 }
@@ -0,0 +1,87 @@
+//! Guesses the programming language of a file. Matching on extension alone misses extensionless
+//! scripts and cannot tell a C header from a C++ one, so the content is taken into account too.
+//!
+//! Language names are lowercase, e.g., "java", "rust", "shell".
+
+/// Detects the language of a file given its path and content. Returns None if no idea.
+///
+/// In order: a shebang line decides, then some well known file names, then the extension. Headers
+/// (`.h`) are considered C++ if they contain C++-only constructs, C otherwise.
+pub fn detect(path: &str, content: &[u8]) -> Option<&'static str> {
+    if let Some(language) = from_shebang(content) {
+        return Some(language);
+    }
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    match file_name {
+        "Makefile" | "makefile" | "GNUmakefile" => return Some("make"),
+        "Dockerfile" => return Some("dockerfile"),
+        "CMakeLists.txt" => return Some("cmake"),
+        _ => {}
+    }
+    let extension = file_name.rsplit_once('.')?.1.to_ascii_lowercase();
+    let language = match extension.as_str() {
+        "java" => "java",
+        "rs" => "rust",
+        "py" => "python",
+        "c" => "c",
+        "h" => {
+            if looks_like_cpp(content) {
+                "cpp"
+            } else {
+                "c"
+            }
+        }
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "go" => "go",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "groovy" | "gradle" => "groovy",
+        "rb" => "ruby",
+        "php" => "php",
+        "pl" | "pm" => "perl",
+        "swift" => "swift",
+        "sh" | "bash" | "zsh" => "shell",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Looks at the interpreter in a `#!` line, also when it is called through `env`.
+fn from_shebang(content: &[u8]) -> Option<&'static str> {
+    let first_line = content.split(|&byte| byte == b'\n').next()?;
+    let first_line = std::str::from_utf8(first_line).ok()?.strip_prefix("#!")?;
+    let mut words = first_line.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        // Skip options such as `env -S`
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match interpreter {
+        "sh" | "bash" | "dash" | "zsh" | "ksh" => Some("shell"),
+        "python" => Some("python"),
+        "perl" => Some("perl"),
+        "ruby" => Some("ruby"),
+        "node" => Some("javascript"),
+        "php" => Some("php"),
+        _ => None,
+    }
+}
+
+fn looks_like_cpp(content: &[u8]) -> bool {
+    let content = String::from_utf8_lossy(content);
+    [
+        "class ",
+        "namespace ",
+        "template<",
+        "template <",
+        "std::",
+        "public:",
+        "private:",
+    ]
+    .iter()
+    .any(|marker| content.contains(marker))
+}
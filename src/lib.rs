@@ -6,9 +6,29 @@
 #[macro_use]
 extern crate lazy_static;
 
+/// Like `eprintln!`, but for routine warnings. These are silenced by `verbosity::set_quiet`.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if !$crate::verbosity::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Like `eprintln!`, but for errors the run can skip past. Under `ErrorPolicy::FailFast` (see
+/// `verbosity::set_error_policy`) this panics with the message instead.
+macro_rules! recoverable_error {
+    ($($arg:tt)*) => {
+        match $crate::verbosity::error_policy() {
+            $crate::verbosity::ErrorPolicy::FailFast => panic!($($arg)*),
+            $crate::verbosity::ErrorPolicy::ContinueOnError => eprintln!($($arg)*),
+        }
+    };
+}
+
 pub mod publish;
 
-mod merge;
+pub mod merge;
 
 pub mod debugging;
 
@@ -17,3 +37,15 @@ pub mod find_bug_fix;
 pub mod git_utils;
 
 mod relative_files;
+
+pub mod language;
+
+pub mod selftest;
+
+pub mod verbosity;
+
+pub mod progress;
+
+pub mod sink;
+
+pub mod compare;
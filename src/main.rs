@@ -1,11 +1,14 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::fs::File;
 use std::io::prelude::*;
 
 fn main() {
     match Cli::parse() {
-        Cli::FindMerge(find_merge) => handle_find_merges(find_merge),
+        Cli::FindMerge(find_merge) => handle_find_merges(*find_merge),
         Cli::FindBugFix(find_bug_fix) => handle_find_fix(find_bug_fix),
+        Cli::Analyze(analyze) => handle_analyze(analyze),
+        Cli::CompareDumps(compare_dumps) => handle_compare_dumps(compare_dumps),
+        Cli::Selftest => handle_selftest(),
     };
 }
 
@@ -13,9 +16,20 @@ fn main() {
 #[command(version, author, about)]
 enum Cli {
     /// Look for merges in a Git repository
-    FindMerge(FindMerge),
+    FindMerge(Box<FindMerge>),
     /// Given merges found in a Git repository, locate bug fixing commits to go with them.
     FindBugFix(FindBugFix),
+    /// Find merges and their bug fixes in one go: find-merge followed by find-bug-fix
+    /// --commitlist, without the intermediate file. Writes O,A,B,M,changed_files,timestamp,fix1,
+    /// fix2,fix3 for every merge.
+    Analyze(Analyze),
+    /// Compare two --output-folder dumps, e.g., of runs on different machines. Lists merges and
+    /// files present in only one of them and files whose content differs. Exits with a non-zero
+    /// code if there is any difference.
+    CompareDumps(CompareDumps),
+    /// Build a small repository with a known merge and bug fix in a temporary folder and check
+    /// that both are found. Exits with a non-zero code on any mismatch.
+    Selftest,
 }
 
 #[derive(Parser)]
@@ -26,21 +40,436 @@ struct FindMerge {
     /// produced if this parameter is not present.
     #[arg(long)]
     output_folder: Option<String>,
-    /// Specify a certain number of seconds since the UNIX epoch. Only merge commits made before
-    /// this time will be used.
+    /// Like --output-folder, but writes everything into a tar archive instead of loose files.
+    /// Gzip compressed if the name ends in .gz (e.g., merges.tar.gz).
+    #[arg(long, conflicts_with = "output_folder")]
+    tar: Option<String>,
+    /// Continue an interrupted --output-folder dump. Merge folders marked as complete (by a .done
+    /// file, written once all files of a merge are) are skipped, all other merges are written
+    /// again.
+    #[arg(long, requires = "output_folder")]
+    resume: bool,
+    /// Like --output-file, but writes a typed Parquet file instead of a CSV. Has the columns of
+    /// merge.json, with changed_files as a count. Only available when built with the parquet
+    /// feature.
+    #[cfg(feature = "parquet")]
+    #[arg(long, conflicts_with_all = ["output_folder", "tar"])]
+    parquet: Option<String>,
+    /// Write the CSV of merges to this file instead of STDOUT. Missing parent folders are created.
+    /// Ignored when using --output-folder.
     #[arg(long)]
+    output_file: Option<String>,
+    /// The CSV or output folder of an earlier run. Merges listed there are skipped, so only new
+    /// merges are added: the output folder may already contain merges, and an existing
+    /// --output-file is appended to without repeating the header.
+    #[arg(long)]
+    existing_manifest: Option<String>,
+    /// A CSV of merge bases computed by earlier runs. Cached bases are used instead of computing
+    /// them again, entries for commits that no longer exist are ignored, and the file is
+    /// (re)written with all bases of this run. Created if missing.
+    #[arg(long, value_name = "PATH")]
+    merge_base_cache: Option<String>,
+    /// Only merge commits made before this time will be used. Either a date (2021-06-01), a full
+    /// timestamp (2021-06-01T12:00:00, optionally with an offset such as +02:00), or a number of
+    /// seconds since the UNIX epoch. Times without an offset are taken as UTC.
+    #[arg(long, value_parser = parse_time)]
     before: Option<i64>,
+    /// Only merge commits made after this time will be used. Same format as --before.
+    #[arg(long, value_parser = parse_time)]
+    after: Option<i64>,
+    /// Only find merges of branches that were open for at least this many days, counted from O
+    /// to M, e.g., 30 for long-lived branches. Merges made before their base (clock skew) are
+    /// skipped with a warning.
+    #[arg(long, value_name = "N")]
+    min_branch_age_days: Option<u32>,
+    /// Only find evil merges: merges where M adds lines that are in neither A nor B, i.e., content
+    /// written while resolving the merge rather than taken from a branch. A heuristic, see the
+    /// documentation of ThreeWayMerge::is_evil_merge.
+    #[arg(long)]
+    only_evil_merges: bool,
+    /// Skip merges that change more than this many files (from O to M), e.g., reformatting or
+    /// vendoring that would dominate the dump.
+    #[arg(long, value_name = "N")]
+    max_changed_files: Option<usize>,
+    /// Only find merges that are not reachable from this tag, i.e., that landed after the release
+    /// it marks.
+    #[arg(long)]
+    since_tag: Option<String>,
+    /// Only find merges reachable from this tag instead of from HEAD, i.e., that landed up to the
+    /// release it marks.
+    #[arg(long)]
+    until_tag: Option<String>,
+    /// Also look for merges reachable from any tag, not just from HEAD. Finds merges on old
+    /// release branches that were since deleted.
+    #[arg(long, conflicts_with = "until_tag")]
+    include_tags: bool,
+    /// Also look for merges reachable from any ref: branches, remote branches, tags, and so on.
+    #[arg(long, conflicts_with = "until_tag")]
+    include_all_refs: bool,
+    /// Only find merges in this git rev-spec instead of everything reachable from HEAD, e.g.,
+    /// v1.0..HEAD (reachable from HEAD but not from v1.0) or main...feature (reachable from either
+    /// but not from both).
+    #[arg(
+        long,
+        value_name = "REVSPEC",
+        conflicts_with_all = ["since_tag", "until_tag", "include_tags", "include_all_refs"]
+    )]
+    range: Option<String>,
     /// Avoid merges where O is the same commit as A (or the same commit as B). These are trivial
-    /// merges.
+    /// merges. What happens to them is decided by --trivial-mode.
     #[arg(long)]
     distinct_o: bool,
+    /// What --distinct-o does with trivial merges. drop: leave them out (default). label: keep
+    /// them, but add a trivial column telling which ones they are. merge.json always records this
+    /// as distinct_o.
+    #[arg(long, value_enum, default_value_t = TrivialMode::Drop)]
+    trivial_mode: TrivialMode,
+    /// How --distinct-o compares O with A and B. oid: O has to be another commit (default).
+    /// tree: O has to have other content, so a branch of empty commits, or of changes that were
+    /// reverted again, counts as trivial too.
+    #[arg(long, value_enum, default_value_t = DistinctOMode::Oid)]
+    distinct_o_mode: DistinctOMode,
+    /// Avoid merges where M has the same tree as A (or the same tree as B). In these merges one
+    /// side was taken as is, so there was no real resolution.
+    #[arg(long)]
+    distinct_m: bool,
+    /// Avoid empty merges, where M has the same tree as A (its first parent). Merging B changed
+    /// nothing, e.g., because it was already merged before. A subset of what --distinct-m skips.
+    #[arg(long)]
+    exclude_empty_merges: bool,
+    /// Only find merges where both branches changed at least one file, i.e., neither A nor B has
+    /// the same content as O. Stricter than --distinct-o, which only compares the commits.
+    #[arg(long)]
+    both_branches_changed: bool,
+    /// Only find merges on the first-parent chain of HEAD, i.e., merges into the checked out
+    /// branch itself. Merges into other branches that were later merged in are skipped.
+    #[arg(long)]
+    mainline_only: bool,
+    /// Only find the first merge between any two branches, e.g., skipping the repeated syncs of
+    /// long-lived branches. Branches are named by the summary of M ("Merge branch 'x' into y",
+    /// "Merge pull request #1 from user/x"), so merges with other summaries are all kept, and the
+    /// default branch is only known when named.
+    #[arg(long)]
+    unique_branch_pairs: bool,
     /// Only find merges where A and B have changed the same file at least once.
     #[arg(long)]
     touches_same_file: bool,
+    /// Only find merges that change a file in one of these languages (comma separated, e.g.
+    /// java,rust). The language is detected from the file's content and name rather than just the
+    /// extension, which is slower.
+    #[arg(long, value_delimiter = ',')]
+    language: Vec<String>,
+    /// Skip merges where all changed files match one of these glob patterns, e.g.,
+    /// CHANGELOG.md or '.github/**'. Can be given multiple times.
+    #[arg(long)]
+    exclude_path_glob: Vec<glob::Pattern>,
+    /// Only find merges that change this file (a path relative to the repository root, with
+    /// forward slashes), to follow the merge history of a single file.
+    #[arg(long, value_name = "PATH")]
+    only_file: Option<String>,
+    /// With --only-file, also find merges changing the file under the names it had before it was
+    /// renamed. Diffs every commit once to find the renames, which is slow on large histories.
+    #[arg(long, requires = "only_file")]
+    follow_renames: bool,
+    /// Only find merges where exactly one of the branches changed this file (a path relative to
+    /// the repository root, with forward slashes), i.e., the file was integrated from one side
+    /// without competing changes.
+    #[arg(long, value_name = "PATH")]
+    single_side_file: Option<String>,
+    /// Only find merges whose resolution only changed whitespace: M differs from naively merging A
+    /// and B, but not when ignoring whitespace. Merges A and B for every merge, so this is slow.
+    #[arg(long)]
+    only_whitespace_merges: bool,
+    /// Only find merges where M has a file that did not exist before. What "before" means is set
+    /// with --new-files-relative-to.
+    #[arg(long)]
+    only_new_files: bool,
+    /// Only find merges that change a build or dependency file: pom.xml, Cargo.toml, build.gradle,
+    /// or package.json in any folder, unless --build-file-glob is given.
+    #[arg(long)]
+    only_build_changes: bool,
+    /// Count files matching this glob pattern as build files for --only-build-changes, e.g.,
+    /// '**/requirements.txt'. Replaces the built-in list. Can be given multiple times.
+    #[arg(long, requires = "only_build_changes")]
+    build_file_glob: Vec<glob::Pattern>,
+    /// For --only-new-files. o: the file is not in O, so it was added on a branch or in the merge.
+    /// parents: the file is in neither A nor B, so it was only added while resolving the merge.
+    #[arg(long, value_enum, default_value_t = NewFilesBase::O)]
+    new_files_relative_to: NewFilesBase,
     /// Copy all files present in either O, A, B, or M of the three way merge, not just those
     /// present in each and changed
     #[arg(long)]
     all_files: bool,
+    /// When copying files, leave a file out of the o, a, and b folders if it is identical to the
+    /// version in m. Saves space, but the four folders no longer contain the same files.
+    #[arg(long)]
+    only_differing: bool,
+    /// When dumping merges where O is the same commit as A (or B), make the o folder a symbolic
+    /// link to the a (or b) folder instead of writing the same files twice. Where links cannot be
+    /// made, o is left out and merge.json notes which folder holds its files as o_same_as.
+    #[arg(long)]
+    dedup_identical_parts: bool,
+    /// When dumping merges, also write the files that conflict when merging A and B into a
+    /// conflicts folder: path.base (O), path.ours (A), path.theirs (B), and path.resolved (M).
+    /// Only files that actually conflict are written.
+    #[arg(long)]
+    conflicts: bool,
+    /// Separator for the changed files listed in merge.json when dumping merges. posix keeps
+    /// git's forward slashes, native uses the separator of the platform the tool runs on.
+    #[arg(long, value_enum, default_value_t = PathStyle::Posix)]
+    path_style: PathStyle,
+    /// Rewrite the line endings of dumped text files to lf or crlf. Binary files are left as they
+    /// are. The dumped files then no longer match the bytes stored in git.
+    #[arg(long, value_enum)]
+    normalize_eol: Option<LineEnding>,
+    /// Do not dump files larger than this many bytes, e.g., minified or generated files. Every
+    /// skipped file is reported.
+    #[arg(long, value_name = "N")]
+    max_file_bytes: Option<usize>,
+    /// Leave files over --max-file-bytes in any of O, A, B, or M out of the dump altogether, so
+    /// the changed files in merge.json match the folders. Has no effect on the folders with
+    /// --all-files.
+    #[arg(long, requires = "max_file_bytes")]
+    exclude_large_files: bool,
+    /// Swap A and B where needed so A is always the side with the smaller change (lines added plus
+    /// removed since O). Whether a merge was swapped is recorded in an a_b_swapped column, or in
+    /// merge.json when using --output-folder.
+    #[arg(long)]
+    order_by_change_size: bool,
+    /// Order in which commits are walked, starting from HEAD. This decides which merges appear
+    /// first in the output. topo: children before parents (default). time: newest committer time
+    /// first, useful when you want the most recent merges at the top. reverse-topo: parents before
+    /// children, so the oldest merges come first.
+    #[arg(long, value_enum, default_value_t = Sort::Topo)]
+    sort: Sort,
+    /// Sort the merges by time, newest first, and skip this many of them. Together with --limit,
+    /// gives a page of the merges, e.g., --offset 100 --limit 50. All merges are kept in memory
+    /// for sorting. Ignored when using --output-folder.
+    #[arg(long, value_name = "N")]
+    offset: Option<usize>,
+    /// Sort the merges by time, newest first, and only output this many of them, after --offset.
+    /// Ignored when using --output-folder.
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+    /// Add a churn column: the number of lines in which M differs from naively merging A and B
+    /// (keeping both sides of a conflict). A measure of manual merge effort. Ignored when using
+    /// --output-folder.
+    #[arg(long)]
+    with_churn: bool,
+    /// Add took_ours, took_theirs, took_both, and custom_resolution columns: of the files that
+    /// conflict when merging A and B again, how many M resolved by taking A's version, B's
+    /// version, both sides of every conflict, or otherwise. Ignored when using --output-folder.
+    #[arg(long)]
+    with_resolutions: bool,
+    /// Add a signed column: whether the merge commit carries a signature. The signature itself is
+    /// not verified. Ignored when using --output-folder.
+    #[arg(long)]
+    with_signed: bool,
+    /// Add a parents column: the number of parents of the merge commit. Ignored when using
+    /// --output-folder.
+    #[arg(long)]
+    with_parent_count: bool,
+    /// Add a relative_days column: the number of whole days between the first commit of the
+    /// repository and the merge. Ignored when using --output-folder.
+    #[arg(long)]
+    relative_time: bool,
+    /// Add a both_branch_files column: the number of files changed in both O→A and O→B, i.e.,
+    /// files that could conflict. Diffs O→A and O→B for every merge, so this is slower. Ignored
+    /// when using --output-folder.
+    #[arg(long)]
+    with_both_branch_count: bool,
+    /// Add lag_from_o and lag_from_tip columns: the seconds from O to M, and from the later of A
+    /// and B to M. Negative when clocks were off. Ignored when using --output-folder.
+    #[arg(long)]
+    with_lag: bool,
+    /// Add a_unique and b_unique columns: the number of commits only on A (reachable from A but
+    /// not from B) and only on B. Ignored when using --output-folder.
+    #[arg(long)]
+    with_unique_counts: bool,
+    /// Add an author_count column: the number of distinct authors (by email) of the commits on
+    /// either branch since O, plus the author of the merge itself. Ignored when using
+    /// --output-folder.
+    #[arg(long)]
+    with_author_count: bool,
+    /// Add a_ins, a_del, b_ins, and b_del columns: the lines added and removed from O to A and
+    /// from O to B. Shows how much each branch diverged. Ignored when using --output-folder.
+    #[arg(long)]
+    with_side_stats: bool,
+    /// Add a hunks column: the number of diff hunks from O to M. Tells how scattered the change
+    /// is, and is cheaper than counting lines. Ignored when using --output-folder.
+    #[arg(long)]
+    with_hunks: bool,
+    /// Add a merge_driver column: whether a changed file has a merge attribute other than the
+    /// default (e.g., merge=binary or a custom driver), so the resolution may not be a plain text
+    /// merge. Attributes are read from the checked out .gitattributes. Ignored when using
+    /// --output-folder.
+    #[arg(long)]
+    with_merge_driver: bool,
+    /// Add a similarity column: the share of changed lines O→A and O→B have in common, from 0 (no
+    /// shared edits) to 1 (identical edits). High values hint at the same work done on both
+    /// branches. Ignored when using --output-folder.
+    #[arg(long)]
+    with_similarity: bool,
+    /// Add o_short, a_short, b_short, and m_short columns: abbreviated hashes that are still
+    /// unique in the repository, for eyeballing the CSV. Ignored when using --output-folder.
+    #[arg(long)]
+    with_short_sha: bool,
+    /// Add a test_ratio column: the share of changed files that are tests, i.e., whose path
+    /// contains test or spec, or matches a --test-path-glob. Ignored when using --output-folder.
+    #[arg(long)]
+    with_test_ratio: bool,
+    /// Count files matching this glob pattern as tests for --with-test-ratio, e.g., '**/it/**'.
+    /// Can be given multiple times.
+    #[arg(long, requires = "with_test_ratio")]
+    test_path_glob: Vec<glob::Pattern>,
+    /// Add a pr column: the number of the pull or merge request the merge came from, going by
+    /// the message of M (GitHub, GitLab, and Bitbucket styles). Empty for other merges. Ignored
+    /// when using --output-folder.
+    #[arg(long)]
+    with_pr: bool,
+    /// Add a nested_merges column: the number of merge commits on the merged branches, between O
+    /// and A or B. Tells how tangled the branches were. Ignored when using --output-folder.
+    #[arg(long)]
+    with_nested_merges: bool,
+    /// Which time of M goes into the timestamp and relative_days columns. committer: when the merge
+    /// was committed (default). author: when it was authored, which differs if M was amended or
+    /// rebased later. Ignored when using --output-folder.
+    #[arg(long, value_enum, default_value_t = TimeSource::Committer)]
+    time_source: TimeSource,
+    /// Add a tz_offset column: the offset in minutes from UTC of the timezone the timestamp was
+    /// recorded in, e.g., 120 for +0200. The timestamp itself stays in UTC. Ignored when using
+    /// --output-folder.
+    #[arg(long)]
+    with_timezone: bool,
+    /// Start the CSV with a `# schema=N` line, N being the version of the column layout. The
+    /// version is bumped whenever columns change, so parsers can detect an unexpected format.
+    /// Ignored when using --output-folder.
+    #[arg(long)]
+    with_schema_header: bool,
+    /// Add a `# run_meta={...}` line before the column header: the tool version, the command
+    /// line, the HEAD commit of the repository, and the time of the run, as JSON. Dumps made with
+    /// --output-folder or --tar always get these in a run_meta.json. Ignored when using
+    /// --output-folder.
+    #[arg(long)]
+    with_run_meta: bool,
+    /// Do not print routine warnings (e.g., skipped files, merges without a common base). Errors
+    /// are still reported.
+    #[arg(long)]
+    quiet: bool,
+    /// Abort on the first merge, fix or file that cannot be processed, instead of reporting it
+    /// and going on with the next one
+    #[arg(long, conflicts_with = "continue_on_error")]
+    fail_fast: bool,
+    /// Report merges, fixes or files that cannot be processed and go on with the next one. This
+    /// is the default.
+    #[arg(long)]
+    continue_on_error: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Sort {
+    Topo,
+    Time,
+    ReverseTopo,
+}
+
+impl From<Sort> for git2::Sort {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Topo => git2::Sort::TOPOLOGICAL,
+            Sort::Time => git2::Sort::TIME,
+            Sort::ReverseTopo => git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum TrivialMode {
+    Drop,
+    Label,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TimeSource {
+    Committer,
+    Author,
+}
+
+impl From<TimeSource> for three_way_merge_finder::merge::TimeSource {
+    fn from(source: TimeSource) -> Self {
+        match source {
+            TimeSource::Committer => Self::Committer,
+            TimeSource::Author => Self::Author,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DistinctOMode {
+    Oid,
+    Tree,
+}
+
+impl From<DistinctOMode> for three_way_merge_finder::merge::DistinctOMode {
+    fn from(mode: DistinctOMode) -> Self {
+        match mode {
+            DistinctOMode::Oid => Self::Oid,
+            DistinctOMode::Tree => Self::Tree,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NewFilesBase {
+    O,
+    Parents,
+}
+
+impl From<NewFilesBase> for three_way_merge_finder::merge::NewFilesBase {
+    fn from(base: NewFilesBase) -> Self {
+        match base {
+            NewFilesBase::O => Self::O,
+            NewFilesBase::Parents => Self::Parents,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PathStyle {
+    Posix,
+    Native,
+}
+
+impl From<PathStyle> for three_way_merge_finder::merge::PathStyle {
+    fn from(style: PathStyle) -> Self {
+        match style {
+            PathStyle::Posix => Self::Posix,
+            PathStyle::Native => Self::Native,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl From<LineEnding> for three_way_merge_finder::git_utils::LineEnding {
+    fn from(eol: LineEnding) -> Self {
+        match eol {
+            LineEnding::Lf => Self::Lf,
+            LineEnding::Crlf => Self::Crlf,
+        }
+    }
+}
+
+#[derive(Parser)]
+struct CompareDumps {
+    /// The first dump folder
+    left: String,
+    /// The second dump folder
+    right: String,
 }
 
 #[derive(Parser)]
@@ -58,23 +487,317 @@ struct FindBugFix {
     /// alongside the existing o, a, b, m folders.
     #[arg(long)]
     commitfolder: Option<String>,
+    /// Next to each bug fix folder, also write a bfN.diff with the changes of that fix, limited to
+    /// the files in the m folder. Shows whether a fix really touches what the merge changed. Only
+    /// applies to --commitfolder.
+    #[arg(long)]
+    write_fix_diffs: bool,
+    /// Print which fixes were found for each merge folder, and which would become bf1, bf2, and
+    /// bf3, without writing anything. Handy for tuning the other options first. Only applies to
+    /// --commitfolder.
+    #[arg(long)]
+    dry_run: bool,
+    /// Rewrite the line endings of the bug fixes' text files to lf or crlf, as when dumping the
+    /// merges with find-merge --normalize-eol. Only applies to --commitfolder.
+    #[arg(long, value_enum)]
+    normalize_eol: Option<LineEnding>,
     /// Specify how 'far' away the fix can be from the merge. This is done in terms of the number
     /// of children. Currently only applies to --commitlist.
     #[arg(long, default_value_t = 10)]
     fix_distance: u32,
+    /// Only consider fixes made at most this many days after the merge. Also saves walking recent
+    /// history for old merges. Only applies to --commitlist.
+    #[arg(long)]
+    fix_window_days: Option<u32>,
     /// Only considers bug fixing commits that also change a line that was changed between O and M.
     /// Should be terrible for recall, but hopefully ups the precision significantly.
     #[arg(long)]
     touches_same_line: bool,
+    /// With --touches-same-line, print to STDERR for every fix which file and line made it overlap
+    /// with the merge, and which commit blame attributed that line to.
+    #[arg(long)]
+    explain_overlap: bool,
+    /// With --touches-same-line, only lines in files with these extensions count for the overlap
+    /// (comma separated, without the dot, e.g. java,kt). By default the extensions of the files
+    /// each merge changed are used.
+    #[arg(long, value_delimiter = ',')]
+    extensions: Option<Vec<String>>,
+    /// With --touches-same-line, also count unchanged lines up to this far from a change of the
+    /// fix. 0 only counts the lines the fix changed; higher values also match fixes right next to
+    /// a line changed by the merge.
+    #[arg(long, default_value_t = 0)]
+    context_lines: u32,
+    /// Add three columns telling for each bug fix whether its author (by email) is the committer
+    /// of the merge. Empty when unknown. Only applies to --commitlist.
+    #[arg(long)]
+    with_same_author: bool,
+    /// Which files of the merge a bug fix has to change. both: changed in O→A and in O→B. om: as
+    /// both, but the change must also be present in O→M. both-ignoring-merges: as both, but
+    /// only counting non-merge commits on each branch, which diffs every branch commit and is
+    /// slower. all: any file. Does not apply to --touches-same-line.
+    #[arg(long, value_enum, default_value_t = FixFileScope::Both)]
+    fix_file_scope: FixFileScope,
+    /// A regex the full commit message of a fix has to match, e.g., "fix|bug" or "#\d+". Can be
+    /// given multiple times, in which case all of them have to match. Replaces the built-in list
+    /// of bug fix keywords. Only applies to --commitlist.
+    #[arg(long, value_parser = regex::Regex::new)]
+    require: Vec<regex::Regex>,
+    /// Pick fixes by a score from 0 to 1 instead of by keywords: keywords in the summary, an issue
+    /// reference, a small change, and touching files changed in the merge all add to it. Fixes
+    /// scoring below this threshold are dropped. Only applies to --commitlist.
+    #[arg(long)]
+    fix_threshold: Option<f64>,
+    /// Add three columns with the score (see --fix-threshold) of each bug fix. Only applies to
+    /// --commitlist.
+    #[arg(long)]
+    with_score: bool,
+    /// Never take a merge commit as a bug fix, nor a commit whose summary mentions a merge. Such
+    /// commits often match keywords like "conflict". Only applies to --commitlist.
+    #[arg(long)]
+    exclude_merge_fixes: bool,
+    /// Only take fixes referencing (e.g., as #123 or PROJ-123) one of the issues listed in this
+    /// file, such as those the issue tracker labels as bugs. One issue number per line, or a CSV
+    /// with the number in the first column. Lines without a number are ignored. Only applies to
+    /// --commitlist.
+    #[arg(long, value_name = "FILE")]
+    bug_issues: Option<String>,
+    /// Number of merges to look for bug fixes for in parallel. The output keeps the order of the
+    /// commitlist. Only applies to --commitlist.
+    #[arg(long, default_value_t = 1)]
+    jobs: usize,
+    /// Also attach the result for each merge as a JSON note (O, A, B, M, and the fixes) on the
+    /// merge commit under this notes ref, e.g., refs/notes/merges. Re-running replaces the notes.
+    /// Only applies to --commitlist.
+    #[arg(long, value_name = "REF")]
+    write_notes: Option<String>,
+    /// Stop looking for fixes of a merge once this many are found, at most 3 (one per column).
+    /// Lower values leave the later columns empty, but save checking more candidates. Only
+    /// applies to --commitlist.
+    #[arg(long, default_value_t = 3)]
+    max_fixes: usize,
+    /// Report on STDERR how many of the merges have been processed. Only applies to --commitlist.
+    #[arg(long)]
+    progress: bool,
+    /// Do not print routine warnings (e.g., skipped files, merges without a common base). Errors
+    /// are still reported.
+    #[arg(long)]
+    quiet: bool,
+    /// Abort on the first merge, fix or file that cannot be processed, instead of reporting it
+    /// and going on with the next one
+    #[arg(long, conflicts_with = "continue_on_error")]
+    fail_fast: bool,
+    /// Report merges, fixes or files that cannot be processed and go on with the next one. This
+    /// is the default.
+    #[arg(long)]
+    continue_on_error: bool,
+}
+
+#[derive(Parser)]
+struct Analyze {
+    /// Give the path of an existing local Git repository.
+    gitrepo: String,
+    /// Write the CSV to this file instead of STDOUT. Missing parent folders are created.
+    #[arg(long)]
+    output_file: Option<String>,
+    /// Only merge commits made before this time will be used. Same format as for find-merge.
+    #[arg(long, value_parser = parse_time)]
+    before: Option<i64>,
+    /// Only merge commits made after this time will be used. Same format as --before.
+    #[arg(long, value_parser = parse_time)]
+    after: Option<i64>,
+    /// Order in which commits are walked, starting from HEAD. See find-merge --sort.
+    #[arg(long, value_enum, default_value_t = Sort::Topo)]
+    sort: Sort,
+    /// Skip trivial merges, where O is the same commit as A (or the same commit as B).
+    #[arg(long)]
+    distinct_o: bool,
+    /// How --distinct-o compares O with A and B. See find-merge --distinct-o-mode.
+    #[arg(long, value_enum, default_value_t = DistinctOMode::Oid)]
+    distinct_o_mode: DistinctOMode,
+    /// Skip empty merges, where M has the same tree as A (its first parent).
+    #[arg(long)]
+    exclude_empty_merges: bool,
+    /// Only use merges where both branches changed at least one file.
+    #[arg(long)]
+    both_branches_changed: bool,
+    /// Only use merges where A and B have changed the same file at least once.
+    #[arg(long)]
+    touches_same_file: bool,
+    /// Specify how 'far' away the fix can be from the merge, in generations.
+    #[arg(long, default_value_t = 10)]
+    fix_distance: u32,
+    /// Only consider fixes made at most this many days after the merge.
+    #[arg(long)]
+    fix_window_days: Option<u32>,
+    /// Which files of the merge a bug fix has to change. See find-bug-fix --fix-file-scope.
+    #[arg(long, value_enum, default_value_t = FixFileScope::Both)]
+    fix_file_scope: FixFileScope,
+    /// A regex the full commit message of a fix has to match. Can be given multiple times, in
+    /// which case all of them have to match. Replaces the built-in list of bug fix keywords.
+    #[arg(long, value_parser = regex::Regex::new)]
+    require: Vec<regex::Regex>,
+    /// Never take a merge commit as a bug fix, nor a commit whose summary mentions a merge.
+    #[arg(long)]
+    exclude_merge_fixes: bool,
+    /// Only take fixes referencing one of the issues listed in this file. See find-bug-fix
+    /// --bug-issues.
+    #[arg(long, value_name = "FILE")]
+    bug_issues: Option<String>,
+    /// Do not print routine warnings (e.g., skipped files, merges without a common base). Errors
+    /// are still reported.
+    #[arg(long)]
+    quiet: bool,
+    /// Abort on the first merge or fix that cannot be processed, instead of reporting it and going
+    /// on with the next one
+    #[arg(long, conflicts_with = "continue_on_error")]
+    fail_fast: bool,
+    /// Report merges or fixes that cannot be processed and go on with the next one. This is the
+    /// default.
+    #[arg(long)]
+    continue_on_error: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FixFileScope {
+    Both,
+    Om,
+    BothIgnoringMerges,
+    All,
+}
+
+impl From<FixFileScope> for three_way_merge_finder::find_bug_fix::FixFileScope {
+    fn from(scope: FixFileScope) -> Self {
+        match scope {
+            FixFileScope::Both => Self::Both,
+            FixFileScope::Om => Self::Om,
+            FixFileScope::BothIgnoringMerges => Self::BothIgnoringMerges,
+            FixFileScope::All => Self::All,
+        }
+    }
+}
+
+fn set_error_policy(fail_fast: bool) {
+    use three_way_merge_finder::verbosity::ErrorPolicy;
+    three_way_merge_finder::verbosity::set_error_policy(if fail_fast {
+        ErrorPolicy::FailFast
+    } else {
+        ErrorPolicy::ContinueOnError
+    });
 }
 
 fn handle_find_merges(cli: FindMerge) {
-    let repo = match git2::Repository::open(cli.gitrepo) {
-        Ok(repo) => repo,
-        Err(e) => panic!("Failed to open: {}", e),
+    three_way_merge_finder::verbosity::set_quiet(cli.quiet);
+    set_error_policy(cli.fail_fast);
+    let repo = open_repo(&cli.gitrepo);
+    let resolve_tag = |tag: &String| {
+        three_way_merge_finder::git_utils::resolve_tag(&repo, tag)
+            .unwrap_or_else(|e| panic!("Could not resolve tag {}: {}", tag, e))
+    };
+    let mut revwalk = match &cli.range {
+        Some(range) => {
+            three_way_merge_finder::git_utils::create_revwalk_spec(&repo, cli.sort.into(), range)
+                .unwrap_or_else(|e| panic!("Could not walk the range {}: {}", range, e))
+        }
+        None => three_way_merge_finder::git_utils::create_revwalk_range(
+            &repo,
+            cli.sort.into(),
+            cli.since_tag.as_ref().map(resolve_tag),
+            cli.until_tag.as_ref().map(resolve_tag),
+        )
+        .expect("Could not create revwalk"),
+    };
+    // Globs are relative to refs/, see git_revwalk_push_glob. Commits reachable from several
+    // roots are still only walked once.
+    if cli.include_all_refs {
+        revwalk.push_glob("*").expect("Could not add all refs");
+    } else if cli.include_tags {
+        revwalk.push_glob("tags").expect("Could not add the tags");
+    }
+
+    let filter = three_way_merge_finder::merge::MergeFilter {
+        skip: cli
+            .existing_manifest
+            .as_deref()
+            .map(read_manifest)
+            .unwrap_or_default(),
+        after: cli.after,
+        min_branch_age_days: cli.min_branch_age_days,
+        max_changed_files: cli.max_changed_files,
+        only_evil: cli.only_evil_merges,
+        mainline: cli.mainline_only.then(|| {
+            three_way_merge_finder::git_utils::first_parent_chain(&repo)
+                .expect("Could not follow the first parents of HEAD")
+        }),
+        unique_branch_pairs: cli.unique_branch_pairs.then(|| {
+            three_way_merge_finder::merge::first_merges_per_branch_pair(&repo)
+                .expect("Could not walk the merges of HEAD")
+        }),
+        distinct_o: cli.distinct_o && cli.trivial_mode == TrivialMode::Drop,
+        distinct_o_mode: cli.distinct_o_mode.into(),
+        distinct_m: cli.distinct_m,
+        exclude_empty: cli.exclude_empty_merges,
+        both_branches_changed: cli.both_branches_changed,
+        touches_same_file: cli.touches_same_file,
+        languages: cli.language,
+        exclude_paths: cli.exclude_path_glob,
+        only_paths: match &cli.only_file {
+            Some(path) if cli.follow_renames => {
+                three_way_merge_finder::git_utils::former_paths(&repo, path)
+                    .unwrap_or_else(|err| panic!("Could not follow renames of {}: {}", path, err))
+            }
+            Some(path) => std::collections::HashSet::from([path.clone()]),
+            None => std::collections::HashSet::new(),
+        },
+        single_side_file: cli.single_side_file,
+        build_files: cli.only_build_changes.then(|| {
+            if cli.build_file_glob.is_empty() {
+                three_way_merge_finder::merge::build_file_patterns()
+            } else {
+                cli.build_file_glob
+            }
+        }),
+        only_whitespace: cli.only_whitespace_merges,
+        new_files: cli
+            .only_new_files
+            .then_some(cli.new_files_relative_to.into()),
+    };
+
+    let mut base_cache = cli.merge_base_cache.as_ref().map(|path| {
+        three_way_merge_finder::merge::MergeBaseCache::load(&repo, path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err))
+    });
+
+    let run_meta = three_way_merge_finder::publish::RunMeta::new(&repo, std::env::args().collect());
+    let dump_options = three_way_merge_finder::publish::DumpOptions {
+        all_files: cli.all_files,
+        only_differing: cli.only_differing,
+        dedup_identical_parts: cli.dedup_identical_parts,
+        order_by_change_size: cli.order_by_change_size,
+        incremental: cli.existing_manifest.is_some(),
+        conflicts: cli.conflicts,
+        path_style: cli.path_style.into(),
+        resume: cli.resume,
+        normalize_eol: cli.normalize_eol.map(Into::into),
+        max_file_bytes: cli.max_file_bytes,
+        exclude_large_files: cli.exclude_large_files,
+        run_meta: Some(run_meta.clone()),
     };
-    let revwalk =
-        three_way_merge_finder::git_utils::create_revwalk(&repo).expect("Could not create revwalk");
+
+    #[cfg(feature = "parquet")]
+    if let Some(parquet) = cli.parquet {
+        three_way_merge_finder::publish::parquet_dump(
+            &parquet,
+            &repo,
+            revwalk,
+            cli.before,
+            base_cache.as_mut(),
+            &filter,
+            cli.order_by_change_size,
+        )
+        .unwrap_or_else(|err| panic!("Failed to write {}: {}", parquet, err));
+        save_merge_base_cache(cli.merge_base_cache.as_deref(), base_cache.as_ref());
+        return;
+    }
 
     if let Some(output_folder) = cli.output_folder {
         three_way_merge_finder::publish::folder_dump(
@@ -82,67 +805,331 @@ fn handle_find_merges(cli: FindMerge) {
             &repo,
             revwalk,
             cli.before,
-            cli.all_files,
-            cli.distinct_o,
+            base_cache.as_mut(),
+            &filter,
+            &dump_options,
         );
-    } else {
-        three_way_merge_finder::publish::print_csv_of_merges(
+    } else if let Some(archive) = cli.tar {
+        three_way_merge_finder::publish::tar_dump(
+            &archive,
             &repo,
             revwalk,
             cli.before,
-            cli.distinct_o,
-            cli.touches_same_file,
-        );
+            base_cache.as_mut(),
+            &filter,
+            &dump_options,
+        )
+        .unwrap_or_else(|err| panic!("Failed to write {}: {}", archive, err));
+    } else {
+        let options = three_way_merge_finder::publish::CsvOptions {
+            order_by_change_size: cli.order_by_change_size,
+            churn: cli.with_churn,
+            resolutions: cli.with_resolutions,
+            signed: cli.with_signed,
+            parent_count: cli.with_parent_count,
+            relative_time: cli.relative_time,
+            both_branch_count: cli.with_both_branch_count,
+            lag: cli.with_lag,
+            unique_counts: cli.with_unique_counts,
+            author_count: cli.with_author_count,
+            side_stats: cli.with_side_stats,
+            hunks: cli.with_hunks,
+            merge_driver: cli.with_merge_driver,
+            similarity: cli.with_similarity,
+            short_sha: cli.with_short_sha,
+            test_ratio: cli.with_test_ratio.then_some(cli.test_path_glob),
+            pull_request: cli.with_pr,
+            nested_merges: cli.with_nested_merges,
+            time_source: cli.time_source.into(),
+            timezone: cli.with_timezone,
+            page: (cli.offset.is_some() || cli.limit.is_some()).then(|| {
+                three_way_merge_finder::merge::Page {
+                    offset: cli.offset.unwrap_or(0),
+                    limit: cli.limit,
+                }
+            }),
+            trivial: cli.distinct_o && cli.trivial_mode == TrivialMode::Label,
+            distinct_o_mode: cli.distinct_o_mode.into(),
+            skip_header: false,
+            schema_header: cli.with_schema_header,
+            run_meta: cli.with_run_meta.then(|| run_meta.clone()),
+        };
+        if let Some(output_file) = cli.output_file {
+            let append = cli.existing_manifest.is_some()
+                && std::fs::metadata(&output_file).is_ok_and(|metadata| metadata.len() > 0);
+            let file = if append {
+                File::options()
+                    .append(true)
+                    .open(&output_file)
+                    .unwrap_or_else(|err| panic!("Failed to open {}: {}", output_file, err))
+            } else {
+                create_output_file(&output_file)
+            };
+            let options = three_way_merge_finder::publish::CsvOptions {
+                skip_header: append,
+                ..options
+            };
+            let mut writer = std::io::BufWriter::new(file);
+            three_way_merge_finder::publish::write_csv_of_merges(
+                &mut writer,
+                &repo,
+                revwalk,
+                cli.before,
+                base_cache.as_mut(),
+                &filter,
+                &options,
+            )
+            .and_then(|_| writer.flush())
+            .unwrap_or_else(|err| panic!("Failed to write to {}: {}", output_file, err));
+        } else {
+            three_way_merge_finder::publish::write_csv_of_merges(
+                &mut std::io::stdout().lock(),
+                &repo,
+                revwalk,
+                cli.before,
+                base_cache.as_mut(),
+                &filter,
+                &options,
+            )
+            .expect("Failed to write to STDOUT");
+        }
     }
+    save_merge_base_cache(cli.merge_base_cache.as_deref(), base_cache.as_ref());
 }
 
-fn handle_find_fix(cli: FindBugFix) {
-    let repo = match git2::Repository::open(cli.gitrepo) {
-        Ok(repo) => repo,
-        Err(e) => panic!("Failed to open: {}", e),
+fn save_merge_base_cache(
+    path: Option<&str>,
+    cache: Option<&three_way_merge_finder::merge::MergeBaseCache>,
+) {
+    if let (Some(path), Some(cache)) = (path, cache) {
+        cache
+            .save(path)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", path, err));
+    }
+}
+
+fn handle_analyze(cli: Analyze) {
+    three_way_merge_finder::verbosity::set_quiet(cli.quiet);
+    set_error_policy(cli.fail_fast);
+    let repo = open_repo(&cli.gitrepo);
+    let revwalk = three_way_merge_finder::git_utils::create_revwalk(&repo, cli.sort.into())
+        .expect("Could not create revwalk");
+    let filter = three_way_merge_finder::merge::MergeFilter {
+        after: cli.after,
+        distinct_o: cli.distinct_o,
+        distinct_o_mode: cli.distinct_o_mode.into(),
+        exclude_empty: cli.exclude_empty_merges,
+        both_branches_changed: cli.both_branches_changed,
+        touches_same_file: cli.touches_same_file,
+        ..Default::default()
     };
+    let options = three_way_merge_finder::publish::BugFixOptions {
+        fix_distance: cli.fix_distance,
+        fix_window_days: cli.fix_window_days,
+        exclude_merge_fixes: cli.exclude_merge_fixes,
+        required: cli.require,
+        file_scope: cli.fix_file_scope.into(),
+        max_fixes: 3,
+        bug_issues: cli.bug_issues.as_deref().map(read_issue_list),
+        ..Default::default()
+    };
+    let mut out: Box<dyn Write> = match &cli.output_file {
+        Some(output_file) => Box::new(std::io::BufWriter::new(create_output_file(output_file))),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    three_way_merge_finder::publish::write_analysis_csv(
+        &mut out, &repo, revwalk, cli.before, &filter, &options,
+    )
+    .and_then(|_| out.flush())
+    .expect("Failed to write the analysis");
+}
+
+fn handle_find_fix(cli: FindBugFix) {
+    three_way_merge_finder::verbosity::set_quiet(cli.quiet);
+    set_error_policy(cli.fail_fast);
+    let repo = open_repo(&cli.gitrepo);
 
     if let Some(commitfolder) = cli.commitfolder {
-        three_way_merge_finder::publish::write_bug_fix_files(commitfolder, &repo);
+        let options = three_way_merge_finder::publish::FixFolderOptions {
+            write_fix_diffs: cli.write_fix_diffs,
+            dry_run: cli.dry_run,
+            normalize_eol: cli.normalize_eol.map(Into::into),
+        };
+        let mut stdout = std::io::stdout().lock();
+        three_way_merge_finder::publish::write_bug_fix_files(
+            &mut stdout,
+            commitfolder,
+            &repo,
+            &options,
+        )
+        .expect("Failed to write to STDOUT");
     } else if let Some(commitfile) = cli.commitlist {
-        let commitlist = read_commitlist_file(&commitfile);
-
+        // The progress can only tell the total once the whole commitlist has been read
+        let commitlist: Box<dyn Iterator<Item = (String, String, String, String)> + Send> =
+            if cli.progress {
+                Box::new(
+                    read_commitlist_file(&commitfile)
+                        .collect::<Vec<_>>()
+                        .into_iter(),
+                )
+            } else {
+                Box::new(read_commitlist_file(&commitfile))
+            };
+        let options = three_way_merge_finder::publish::BugFixOptions {
+            fix_distance: cli.fix_distance,
+            fix_window_days: cli.fix_window_days,
+            with_same_author: cli.with_same_author,
+            exclude_merge_fixes: cli.exclude_merge_fixes,
+            required: cli.require,
+            fix_threshold: cli.fix_threshold,
+            with_score: cli.with_score,
+            file_scope: cli.fix_file_scope.into(),
+            explain_overlap: cli.explain_overlap,
+            extensions: cli.extensions,
+            context_lines: cli.context_lines,
+            show_progress: cli.progress,
+            jobs: cli.jobs,
+            notes_ref: cli.write_notes,
+            max_fixes: cli.max_fixes,
+            bug_issues: cli.bug_issues.as_deref().map(read_issue_list),
+        };
+        let mut stdout = std::io::stdout().lock();
         if cli.touches_same_line {
-            three_way_merge_finder::publish::print_bug_fix_csv_overlapping_lines(
+            three_way_merge_finder::publish::write_bug_fix_csv_overlapping_lines(
+                &mut stdout,
                 &repo,
-                &commitlist,
-                cli.fix_distance,
-            );
+                commitlist,
+                &options,
+            )
         } else {
-            three_way_merge_finder::publish::print_bug_fix_csv(
+            three_way_merge_finder::publish::write_bug_fix_csv(
+                &mut stdout,
                 &repo,
-                &commitlist,
-                cli.fix_distance,
-            );
+                commitlist,
+                &options,
+            )
         }
+        .expect("Failed to write to STDOUT");
     } else {
         eprintln!("Nothing to do");
     }
 }
 
-/// Reads in a CSV file of the form O,A,B,M SHAs.
-fn read_commitlist_file(filename: &str) -> Vec<(String, String, String, String)> {
-    // Read in the commitlist file
-    let mut content = String::new();
-    let mut f = File::open(filename).unwrap();
-    f.read_to_string(&mut content).unwrap();
+fn handle_compare_dumps(cli: CompareDumps) {
+    let differences = three_way_merge_finder::compare::compare_dumps(&cli.left, &cli.right)
+        .unwrap_or_else(|err| panic!("Failed to compare the dumps: {}", err));
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    if !differences.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn handle_selftest() {
+    if let Err(e) = three_way_merge_finder::selftest::run() {
+        eprintln!("Selftest failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Opens the repository at the given path. Exits with a message on STDERR (rather than a panic) if
+/// it cannot be opened, as that is usually a typo.
+fn open_repo(path: &str) -> git2::Repository {
+    match git2::Repository::open(path) {
+        Ok(repo) => repo,
+        Err(_) if !std::path::Path::new(path).exists() => {
+            eprintln!("Error: {} does not exist", path);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {} is not a Git repository: {}", path, e.message());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Creates (or truncates) a file to write output to, creating its parent folders if needed.
+fn create_output_file(filename: &str) -> File {
+    let path = std::path::Path::new(filename);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .unwrap_or_else(|err| panic!("Failed to create folder for {}: {}", filename, err));
+    }
+    File::create(path).unwrap_or_else(|err| panic!("Failed to create {}: {}", filename, err))
+}
+
+/// Turns a time given on the command line into seconds since the UNIX epoch. Plain integers are
+/// taken to already be epoch seconds.
+fn parse_time(input: &str) -> Result<i64, String> {
+    if let Ok(seconds) = input.parse::<i64>() {
+        return Ok(seconds);
+    }
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(input) {
+        return Ok(datetime.timestamp());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(input, format) {
+            return Ok(datetime.and_utc().timestamp());
+        }
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp());
+    }
+    Err(format!(
+        "'{}' is neither epoch seconds nor a date like 2021-06-01 or 2021-06-01T12:00:00",
+        input
+    ))
+}
+
+/// Reads the merge commits reported by an earlier run. Either a folder dump, where every subfolder
+/// is named after a merge commit, or a CSV with the merge commit in the fourth column. A file with
+/// just one hash per line works too. Lines that are not a hash, like the header, are ignored.
+fn read_manifest(path: &str) -> std::collections::HashSet<git2::Oid> {
+    let path = std::path::Path::new(path);
+    if path.is_dir() {
+        return path
+            .read_dir()
+            .unwrap_or_else(|err| panic!("Failed to read {}: {}", path.display(), err))
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str().map(git2::Oid::from_str))
+            .filter_map(Result::ok)
+            .collect();
+    }
+    let content = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", path.display(), err));
     content
-        .trim()
-        .split('\n')
-        .filter(|line| !line.is_empty())
-        .map(|line| {
-            let mut split_line = line.trim().split(',');
-            (
-                split_line.next().expect("Should be an O commit").to_owned(),
-                split_line.next().expect("Should be an A commit").to_owned(),
-                split_line.next().expect("Should be an B commit").to_owned(),
-                split_line.next().expect("Should be an M commit").to_owned(),
-            )
+        .lines()
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.trim().split(',').collect();
+            let m = if columns.len() >= 4 {
+                columns[3]
+            } else {
+                columns[0]
+            };
+            git2::Oid::from_str(m).ok()
         })
         .collect()
 }
+
+/// Reads the issue numbers for --bug-issues: the first column of every line, with or without a
+/// leading `#`. Lines where that is not a number, like a header, are ignored.
+fn read_issue_list(path: &str) -> std::collections::HashSet<u32> {
+    let content = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+    content
+        .lines()
+        .filter_map(|line| {
+            let first = line.split(',').next().unwrap_or_default().trim();
+            first.trim_start_matches('#').parse().ok()
+        })
+        .collect()
+}
+
+/// Reads in a CSV file of the form O,A,B,M SHAs, see `publish::read_commitlist`.
+fn read_commitlist_file(filename: &str) -> impl Iterator<Item = (String, String, String, String)> {
+    // Read the commitlist file a line at a time, as the merges get processed
+    let f =
+        File::open(filename).unwrap_or_else(|err| panic!("Failed to open {}: {}", filename, err));
+    three_way_merge_finder::publish::read_commitlist(std::io::BufReader::new(f))
+}
@@ -1,7 +1,10 @@
 //! This module is used to find three way merges
 
 use crate::git_utils;
-use std::collections::HashSet;
+use crate::sink::BlobSink;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
 /// Walks through commits, looking for those with (exactly) two parents. Collects parents and
 /// the common base.
@@ -10,13 +13,268 @@ pub fn find_merges(
     revwalk: git2::Revwalk,
     before: Option<i64>,
 ) -> Vec<ThreeWayMerge> {
+    iter_merges(repo, revwalk, before).collect()
+}
+
+/// Lazy version of `find_merges`. Merges are only looked up as the iterator advances.
+pub fn iter_merges<'repo>(
+    repo: &'repo git2::Repository,
+    revwalk: git2::Revwalk<'repo>,
+    before: Option<i64>,
+) -> impl Iterator<Item = ThreeWayMerge> + 'repo {
+    iter_merges_with_base(repo, revwalk, before, BaseStrategy::MergeBase)
+}
+
+/// Like `find_merges`, but instead of warning about every merge whose parents have no common
+/// base, counts them. See `MergeStats`. Merge bases are looked up in `cache` if given, see
+/// `MergeBaseCache`.
+pub fn find_merges_with_stats(
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    before: Option<i64>,
+    cache: Option<&mut MergeBaseCache>,
+) -> (Vec<ThreeWayMerge>, MergeStats) {
+    let mut stats = MergeStats::default();
+    let merges = merges_with(
+        repo,
+        revwalk,
+        before,
+        BaseStrategy::MergeBase,
+        Some(&mut stats),
+        cache,
+    )
+    .collect();
+    (merges, stats)
+}
+
+/// Like `find_merges`, but takes the merge bases from `cache` where possible and adds the ones it
+/// had to compute. Save the cache afterwards to skip them on the next run.
+pub fn find_merges_cached(
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    before: Option<i64>,
+    cache: &mut MergeBaseCache,
+) -> Vec<ThreeWayMerge> {
+    merges_with(
+        repo,
+        revwalk,
+        before,
+        BaseStrategy::MergeBase,
+        None,
+        Some(cache),
+    )
+    .collect()
+}
+
+/// The merges whose changed files (see `ThreeWayMerge::files_to_consider`) include `path`, e.g.,
+/// to follow the merge history of a hot file. With `follow_renames`, the names the file had
+/// before count as well, see `git_utils::former_paths`. That diffs every commit reachable from
+/// HEAD once, on top of the merges.
+pub fn find_merges_touching(
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    path: &str,
+    follow_renames: bool,
+) -> Result<Vec<ThreeWayMerge>, git2::Error> {
+    let paths = if follow_renames {
+        git_utils::former_paths(repo, path)?
+    } else {
+        HashSet::from([path.to_owned()])
+    };
+    Ok(iter_merges(repo, revwalk, None)
+        .filter(|merge| merge.changes_one_of(repo, &paths))
+        .collect())
+}
+
+/// Which merges `page_newest_first` keeps: `limit` merges (all remaining ones if None), after
+/// skipping the `offset` newest.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// Sorts the merges by the time of M, newest first, and returns the requested page of them.
+/// Merges made at the same time keep their order. Every merge has to be held in memory to sort
+/// them, though that is only the four commit ids each, roughly 100 bytes per merge.
+pub fn page_newest_first(
+    repo: &git2::Repository,
+    merges: impl IntoIterator<Item = ThreeWayMerge>,
+    page: Page,
+) -> Vec<ThreeWayMerge> {
+    let mut merges: Vec<_> = merges.into_iter().collect();
+    merges.sort_by_cached_key(|merge| std::cmp::Reverse(merge.time(repo)));
+    merges
+        .into_iter()
+        .skip(page.offset)
+        .take(page.limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// The two branches a merge commit joined, going by its summary, as (merged, into). The order of
+/// the pair does not matter to `first_merges_per_branch_pair`. Understands the summaries of
+///
+/// * git: `Merge branch 'topic'`, `Merge branch 'topic' of <url> into main`, and
+///   `Merge remote-tracking branch 'origin/topic'`, the remote being dropped
+/// * GitLab: `Merge branch 'topic' into 'main'`
+/// * GitHub: `Merge pull request #123 from user/topic`, the fork owner being dropped
+///
+/// git leaves out the `into` part when merging into the default branch, as does GitHub always,
+/// so `into` is then empty. None for other summaries, including octopus merges
+/// (`Merge branches 'a' and 'b'`).
+pub fn branch_pair(summary: &str) -> Option<(String, String)> {
+    lazy_static! {
+        static ref BRANCH_MATCHER: regex::Regex = regex::Regex::new(
+            r"^Merge (remote-tracking )?branch '([^']+)'(?: of \S+)?(?: into '?([^']+?)'?)?$"
+        )
+        .unwrap();
+        static ref PULL_REQUEST_MATCHER: regex::Regex =
+            regex::Regex::new(r"^Merge pull request #\d+ from [^/\s]+/(\S+)$").unwrap();
+    }
+    let summary = summary.trim();
+    if let Some(captures) = BRANCH_MATCHER.captures(summary) {
+        let mut merged = &captures[2];
+        if captures.get(1).is_some() {
+            merged = merged.split_once('/').map_or(merged, |(_, branch)| branch);
+        }
+        let into = captures.get(3).map_or("", |into| into.as_str());
+        return Some((merged.to_owned(), into.to_owned()));
+    }
+    PULL_REQUEST_MATCHER
+        .captures(summary)
+        .map(|captures| (captures[1].to_owned(), String::new()))
+}
+
+/// The merge commits reachable from HEAD, except for those joining the same two branches (see
+/// `branch_pair`) as an older merge. Meant for repositories where long-lived branches are synced
+/// again and again, where every sync would otherwise be counted as a merge of its own.
+///
+/// Merges are identified by their summary, as A and B are different commits for every sync. That
+/// only goes so far:
+///
+/// * Merges whose summary names no branch (edited messages, `git pull` of a detached commit,
+///   other tools) are all kept
+/// * Branches are told apart by name only. A branch that is deleted and later recreated under
+///   the same name, or a generic name like `patch-1` on GitHub, counts as one branch.
+/// * The default branch appears as an empty name, so `Merge branch 'dev'` and
+///   `Merge branch 'dev' into main` count as different pairs
+///
+/// "Older" means earlier in history: parents come before their children, unrelated merges go by
+/// time.
+pub fn first_merges_per_branch_pair(
+    repo: &git2::Repository,
+) -> Result<HashSet<git2::Oid>, git2::Error> {
+    let revwalk = git_utils::create_revwalk(
+        repo,
+        git2::Sort::TOPOLOGICAL | git2::Sort::TIME | git2::Sort::REVERSE,
+    )?;
+    let mut seen_pairs = HashSet::new();
+    let mut firsts = HashSet::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() < 2 {
+            continue;
+        }
+        let summary = commit
+            .summary_bytes()
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+        let first = match branch_pair(&summary) {
+            Some((merged, into)) => {
+                let pair = if merged <= into {
+                    (merged, into)
+                } else {
+                    (into, merged)
+                };
+                seen_pairs.insert(pair)
+            }
+            None => true,
+        };
+        if first {
+            firsts.insert(commit.id());
+        }
+    }
+    Ok(firsts)
+}
+
+/// Like `iter_merges`, but picks O of every merge according to `base`.
+pub fn iter_merges_with_base<'repo>(
+    repo: &'repo git2::Repository,
+    revwalk: git2::Revwalk<'repo>,
+    before: Option<i64>,
+    base: BaseStrategy,
+) -> impl Iterator<Item = ThreeWayMerge> + 'repo {
+    merges_with(repo, revwalk, before, base, None, None)
+}
+
+/// Async version of `iter_merges`, walking `repo` from HEAD like `git_utils::create_revwalk`.
+/// git2 is blocking, so the walk runs on tokio's blocking pool and sends the merges over a channel
+/// as they are found. Dropping the stream stops the walk. Errors creating the revwalk end the stream, see
+/// `verbosity::ErrorPolicy`.
+///
+/// Must be called from within a tokio runtime. Only available with the `async` feature.
+#[cfg(feature = "async")]
+pub fn find_merges_stream(
+    repo: git2::Repository,
+    sorting: git2::Sort,
+    before: Option<i64>,
+) -> impl futures_core::Stream<Item = ThreeWayMerge> + Unpin {
+    /// Merges waiting for the consumer. The walk pauses when this many are waiting.
+    const BUFFER: usize = 64;
+
+    struct MergeStream(tokio::sync::mpsc::Receiver<ThreeWayMerge>);
+
+    impl futures_core::Stream for MergeStream {
+        type Item = ThreeWayMerge;
+
+        fn poll_next(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<ThreeWayMerge>> {
+            self.0.poll_recv(cx)
+        }
+    }
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let revwalk = match git_utils::create_revwalk(&repo, sorting) {
+            Ok(revwalk) => revwalk,
+            Err(e) => {
+                recoverable_error!("Failed to walk {:?}: {}", repo.path(), e);
+                return;
+            }
+        };
+        for merge in iter_merges(&repo, revwalk, before) {
+            if sender.blocking_send(merge).is_err() {
+                // The stream was dropped, nobody is interested in further merges.
+                break;
+            }
+        }
+    });
+    MergeStream(receiver)
+}
+
+/// Shared by the functions above. Merges without a common base are counted in `stats` if given,
+/// or warned about one by one otherwise. `cache` is only used with `BaseStrategy::MergeBase`.
+/// Merges with multiple merge bases are only counted for `BaseStrategy::MergeBase`.
+fn merges_with<'a>(
+    repo: &'a git2::Repository,
+    revwalk: git2::Revwalk<'a>,
+    before: Option<i64>,
+    base: BaseStrategy,
+    mut stats: Option<&'a mut MergeStats>,
+    mut cache: Option<&'a mut MergeBaseCache>,
+) -> impl Iterator<Item = ThreeWayMerge> + 'a {
+    // Counting needs all merge bases, not just the first. Without a cache, a throwaway one keeps
+    // that from being computed twice.
+    let mut own_cache = (stats.is_some() && cache.is_none()).then(MergeBaseCache::default);
     revwalk
         .map(|oid| {
             repo.find_commit(oid.expect("Failed to get Oid"))
                 .expect("Failed to turn oid into a commit")
         })
         .filter(|commit| commit.parent_count() == 2)
-        .filter(|commit| {
+        .filter(move |commit| {
             if let Some(before) = before {
                 commit.time().seconds() < before
             } else {
@@ -24,11 +282,43 @@ pub fn find_merges(
             }
         })
         // filter_map is map + flatten. Filters out None and unwraps Some
-        .filter_map(|commit| {
-            match ThreeWayMerge::new(repo, &commit) {
+        .filter_map(move |commit| {
+            let cache = cache.as_deref_mut().or(own_cache.as_mut());
+            let (twm, multiple) = match (&base, cache) {
+                (BaseStrategy::MergeBase, Some(cache)) => {
+                    let twm = ThreeWayMerge::with_cached_base(repo, &commit, cache);
+                    let multiple = twm
+                        .as_ref()
+                        .ok()
+                        .and_then(|twm| cache.has_multiple_bases(twm.a, twm.b));
+                    (twm, multiple.unwrap_or(false))
+                }
+                _ => (ThreeWayMerge::with_base(repo, &commit, &base), false),
+            };
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.examined += 1;
+                match &twm {
+                    Ok(_) if multiple => stats.multiple_bases += 1,
+                    Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                        stats.no_base += 1;
+                        return None;
+                    }
+                    _ => {}
+                }
+            }
+            match twm {
                 Ok(twm) => Some(twm),
+                // Unrelated histories are routine, not an error in the data.
+                Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                    warning!(
+                        "Failed to find either parent commits or their common base for {}. Full error: {}",
+                        commit.id(),
+                        e
+                    );
+                    None
+                }
                 Err(e) => {
-                    eprintln!(
+                    recoverable_error!(
                         "Failed to find either parent commits or their common base for {}. Full error: {}",
                         commit.id(),
                         e
@@ -37,7 +327,176 @@ pub fn find_merges(
                 }
             }
         })
-        .collect()
+}
+
+/// How M resolved a file that conflicts when merging A and B, see
+/// `ThreeWayMerge::conflict_resolutions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resolution {
+    /// M has A's version of the file (or lacks it, like A)
+    Ours,
+    /// M has B's version of the file (or lacks it, like B)
+    Theirs,
+    /// M has both sides of every conflicting hunk, A's first, as a union merge would
+    Both,
+    /// Anything else, i.e., a manual resolution
+    Custom,
+}
+
+/// The number of conflicting files of a merge per `Resolution`, see
+/// `ThreeWayMerge::resolution_counts`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResolutionCounts {
+    pub ours: usize,
+    pub theirs: usize,
+    pub both: usize,
+    pub custom: usize,
+}
+
+/// How many merges `find_merges_with_stats` came across, and how many of those had an odd base.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MergeStats {
+    /// Commits with two parents, within the time limit
+    pub examined: usize,
+    /// Merges of unrelated histories, whose parents have no common ancestor. These are left out.
+    pub no_base: usize,
+    /// Merges whose parents have more than one best common ancestor, e.g., after criss-cross
+    /// merges. O is then just one of them.
+    pub multiple_bases: usize,
+}
+
+impl std::fmt::Display for MergeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Examined {} merges: {} without a merge base (skipped), {} with multiple merge bases",
+            self.examined, self.no_base, self.multiple_bases
+        )
+    }
+}
+
+/// Merge bases of earlier runs, by the two parents of the merge. Computing the merge base is the
+/// expensive part of finding merges, so repositories analysed again and again can keep them in a
+/// CSV file (`parent0,parent1,base,multiple_bases`) between runs, see `load` and `save`.
+#[derive(Default)]
+pub struct MergeBaseCache {
+    bases: HashMap<(git2::Oid, git2::Oid), (git2::Oid, bool)>,
+    computed: usize,
+}
+
+impl MergeBaseCache {
+    const HEADER: &'static str = "parent0,parent1,base,multiple_bases";
+
+    /// Reads a cache written by `save`. A missing file is an empty cache. Stale entries, whose
+    /// commits are not (or no longer) in `repo`, are dropped.
+    pub fn load<P: AsRef<Path>>(repo: &git2::Repository, path: P) -> std::io::Result<Self> {
+        let invalid = |line: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid merge base cache entry: {}", line),
+            )
+        };
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let mut cache = Self::default();
+        for line in content.lines().filter(|line| *line != Self::HEADER) {
+            let fields: Vec<&str> = line.split(',').collect();
+            let [a, b, base, multiple] = fields[..] else {
+                return Err(invalid(line));
+            };
+            let oids = [a, b, base].map(|oid| git2::Oid::from_str(oid).map_err(|_| invalid(line)));
+            let [a, b, base] = match oids {
+                [Ok(a), Ok(b), Ok(base)] => [a, b, base],
+                _ => return Err(invalid(line)),
+            };
+            let multiple = multiple.parse().map_err(|_| invalid(line))?;
+            if [a, b, base]
+                .iter()
+                .all(|oid| repo.find_commit(*oid).is_ok())
+            {
+                cache.bases.insert((a, b), (base, multiple));
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Writes every cached merge base to `path`, sorted, replacing the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut entries: Vec<_> = self.bases.iter().collect();
+        entries.sort();
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(out, "{}", Self::HEADER)?;
+        for ((a, b), (base, multiple)) in entries {
+            writeln!(out, "{},{},{},{}", a, b, base, multiple)?;
+        }
+        out.flush()
+    }
+
+    /// The merge base of `a` and `b`, from the cache or computed (and then cached).
+    pub fn merge_base(
+        &mut self,
+        repo: &git2::Repository,
+        a: git2::Oid,
+        b: git2::Oid,
+    ) -> Result<git2::Oid, git2::Error> {
+        if let Some((base, _)) = self.bases.get(&(a, b)) {
+            return Ok(*base);
+        }
+        // The first of the merge bases is the one `merge_base` picks.
+        let bases = repo.merge_bases(a, b)?;
+        self.computed += 1;
+        let base = bases[0];
+        self.bases.insert((a, b), (base, bases.len() > 1));
+        Ok(base)
+    }
+
+    /// Whether `a` and `b` have more than one best common ancestor, if their merge base is cached.
+    pub fn has_multiple_bases(&self, a: git2::Oid, b: git2::Oid) -> Option<bool> {
+        self.bases.get(&(a, b)).map(|(_, multiple)| *multiple)
+    }
+
+    /// How many merge bases were computed rather than taken from the cache
+    pub fn computed(&self) -> usize {
+        self.computed
+    }
+
+    /// The number of cached merge bases
+    pub fn len(&self) -> usize {
+        self.bases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bases.is_empty()
+    }
+}
+
+/// Calls `f` on every merge as soon as it is found. This is the intended extension point for
+/// library users who want to do their own processing (custom metrics, feeding a model, ...)
+/// without going through the CSV or folder output.
+///
+/// ```no_run
+/// let repo = git2::Repository::open(".").unwrap();
+/// let revwalk =
+///     three_way_merge_finder::git_utils::create_revwalk(&repo, git2::Sort::TOPOLOGICAL).unwrap();
+/// let mut count = 0;
+/// three_way_merge_finder::merge::for_each_merge(&repo, revwalk, None, |_merge| count += 1);
+/// println!("{} merges", count);
+/// ```
+pub fn for_each_merge<F>(
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    before: Option<i64>,
+    mut f: F,
+) where
+    F: FnMut(&ThreeWayMerge),
+{
+    for merge in iter_merges(repo, revwalk, before) {
+        f(&merge);
+    }
 }
 
 /// Represents the four parts of a merge by storing the Oid of the merge commit, its parent
@@ -51,21 +510,400 @@ pub struct ThreeWayMerge {
     pub b: git2::Oid,
     /// The merge commit
     pub m: git2::Oid,
+    /// Whether a and b are in the opposite order of the merge commit's parents, see
+    /// `order_by_change_size`.
+    pub a_b_swapped: bool,
+}
+
+/// How O of a merge is picked. Anything but `MergeBase` changes what O means: O→A, O→B, and O→M
+/// are then no longer the changes git itself merged, so e.g. `has_distinct_o`, the churn, and the
+/// side stats describe the merge relative to the chosen commit instead.
+#[derive(Clone, Copy, Default)]
+pub enum BaseStrategy {
+    /// The best common ancestor of A and B, as picked by `git merge-base`
+    #[default]
+    MergeBase,
+    /// The oldest common ancestor of A and B within this many generations of both, see
+    /// `git_utils::oldest_common_ancestor_within`. Falls back to the merge base if A and B do not
+    /// meet within that distance.
+    OldestCommonAncestorWithin(u32),
+    /// This exact commit, e.g., a tagged release the merge is compared against
+    Explicit(git2::Oid),
+}
+
+/// Paths as nested folders, e.g., for a file browser. Serialised as an object per folder, with a
+/// key per entry: another object for a subfolder, `null` for a file.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum FileTree {
+    File,
+    Folder(std::collections::BTreeMap<String, FileTree>),
+}
+
+impl FileTree {
+    /// Builds the folder holding all the given git paths (split on `/`). Should a path be a file
+    /// as well as a folder of other paths, e.g., when a file was replaced by a folder of the same
+    /// name, the folder wins.
+    pub fn from_paths<'a>(paths: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut root = FileTree::Folder(Default::default());
+        for path in paths {
+            let mut node = &mut root;
+            let mut parts = path.split('/').peekable();
+            while let Some(part) = parts.next() {
+                if let FileTree::File = node {
+                    *node = FileTree::Folder(Default::default());
+                }
+                let FileTree::Folder(entries) = node else {
+                    unreachable!("Files were just turned into folders");
+                };
+                let default = if parts.peek().is_some() {
+                    FileTree::Folder(Default::default())
+                } else {
+                    FileTree::File
+                };
+                node = entries.entry(part.to_owned()).or_insert(default);
+            }
+        }
+        root
+    }
+}
+
+/// Everything we know about a single three way merge, in a form that can be serialised. Used for
+/// the `merge.json` file placed in each merge folder.
+#[derive(Serialize)]
+pub struct MergeRecord {
+    pub o: String,
+    pub a: String,
+    pub b: String,
+    pub m: String,
+    /// Epoch seconds of the merge commit
+    pub timestamp: i64,
+    /// Files changed from O to M, sorted
+    pub changed_files: Vec<String>,
+    /// The same files as nested folders, see `FileTree`
+    pub changed_file_tree: FileTree,
+    pub distinct_o: bool,
+    pub a_b_swapped: bool,
+    /// The folder ("a" or "b") that holds the files of O, when the o folder was left out because
+    /// O is the same commit, see `WriteOptions::dedup_identical_parts`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub o_same_as: Option<String>,
+}
+
+/// How `ThreeWayMerge::write_files` and `ThreeWayMerge::write_all_files` fill the o, a, b, and m
+/// folders.
+#[derive(Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Leave a file out of o, a, and b when it is identical to the version in m. The four folders
+    /// then no longer contain the same files.
+    pub only_differing: bool,
+    /// When O is the same commit as A (or B), make the o folder a link to the a (or b) folder
+    /// instead of writing the same files twice. If the sink cannot hold links, o is left out.
+    pub dedup_identical_parts: bool,
+    /// Rewrite line endings or leave out large files, see
+    /// `git_utils::write_files_from_commit_to_disk`
+    pub blob_options: git_utils::BlobOptions,
+}
+
+/// What a file in M has to be missing from to count as new, see
+/// `ThreeWayMerge::introduces_new_files`.
+#[derive(Clone, Copy, Default)]
+pub enum NewFilesBase {
+    /// Not in O, i.e., added on either branch or in the merge itself
+    #[default]
+    O,
+    /// Neither in A nor in B, i.e., only added while resolving the merge
+    Parents,
+}
+
+/// What makes O distinct from A and B, see `ThreeWayMerge::has_distinct_o_by`.
+#[derive(Clone, Copy, Default)]
+pub enum DistinctOMode {
+    /// O is a different commit than A and B, see `ThreeWayMerge::has_distinct_o`
+    #[default]
+    Oid,
+    /// O has a different tree than A and B, see `ThreeWayMerge::both_branches_nonempty`. A branch
+    /// of empty commits, or of changes that were reverted again, has nothing to merge either.
+    Tree,
+}
+
+/// Which time of M stands for the time of the merge, see `ThreeWayMerge::time_by`.
+#[derive(Clone, Copy, Default)]
+pub enum TimeSource {
+    /// When M was committed, i.e., when the merge was made
+    #[default]
+    Committer,
+    /// When M was authored. Differs from the committer time if M was rebased or amended later.
+    Author,
+}
+
+/// How paths are written out, e.g., the changed files in `merge.json`. Git always uses forward
+/// slashes, which is also the default here.
+#[derive(Clone, Copy, Default)]
+pub enum PathStyle {
+    /// Forward slashes, as git has them
+    #[default]
+    Posix,
+    /// The separator of the platform the tool runs on, i.e., backslashes on Windows
+    Native,
+}
+
+impl PathStyle {
+    /// Rewrites a git path (forward slashes) in this style.
+    pub fn apply(self, path: &str) -> String {
+        match self {
+            PathStyle::Posix => path.to_owned(),
+            PathStyle::Native => path.replace('/', std::path::MAIN_SEPARATOR_STR),
+        }
+    }
+}
+
+/// One of the two branches of a merge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// The first parent's branch, O→A
+    A,
+    /// The second parent's branch, O→B
+    B,
+}
+
+/// Build and dependency configuration files of common ecosystems, in any folder. The default
+/// patterns for `ThreeWayMerge::touches_build_files`.
+pub const BUILD_FILE_GLOBS: [&str; 4] = [
+    "**/pom.xml",
+    "**/Cargo.toml",
+    "**/build.gradle",
+    "**/package.json",
+];
+
+/// `BUILD_FILE_GLOBS` as patterns.
+pub fn build_file_patterns() -> Vec<glob::Pattern> {
+    BUILD_FILE_GLOBS
+        .iter()
+        .map(|glob| glob::Pattern::new(glob).expect("Built-in glob should be valid"))
+        .collect()
+}
+
+/// Criteria a merge has to meet to be reported. The default lets every merge through.
+#[derive(Default)]
+pub struct MergeFilter {
+    /// Skip merges whose merge commit is in this set, e.g., because an earlier run already
+    /// reported them.
+    pub skip: HashSet<git2::Oid>,
+    /// Skip merges made at or before this time (epoch seconds).
+    pub after: Option<i64>,
+    /// If given, skip merges whose branches were open for fewer days, i.e., from O to M, see
+    /// `ThreeWayMerge::integration_lag`. Merges where M predates O (clock skew) are skipped with
+    /// a warning.
+    pub min_branch_age_days: Option<u32>,
+    /// Skip merges that are not evil, see `ThreeWayMerge::is_evil_merge`
+    pub only_evil: bool,
+    /// If given, skip merges changing more files than this, see
+    /// `ThreeWayMerge::files_to_consider`. Huge merges are mostly reformatting or vendoring.
+    pub max_changed_files: Option<usize>,
+    /// If given, skip merges whose merge commit is not in this set. Meant to be HEAD's
+    /// first-parent chain, see `git_utils::first_parent_chain`.
+    pub mainline: Option<HashSet<git2::Oid>>,
+    /// If given, skip merges whose merge commit is not in this set. Meant to be the first merge
+    /// of every pair of branches, see `first_merges_per_branch_pair`.
+    pub unique_branch_pairs: Option<HashSet<git2::Oid>>,
+    /// Skip merges where O equals A or B. See `ThreeWayMerge::has_distinct_o_by`.
+    pub distinct_o: bool,
+    /// How `distinct_o` compares O with A and B
+    pub distinct_o_mode: DistinctOMode,
+    /// Skip merges where M has the same tree as A or B. See
+    /// `ThreeWayMerge::m_differs_from_parents`.
+    pub distinct_m: bool,
+    /// Skip merges where M has the same tree as A. See `ThreeWayMerge::is_empty_merge`.
+    pub exclude_empty: bool,
+    /// Skip merges where A or B has the same tree as O. See
+    /// `ThreeWayMerge::both_branches_nonempty`.
+    pub both_branches_changed: bool,
+    /// Skip merges where A and B did not change a common Java file.
+    pub touches_same_file: bool,
+    /// If not empty, skip merges that do not change a file in one of these languages. See
+    /// `crate::language::detect` for the names.
+    pub languages: Vec<String>,
+    /// Skip merges where every file changed from O to M matches one of these patterns, e.g.,
+    /// merges only touching `CHANGELOG.md` or `.github/**`.
+    pub exclude_paths: Vec<glob::Pattern>,
+    /// If not empty, skip merges that change none of these paths. A targeted variant of
+    /// `exclude_paths`, see `ThreeWayMerge::changes_one_of` and `find_merges_touching`.
+    pub only_paths: HashSet<String>,
+    /// If given, skip merges unless exactly one of the branches changed this file. See
+    /// `ThreeWayMerge::only_one_side_changed`.
+    pub single_side_file: Option<String>,
+    /// If given, skip merges that change no file matching one of these patterns, e.g.,
+    /// `build_file_patterns()`. See `ThreeWayMerge::touches_build_files`.
+    pub build_files: Option<Vec<glob::Pattern>>,
+    /// Skip merges whose resolution changed more than whitespace, or nothing at all. See
+    /// `ThreeWayMerge::only_whitespace_resolution`.
+    pub only_whitespace: bool,
+    /// If given, skip merges that do not introduce a new file. See
+    /// `ThreeWayMerge::introduces_new_files`.
+    pub new_files: Option<NewFilesBase>,
+}
+
+impl MergeFilter {
+    /// Whether the given merge meets all the criteria. Cheap checks are done first.
+    pub fn keep(&self, merge: &ThreeWayMerge, repo: &git2::Repository) -> bool {
+        self.keep_with_file_count(merge, repo, &mut None)
+    }
+
+    /// Like `keep`, but also returns the number of files the merge changes, see
+    /// `ThreeWayMerge::files_to_consider`. None if the merge is not kept. Where the filter already
+    /// needed the count, O and M are not diffed again.
+    pub fn keep_counting_files(
+        &self,
+        merge: &ThreeWayMerge,
+        repo: &git2::Repository,
+    ) -> Option<usize> {
+        let mut file_count = None;
+        if !self.keep_with_file_count(merge, repo, &mut file_count) {
+            return None;
+        }
+        Some(file_count.unwrap_or_else(|| merge.files_to_consider(repo).len()))
+    }
+
+    /// Shared by `keep` and `keep_counting_files`. `file_count` is filled in once known.
+    fn keep_with_file_count(
+        &self,
+        merge: &ThreeWayMerge,
+        repo: &git2::Repository,
+        file_count: &mut Option<usize>,
+    ) -> bool {
+        if self.skip.contains(&merge.m) {
+            return false;
+        }
+        if let Some(after) = self.after {
+            if merge.time(repo) <= after {
+                return false;
+            }
+        }
+        if let Some(days) = self.min_branch_age_days {
+            let (age, _) = merge.integration_lag(repo);
+            if age < 0 {
+                warning!(
+                    "Skipping {}, made {} seconds before its base {}. Clock skew?",
+                    merge.m,
+                    -age,
+                    merge.o
+                );
+                return false;
+            }
+            if age < i64::from(days) * 24 * 60 * 60 {
+                return false;
+            }
+        }
+        if let Some(mainline) = &self.mainline {
+            if !mainline.contains(&merge.m) {
+                return false;
+            }
+        }
+        if let Some(firsts) = &self.unique_branch_pairs {
+            if !firsts.contains(&merge.m) {
+                return false;
+            }
+        }
+        if self.distinct_o && !merge.has_distinct_o_by(repo, self.distinct_o_mode) {
+            return false;
+        }
+        if self.exclude_empty && merge.is_empty_merge(repo) {
+            return false;
+        }
+        if self.both_branches_changed && !merge.both_branches_nonempty(repo) {
+            return false;
+        }
+        if self.distinct_m && !merge.m_differs_from_parents(repo) {
+            return false;
+        }
+        if let Some(max) = self.max_changed_files {
+            let count = file_count.get_or_insert_with(|| merge.files_to_consider(repo).len());
+            if *count > max {
+                return false;
+            }
+        }
+        if self.touches_same_file && !merge.a_b_change_same_file(repo, &[".java"]) {
+            return false;
+        }
+        if !self.languages.is_empty() && !merge.changes_language(repo, &self.languages) {
+            return false;
+        }
+        if !self.exclude_paths.is_empty() && merge.only_changes_paths(repo, &self.exclude_paths) {
+            return false;
+        }
+        if !self.only_paths.is_empty() && !merge.changes_one_of(repo, &self.only_paths) {
+            return false;
+        }
+        if let Some(path) = &self.single_side_file {
+            if merge.only_one_side_changed(repo, path).is_none() {
+                return false;
+            }
+        }
+        if let Some(patterns) = &self.build_files {
+            if !merge.touches_build_files(repo, patterns) {
+                return false;
+            }
+        }
+        if self.only_whitespace && !merge.only_whitespace_resolution(repo) {
+            return false;
+        }
+        if let Some(base) = self.new_files {
+            if !merge.introduces_new_files(repo, base) {
+                return false;
+            }
+        }
+        if self.only_evil && !merge.is_evil_merge(repo) {
+            return false;
+        }
+        true
+    }
 }
 
 impl ThreeWayMerge {
-    // Create new ThreeWayMerge based on a valid merge commit.
-    fn new(repo: &git2::Repository, commit: &git2::Commit) -> Result<ThreeWayMerge, git2::Error> {
+    /// Create a new ThreeWayMerge based on a valid merge commit, picking O according to `base`.
+    pub fn with_base(
+        repo: &git2::Repository,
+        commit: &git2::Commit,
+        base: &BaseStrategy,
+    ) -> Result<ThreeWayMerge, git2::Error> {
         // Parent order is deterministic and saved as part of the merge commit. Subsequent runs
         // will thus give the same parents for each position.
         let parent1 = commit.parent_id(0)?;
         let parent2 = commit.parent_id(1)?;
-        let base = repo.merge_base(parent1, parent2)?;
+        let base = match base {
+            BaseStrategy::MergeBase => repo.merge_base(parent1, parent2)?,
+            BaseStrategy::OldestCommonAncestorWithin(n) => {
+                match git_utils::oldest_common_ancestor_within(repo, parent1, parent2, *n)? {
+                    Some(oldest) => oldest,
+                    None => repo.merge_base(parent1, parent2)?,
+                }
+            }
+            BaseStrategy::Explicit(o) => *o,
+        };
         Ok(ThreeWayMerge {
             o: base,
             a: parent1,
             b: parent2,
             m: commit.id(),
+            a_b_swapped: false,
+        })
+    }
+
+    /// Like `with_base` with `BaseStrategy::MergeBase`, but looking the merge base up in `cache`.
+    pub fn with_cached_base(
+        repo: &git2::Repository,
+        commit: &git2::Commit,
+        cache: &mut MergeBaseCache,
+    ) -> Result<ThreeWayMerge, git2::Error> {
+        let parent1 = commit.parent_id(0)?;
+        let parent2 = commit.parent_id(1)?;
+        Ok(ThreeWayMerge {
+            o: cache.merge_base(repo, parent1, parent2)?,
+            a: parent1,
+            b: parent2,
+            m: commit.id(),
+            a_b_swapped: false,
         })
     }
 
@@ -81,6 +919,18 @@ impl ThreeWayMerge {
         )
     }
 
+    /// Like `with_base`, but starting from the hash of the merge commit.
+    pub fn from_merge_str(
+        repo: &git2::Repository,
+        m_str: &str,
+        base: &BaseStrategy,
+    ) -> Result<Self, git2::Error> {
+        let commit = repo.find_commit(git2::Oid::from_str(m_str)?)?;
+        Self::with_base(repo, &commit, base)
+    }
+
+    /// Build a merge from the four hashes as given, e.g., a line of a commitlist. Nothing is
+    /// checked, so O can be any commit; see `BaseStrategy` for what that means.
     pub fn from_oid_str(
         o_str: &str,
         a_str: &str,
@@ -91,7 +941,39 @@ impl ThreeWayMerge {
         let a = git2::Oid::from_str(a_str)?;
         let b = git2::Oid::from_str(b_str)?;
         let m = git2::Oid::from_str(m_str)?;
-        Ok(Self { o, a, b, m })
+        Ok(Self {
+            o,
+            a,
+            b,
+            m,
+            a_b_swapped: false,
+        })
+    }
+
+    /// Checks the four commits fit together: A and B are the parents of M (in either order), and O
+    /// is an ancestor of (or equal to) each of A, B, and M. Meant for merges read from elsewhere,
+    /// e.g., through `from_oid_str`, where nothing guarantees this. The error names the first
+    /// relationship that does not hold.
+    pub fn validate(&self, repo: &git2::Repository) -> Result<(), git2::Error> {
+        let m = repo.find_commit(self.m)?;
+        let parents: Vec<git2::Oid> = m.parent_ids().collect();
+        for (name, parent) in [("A", self.a), ("B", self.b)] {
+            if !parents.contains(&parent) {
+                return Err(git2::Error::from_str(&format!(
+                    "{} {} is not a parent of M {}",
+                    name, parent, self.m
+                )));
+            }
+        }
+        for (name, commit) in [("A", self.a), ("B", self.b), ("M", self.m)] {
+            if commit != self.o && !repo.graph_descendant_of(commit, self.o)? {
+                return Err(git2::Error::from_str(&format!(
+                    "O {} is not an ancestor of {} {}",
+                    self.o, name, commit
+                )));
+            }
+        }
+        Ok(())
     }
 
     /// Analyse the merge diffs to decide which files have been modified and are thus
@@ -133,72 +1015,394 @@ impl ThreeWayMerge {
         paths
     }
 
+    /// Same as `files_to_consider`, but sorted, for when the output has to be reproducible.
+    pub fn files_to_consider_sorted(&self, repo: &git2::Repository) -> Vec<String> {
+        let mut files: Vec<String> = self.files_to_consider(repo).into_iter().collect();
+        files.sort();
+        files
+    }
+
+    /// Keeps those of the given files that are at most `max_bytes` large in each of O, A, B, and M.
+    /// A file missing from a part does not count against it. Meant to leave out the files a
+    /// `git_utils::BlobOptions::max_bytes` would skip in some of the parts.
+    pub fn files_within_size(
+        &self,
+        repo: &git2::Repository,
+        files: HashSet<String>,
+        max_bytes: usize,
+    ) -> HashSet<String> {
+        let odb = repo.odb().expect("Failed to open the object database");
+        let trees: Vec<_> = [self.o, self.a, self.b, self.m]
+            .into_iter()
+            .map(|oid| {
+                repo.find_commit(oid)
+                    .and_then(|commit| commit.tree())
+                    .expect("Failed to find tree for commit")
+            })
+            .collect();
+        files
+            .into_iter()
+            .filter(|file| {
+                trees.iter().all(|tree| {
+                    tree.get_path(Path::new(file))
+                        .and_then(|entry| odb.read_header(entry.id()))
+                        .map_or(true, |(size, _kind)| size <= max_bytes)
+                })
+            })
+            .collect()
+    }
+
     /// For a given list of files, locates them in each part of the ThreeWayMerge. Places them
-    /// in o, a, b, or m folders which are created as subfolders of the provided folder.
+    /// in o, a, b, or m folders which are created as subfolders of the provided folder. See
+    /// `WriteOptions` for what goes into them.
+    ///
+    /// Returns the folder O's files are in if the o folder was left out, see
+    /// `WriteOptions::dedup_identical_parts`.
     pub fn write_files_to_disk<P: AsRef<std::path::Path>>(
         &self,
         folder: P,
         files: std::collections::HashSet<String>,
         repo: &git2::Repository,
-    ) {
-        let folder = folder.as_ref();
-        let paths = [
-            folder.join("o"),
-            folder.join("a"),
-            folder.join("b"),
-            folder.join("m"),
-        ];
-        for path in &paths {
-            std::fs::create_dir_all(path).expect("Could not create folder");
-        }
+        options: WriteOptions,
+    ) -> Option<&'static str> {
+        let mut sink = crate::sink::FolderSink::new(folder);
+        self.write_files(&mut sink, Path::new(""), files, repo, options)
+    }
+
+    /// Like `write_files_to_disk`, but writes to any sink, with the o, a, b, and m folders placed
+    /// below `prefix`.
+    pub fn write_files(
+        &self,
+        sink: &mut dyn BlobSink,
+        prefix: &Path,
+        files: HashSet<String>,
+        repo: &git2::Repository,
+        options: WriteOptions,
+    ) -> Option<&'static str> {
+        let files_for = |part: git2::Oid| {
+            if options.only_differing {
+                self.files_differing_from_m(repo, part, &files)
+            } else {
+                files.clone()
+            }
+        };
+        let o_files = files_for(self.o);
+        let a_files = files_for(self.a);
+        let b_files = files_for(self.b);
 
-        git_utils::write_files_from_commit_to_disk(folder.join("o"), self.o, repo, &files, "O");
-        git_utils::write_files_from_commit_to_disk(folder.join("a"), self.a, repo, &files, "A");
-        git_utils::write_files_from_commit_to_disk(folder.join("b"), self.b, repo, &files, "B");
-        git_utils::write_files_from_commit_to_disk(folder.join("m"), self.m, repo, &files, "M");
+        self.write_parts(
+            sink,
+            prefix,
+            [o_files, a_files, b_files, files],
+            repo,
+            options,
+        )
     }
 
     /// For O, A, B, and M, writes all the files in each version to disk. In other words, a file
     /// does not need to be present in all four parts, let alone needing to have a change.
+    ///
+    /// `options` and the result are as for `write_files_to_disk`.
     pub fn write_all_files_to_disk<P: AsRef<std::path::Path>>(
         &self,
         folder: P,
         repo: &git2::Repository,
-    ) {
-        let folder = folder.as_ref();
-        let paths = [
-            folder.join("o"),
-            folder.join("a"),
-            folder.join("b"),
-            folder.join("m"),
+        options: WriteOptions,
+    ) -> Option<&'static str> {
+        let mut sink = crate::sink::FolderSink::new(folder);
+        self.write_all_files(&mut sink, Path::new(""), repo, options)
+    }
+
+    /// Like `write_all_files_to_disk`, but writes to any sink, with the o, a, b, and m folders
+    /// placed below `prefix`.
+    pub fn write_all_files(
+        &self,
+        sink: &mut dyn BlobSink,
+        prefix: &Path,
+        repo: &git2::Repository,
+        options: WriteOptions,
+    ) -> Option<&'static str> {
+        // Create a list of all files for each version. The four trees share most of their
+        // subtrees, so keep one cache for all of them.
+        let mut cache = HashMap::new();
+        let mut paths_of = |oid: git2::Oid| {
+            let commit = repo.find_commit(oid).unwrap();
+            git_utils::get_all_paths_cached(&commit.tree().unwrap(), "", repo, &mut cache)
+        };
+        let o_paths = paths_of(self.o);
+        let a_paths = paths_of(self.a);
+        let b_paths = paths_of(self.b);
+        let m_paths = paths_of(self.m);
+        let (o_paths, a_paths, b_paths) = if options.only_differing {
+            (
+                self.files_differing_from_m(repo, self.o, &o_paths),
+                self.files_differing_from_m(repo, self.a, &a_paths),
+                self.files_differing_from_m(repo, self.b, &b_paths),
+            )
+        } else {
+            (o_paths, a_paths, b_paths)
+        };
+
+        self.write_parts(
+            sink,
+            prefix,
+            [o_paths, a_paths, b_paths, m_paths],
+            repo,
+            options,
+        )
+    }
+
+    /// Creates the o, a, b, and m folders below `prefix` and writes the given files of each
+    /// part in them. With `WriteOptions::dedup_identical_parts`, o may instead be linked to or
+    /// left out in favour of another folder; the latter is returned.
+    fn write_parts(
+        &self,
+        sink: &mut dyn BlobSink,
+        prefix: &Path,
+        files: [HashSet<String>; 4],
+        repo: &git2::Repository,
+        options: WriteOptions,
+    ) -> Option<&'static str> {
+        let o_same_as = if !options.dedup_identical_parts {
+            None
+        } else if self.o == self.a {
+            Some("a")
+        } else if self.o == self.b {
+            Some("b")
+        } else {
+            None
+        };
+        let mut skipped = None;
+        let parts = [
+            ("o", self.o, "O"),
+            ("a", self.a, "A"),
+            ("b", self.b, "B"),
+            ("m", self.m, "M"),
         ];
-        for path in &paths {
-            std::fs::create_dir_all(path).expect("Could not create folder");
+        for ((folder, oid, description), files) in parts.into_iter().zip(files) {
+            let folder = prefix.join(folder);
+            if let (Some(target), "O") = (o_same_as, description) {
+                if let Err(e) = sink.symlink_dir(&folder, Path::new(target)) {
+                    warning!(
+                        "Could not link {:?} to {}, leaving it out: {}",
+                        folder,
+                        target,
+                        e
+                    );
+                    skipped = Some(target);
+                }
+                continue;
+            }
+            sink.create_dir(&folder).expect("Could not create folder");
+            git_utils::write_files_from_commit(
+                sink,
+                &folder,
+                oid,
+                repo,
+                &files,
+                description,
+                options.blob_options,
+            );
         }
+        skipped
+    }
 
-        // Create a list of all files for each version
-        let commit = repo.find_commit(self.o).unwrap();
-        let o_paths = git_utils::get_all_paths(&commit.tree().unwrap(), "", repo);
-        let commit = repo.find_commit(self.a).unwrap();
-        let a_paths = git_utils::get_all_paths(&commit.tree().unwrap(), "", repo);
-        let commit = repo.find_commit(self.b).unwrap();
-        let b_paths = git_utils::get_all_paths(&commit.tree().unwrap(), "", repo);
-        let commit = repo.find_commit(self.m).unwrap();
-        let m_paths = git_utils::get_all_paths(&commit.tree().unwrap(), "", repo);
-
-        git_utils::write_files_from_commit_to_disk(folder.join("o"), self.o, repo, &o_paths, "O");
-        git_utils::write_files_from_commit_to_disk(folder.join("a"), self.a, repo, &a_paths, "A");
-        git_utils::write_files_from_commit_to_disk(folder.join("b"), self.b, repo, &b_paths, "B");
-        git_utils::write_files_from_commit_to_disk(folder.join("m"), self.m, repo, &m_paths, "M");
+    /// Keeps those of the given files whose version in `part` is not the exact same blob as the
+    /// version in M. A file missing from either side counts as differing.
+    fn files_differing_from_m(
+        &self,
+        repo: &git2::Repository,
+        part: git2::Oid,
+        files: &HashSet<String>,
+    ) -> HashSet<String> {
+        let tree_of = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .and_then(|commit| commit.tree())
+                .expect("Failed to find tree for commit")
+        };
+        let part_tree = tree_of(part);
+        let m_tree = tree_of(self.m);
+        let blob_id = |tree: &git2::Tree, file: &str| {
+            tree.get_path(std::path::Path::new(file))
+                .ok()
+                .map(|entry| entry.id())
+        };
+        files
+            .iter()
+            .filter(|file| {
+                let part_blob = blob_id(&part_tree, file);
+                part_blob.is_none() || part_blob != blob_id(&m_tree, file)
+            })
+            .cloned()
+            .collect()
     }
 
     /// Returns epoch seconds for the merge commit of the ThreeWayMerge. Timezone information is
     /// discarded.
     pub fn time(&self, repo: &git2::Repository) -> i64 {
+        self.time_by(repo, TimeSource::Committer).seconds()
+    }
+
+    /// The committer or author time of the merge commit, along with the offset of the timezone it
+    /// was made in (see `git2::Time::offset_minutes`). For telling the local time of day.
+    pub fn time_by(&self, repo: &git2::Repository, source: TimeSource) -> git2::Time {
+        let commit = repo
+            .find_commit(self.m)
+            .expect("Failed to find merge commit");
+        match source {
+            TimeSource::Committer => commit.time(),
+            TimeSource::Author => commit.author().when(),
+        }
+    }
+
+    /// How long the merged work was under way, in seconds: from O to M, and from the later of A and
+    /// B to M. Commit times are used, so clock skew can make these negative. They are not clamped.
+    pub fn integration_lag(&self, repo: &git2::Repository) -> (i64, i64) {
+        let time = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .expect("Failed to find commit")
+                .time()
+                .seconds()
+        };
+        let m_time = time(self.m);
+        let tip_time = time(self.a).max(time(self.b));
+        (m_time - time(self.o), m_time - tip_time)
+    }
+
+    /// Email addresses of the authors of the commits on either branch (reachable from A or B, but
+    /// not from O), and of the author of M itself.
+    pub fn contributing_authors(&self, repo: &git2::Repository) -> HashSet<String> {
+        let email = |oid: git2::Oid| {
+            let commit = repo.find_commit(oid).expect("Failed to find commit");
+            let author = commit.author();
+            String::from_utf8_lossy(author.email_bytes()).into_owned()
+        };
+        let mut revwalk = repo.revwalk().expect("Failed to create revwalk");
+        revwalk.push(self.a).expect("Failed to push A");
+        revwalk.push(self.b).expect("Failed to push B");
+        revwalk.hide(self.o).expect("Failed to hide O");
+        let mut authors: HashSet<String> = revwalk
+            .map(|oid| email(oid.expect("Failed to walk the branches")))
+            .collect();
+        authors.insert(email(self.m));
+        authors
+    }
+
+    /// Number of commits only on A (reachable from A but not from B) and only on B. Unlike the
+    /// distance to O, commits that one branch took over from the other, e.g., by merging it in
+    /// halfway, are not counted for either.
+    pub fn branch_unique_counts(&self, repo: &git2::Repository) -> (usize, usize) {
+        let count = |include, exclude| {
+            git_utils::count_unique(repo, include, exclude).expect("Failed to walk the branches")
+        };
+        (count(self.a, self.b), count(self.b, self.a))
+    }
+
+    /// Number of merge commits on the branches, i.e., reachable from A or B but not from O. M
+    /// itself is not counted. 0 if both branches are straight lines of commits; higher values
+    /// mean the branches were themselves integrated piecemeal.
+    pub fn ancestor_merge_count(&self, repo: &git2::Repository) -> usize {
+        git_utils::count_merges(repo, &[self.a, self.b], self.o)
+            .expect("Failed to walk the branches")
+    }
+
+    /// Lines added and removed on each side: O→A and O→B, in that order. Tells how far each branch
+    /// diverged from the base.
+    pub fn side_stats(&self, repo: &git2::Repository) -> ((usize, usize), (usize, usize)) {
+        (
+            git_utils::line_stats(repo, &self.o, &self.a),
+            git_utils::line_stats(repo, &self.o, &self.b),
+        )
+    }
+
+    /// Number of diff hunks in O→M, see `git_utils::hunk_count`. A size proxy next to the number
+    /// of changed files, telling how dispersed the change is.
+    pub fn hunk_count(&self, repo: &git2::Repository) -> usize {
+        git_utils::hunk_count(repo, &self.o, &self.m)
+    }
+
+    /// Jaccard similarity of the lines changed in O→A and in O→B (see `git_utils::changed_lines`):
+    /// 1.0 if both branches made exactly the same edits, 0.0 if they share none. A high score hints
+    /// at duplicated effort. Also 0.0 if neither branch changed a line.
+    pub fn branch_change_similarity(&self, repo: &git2::Repository) -> f64 {
+        let a_lines = git_utils::changed_lines(repo, &self.o, &self.a);
+        let b_lines = git_utils::changed_lines(repo, &self.o, &self.b);
+        let union = a_lines.union(&b_lines).count();
+        if union == 0 {
+            return 0.0;
+        }
+        a_lines.intersection(&b_lines).count() as f64 / union as f64
+    }
+
+    /// Check whether one of the files changed from O to M has a `merge` attribute other than the
+    /// default, e.g., `merge=binary`, `merge=union`, `-merge`, or a custom driver. Git then did not
+    /// do a plain three way text merge for it.
+    ///
+    /// The attributes come from the `.gitattributes` of the working copy (and index), not from the
+    /// merge's own tree, as that is all git2 can check.
+    pub fn uses_merge_driver(&self, repo: &git2::Repository) -> bool {
+        self.files_to_consider(repo).iter().any(|file| {
+            let value = repo
+                .get_attr(Path::new(file), "merge", git2::AttrCheckFlags::default())
+                .ok()
+                .flatten();
+            match git2::AttrValue::from_string(value) {
+                git2::AttrValue::Unspecified | git2::AttrValue::True => false,
+                git2::AttrValue::String(driver) => driver != "text",
+                _ => true,
+            }
+        })
+    }
+
+    /// The number of parents of the merge commit, read from the commit itself. Only A and B are
+    /// kept in a ThreeWayMerge, but the merge commit may have had more.
+    pub fn parent_count(&self, repo: &git2::Repository) -> usize {
         repo.find_commit(self.m)
             .expect("Failed to find merge commit")
-            .time()
-            .seconds()
+            .parent_count()
+    }
+
+    /// The number of the pull or merge request M merged, going by its message:
+    ///
+    /// * GitHub: `Merge pull request #123 from user/branch`, or `Title (#123)` for squashes
+    /// * GitLab: `See merge request group/project!123` in the body
+    /// * Bitbucket: `Merged in branch (pull request #123)`
+    ///
+    /// None if the message mentions no request, e.g., for a local `git merge`.
+    pub fn pull_request_number(&self, repo: &git2::Repository) -> Option<u32> {
+        lazy_static! {
+            /// Most specific first, so a summary referencing an issue as (#N) does not win over
+            /// the request itself.
+            static ref PR_MATCHERS: Vec<regex::Regex> = vec![
+                regex::Regex::new(r"Merge pull request #(\d+)").unwrap(),
+                regex::Regex::new(r"See merge request \S*!(\d+)").unwrap(),
+                regex::Regex::new(r"\(pull request #(\d+)\)").unwrap(),
+                regex::Regex::new(r"\(#(\d+)\)").unwrap(),
+            ];
+        }
+        let commit = repo.find_commit(self.m).ok()?;
+        let message = String::from_utf8_lossy(commit.message_bytes());
+        PR_MATCHERS.iter().find_map(|matcher| {
+            matcher
+                .captures(&message)
+                .and_then(|captures| captures[1].parse().ok())
+        })
+    }
+
+    /// Whether the merge commit carries a (GPG or SSH) signature. The signature is not verified.
+    pub fn is_signed(&self, repo: &git2::Repository) -> bool {
+        repo.extract_signature(&self.m, None).is_ok()
+    }
+
+    /// Swaps A and B if needed so A is the side with the smaller change, measured in lines added
+    /// plus lines removed since O. Sets `a_b_swapped` when they were swapped.
+    pub fn order_by_change_size(&mut self, repo: &git2::Repository) {
+        let a_size = git_utils::changed_line_count(repo, &self.o, &self.a);
+        let b_size = git_utils::changed_line_count(repo, &self.o, &self.b);
+        if b_size < a_size {
+            std::mem::swap(&mut self.a, &mut self.b);
+            self.a_b_swapped = !self.a_b_swapped;
+        }
     }
 
     /// Check whether O is a different commit than A or B. If it is the same as either, then we're
@@ -208,6 +1412,169 @@ impl ThreeWayMerge {
         self.o != self.a && self.o != self.b
     }
 
+    /// `has_distinct_o` or `both_branches_nonempty`, depending on `mode`.
+    pub fn has_distinct_o_by(&self, repo: &git2::Repository, mode: DistinctOMode) -> bool {
+        match mode {
+            DistinctOMode::Oid => self.has_distinct_o(),
+            DistinctOMode::Tree => self.both_branches_nonempty(repo),
+        }
+    }
+
+    /// Check whether M's tree differs from both A's and B's tree. If it equals either, one side was
+    /// taken as is and there was no real resolution. Only compares tree Oids, so this is cheap.
+    pub fn m_differs_from_parents(&self, repo: &git2::Repository) -> bool {
+        let tree_id = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .expect("Failed to find commit")
+                .tree_id()
+        };
+        let m_tree = tree_id(self.m);
+        m_tree != tree_id(self.a) && m_tree != tree_id(self.b)
+    }
+
+    /// Whether both O→A and O→B change at least one file, i.e., neither A nor B has the same tree
+    /// as O. Stricter than `has_distinct_o`: a branch of commits that changed nothing, or whose
+    /// changes were reverted again, counts as empty here.
+    pub fn both_branches_nonempty(&self, repo: &git2::Repository) -> bool {
+        let tree_id = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .expect("Failed to find commit")
+                .tree_id()
+        };
+        let o_tree = tree_id(self.o);
+        tree_id(self.a) != o_tree && tree_id(self.b) != o_tree
+    }
+
+    /// Check whether M's tree equals A's (the first parent's) tree, i.e., merging B did not change
+    /// anything. Happens for instance when merging a branch that was already merged before. Only
+    /// compares tree Oids, so this is cheap.
+    pub fn is_empty_merge(&self, repo: &git2::Repository) -> bool {
+        let tree_id = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .expect("Failed to find commit")
+                .tree_id()
+        };
+        tree_id(self.m) == tree_id(self.a)
+    }
+
+    /// Check whether M has a file that is not in O, or in neither A nor B, depending on `base`.
+    /// Compares the full lists of paths, so this is slower than looking at a diff.
+    pub fn introduces_new_files(&self, repo: &git2::Repository, base: NewFilesBase) -> bool {
+        let mut cache = HashMap::new();
+        let mut paths_of = |oid: git2::Oid| {
+            let commit = repo.find_commit(oid).unwrap();
+            git_utils::get_all_paths_cached(&commit.tree().unwrap(), "", repo, &mut cache)
+        };
+        let m_paths = paths_of(self.m);
+        match base {
+            NewFilesBase::O => {
+                let o_paths = paths_of(self.o);
+                m_paths.iter().any(|path| !o_paths.contains(path))
+            }
+            NewFilesBase::Parents => {
+                let a_paths = paths_of(self.a);
+                let b_paths = paths_of(self.b);
+                m_paths
+                    .iter()
+                    .any(|path| !a_paths.contains(path) && !b_paths.contains(path))
+            }
+        }
+    }
+
+    /// Check whether one of the files changed from O to M is written in one of the given
+    /// languages. The language is detected on the content of the file in M (or in O if the file
+    /// was removed), which is slower than only looking at extensions.
+    pub fn changes_language(&self, repo: &git2::Repository, languages: &[String]) -> bool {
+        let o_tree = repo
+            .find_commit(self.o)
+            .and_then(|commit| commit.tree())
+            .expect("Failed to find tree for commit O");
+        let m_tree = repo
+            .find_commit(self.m)
+            .and_then(|commit| commit.tree())
+            .expect("Failed to find tree for commit M");
+        self.files_to_consider(repo).iter().any(|file| {
+            let path = std::path::Path::new(file);
+            let blob = m_tree
+                .get_path(path)
+                .or_else(|_| o_tree.get_path(path))
+                .and_then(|entry| repo.find_blob(entry.id()));
+            match blob {
+                Ok(blob) => crate::language::detect(file, blob.content())
+                    .map(|language| languages.iter().any(|wanted| wanted == language))
+                    .unwrap_or(false),
+                Err(_) => false,
+            }
+        })
+    }
+
+    /// Share of the files changed from O to M that are tests: their path contains "test" or
+    /// "spec" (in any case), or matches one of `patterns`. 0.0 if no file changed.
+    pub fn test_file_ratio(&self, repo: &git2::Repository, patterns: &[glob::Pattern]) -> f64 {
+        let files = self.files_to_consider(repo);
+        if files.is_empty() {
+            return 0.0;
+        }
+        let tests = files
+            .iter()
+            .filter(|file| {
+                let lowercase = file.to_lowercase();
+                lowercase.contains("test")
+                    || lowercase.contains("spec")
+                    || patterns.iter().any(|pattern| pattern.matches(file))
+            })
+            .count();
+        tests as f64 / files.len() as f64
+    }
+
+    /// Check whether a file changed from O to M is a build or dependency configuration file, i.e.,
+    /// matches one of `patterns` (see `build_file_patterns` for the usual ones). Such merges are
+    /// where dependency conflicts show up.
+    pub fn touches_build_files(&self, repo: &git2::Repository, patterns: &[glob::Pattern]) -> bool {
+        self.files_to_consider(repo)
+            .iter()
+            .any(|file| patterns.iter().any(|pattern| pattern.matches(file)))
+    }
+
+    /// Which branch changed `path`, if only one of them did: A if it changed in O→A but not in
+    /// O→B, B the other way around. None if both or neither changed it, i.e., the merge did not
+    /// cleanly take over the file from one side.
+    pub fn only_one_side_changed(&self, repo: &git2::Repository, path: &str) -> Option<Side> {
+        let on_a = git_utils::changed_filenames(repo, &self.o, &self.a).contains(path);
+        let on_b = git_utils::changed_filenames(repo, &self.o, &self.b).contains(path);
+        match (on_a, on_b) {
+            (true, false) => Some(Side::A),
+            (false, true) => Some(Side::B),
+            _ => None,
+        }
+    }
+
+    /// The extensions (without the dot) of the files changed from O to M, sorted and without
+    /// duplicates. Files without an extension are left out.
+    pub fn changed_extensions(&self, repo: &git2::Repository) -> Vec<String> {
+        let extensions: std::collections::BTreeSet<_> = self
+            .files_to_consider(repo)
+            .iter()
+            .filter_map(|file| Path::new(file).extension()?.to_str().map(str::to_owned))
+            .collect();
+        extensions.into_iter().collect()
+    }
+
+    /// Check whether one of the given paths is among the files changed from O to M.
+    pub fn changes_one_of(&self, repo: &git2::Repository, paths: &HashSet<String>) -> bool {
+        !self.files_to_consider(repo).is_disjoint(paths)
+    }
+
+    /// Check whether every file changed from O to M matches one of the patterns. False if nothing
+    /// changed at all.
+    pub fn only_changes_paths(&self, repo: &git2::Repository, patterns: &[glob::Pattern]) -> bool {
+        let files = self.files_to_consider(repo);
+        !files.is_empty()
+            && files
+                .iter()
+                .all(|file| patterns.iter().any(|pattern| pattern.matches(file)))
+    }
+
     pub fn a_b_change_same_file(&self, repo: &git2::Repository, only_extensions: &[&str]) -> bool {
         crate::git_utils::changed_same_file(
             repo,
@@ -219,6 +1586,305 @@ impl ThreeWayMerge {
         )
     }
 
+    /// Collects the details of this merge into a `MergeRecord`.
+    pub fn to_record(&self, repo: &git2::Repository) -> MergeRecord {
+        let changed_files = self.files_to_consider_sorted(repo);
+        MergeRecord {
+            o: self.o.to_string(),
+            a: self.a.to_string(),
+            b: self.b.to_string(),
+            m: self.m.to_string(),
+            timestamp: self.time(repo),
+            changed_file_tree: FileTree::from_paths(changed_files.iter().map(String::as_str)),
+            changed_files,
+            distinct_o: self.has_distinct_o(),
+            a_b_swapped: self.a_b_swapped,
+            o_same_as: None,
+        }
+    }
+
+    /// Writes the `MergeRecord` of this merge as `merge.json` in the given folder. With
+    /// `max_file_bytes`, larger files are left out of its changed files, see `files_within_size`.
+    /// `o_same_as` is what writing the files returned, see `write_files_to_disk`.
+    pub fn write_record_to_disk<P: AsRef<std::path::Path>>(
+        &self,
+        folder: P,
+        repo: &git2::Repository,
+        path_style: PathStyle,
+        max_file_bytes: Option<usize>,
+        o_same_as: Option<&str>,
+    ) {
+        let mut sink = crate::sink::FolderSink::new(folder);
+        self.write_record(
+            &mut sink,
+            Path::new(""),
+            repo,
+            path_style,
+            max_file_bytes,
+            o_same_as,
+        );
+    }
+
+    /// Like `write_record_to_disk`, but writes `merge.json` below `prefix` in any sink.
+    pub fn write_record(
+        &self,
+        sink: &mut dyn BlobSink,
+        prefix: &Path,
+        repo: &git2::Repository,
+        path_style: PathStyle,
+        max_file_bytes: Option<usize>,
+        o_same_as: Option<&str>,
+    ) {
+        let path = prefix.join("merge.json");
+        let mut record = self.to_record(repo);
+        record.o_same_as = o_same_as.map(str::to_owned);
+        if let Some(max_bytes) = max_file_bytes {
+            let changed_files = record.changed_files.iter().cloned().collect();
+            let kept = self.files_within_size(repo, changed_files, max_bytes);
+            record.changed_files.retain(|file| kept.contains(file));
+            record.changed_file_tree =
+                FileTree::from_paths(record.changed_files.iter().map(String::as_str));
+        }
+        for changed_file in record.changed_files.iter_mut() {
+            *changed_file = path_style.apply(changed_file);
+        }
+        let json = serde_json::to_vec_pretty(&record)
+            .unwrap_or_else(|err| panic!("Failed to serialise {:?}: {}", &path, err));
+        sink.put(&path, &json, false)
+            .unwrap_or_else(|err| panic!("Failed to write {:?}: {}", &path, err));
+    }
+
+    /// Merges A and B again with O as base and writes every file that conflicts below
+    /// `prefix/conflicts`: the version in O as `path.base`, in A as `path.ours`, in B as
+    /// `path.theirs`, and the resolution in M as `path.resolved`. A version is left out if the file
+    /// does not exist on that side, e.g., in a modify/delete conflict. Files that merge cleanly are
+    /// not written at all.
+    pub fn write_conflicts(&self, sink: &mut dyn BlobSink, prefix: &Path, repo: &git2::Repository) {
+        let tree_of = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .and_then(|commit| commit.tree())
+                .expect("Failed to find tree for commit")
+        };
+        let index = repo
+            .merge_trees(&tree_of(self.o), &tree_of(self.a), &tree_of(self.b), None)
+            .expect("Should be able to merge A and B");
+        let m_tree = tree_of(self.m);
+        let folder = prefix.join("conflicts");
+        let executable_mode = i32::from(git2::FileMode::BlobExecutable);
+        let mut put = |path: &str, suffix: &str, oid: git2::Oid, mode: i32| {
+            let blob = repo.find_blob(oid).expect("Failed to find blob");
+            let target = folder.join(format!("{}.{}", path, suffix));
+            let executable = mode == executable_mode;
+            sink.put(&target, blob.content(), executable)
+                .unwrap_or_else(|err| panic!("Failed to write {:?}: {}", &target, err));
+        };
+        for conflict in index.conflicts().expect("Failed to read conflicts") {
+            let conflict = conflict.expect("Failed to read conflict");
+            let sides = [
+                (conflict.ancestor, "base"),
+                (conflict.our, "ours"),
+                (conflict.their, "theirs"),
+            ];
+            let Some(path) = sides
+                .iter()
+                .find_map(|(entry, _)| entry.as_ref())
+                .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+            else {
+                continue;
+            };
+            for (entry, suffix) in &sides {
+                if let Some(entry) = entry {
+                    put(&path, suffix, entry.id, entry.mode as i32);
+                }
+            }
+            if let Ok(resolved) = m_tree.get_path(Path::new(&path)) {
+                put(&path, "resolved", resolved.id(), resolved.filemode());
+            }
+        }
+    }
+
+    /// Classifies how M resolved each file that conflicts when merging A and B again with O as
+    /// base, see `Resolution`. Sorted by path.
+    pub fn conflict_resolutions(&self, repo: &git2::Repository) -> Vec<(String, Resolution)> {
+        let tree_of = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .and_then(|commit| commit.tree())
+                .expect("Failed to find tree for commit")
+        };
+        let index = repo
+            .merge_trees(&tree_of(self.o), &tree_of(self.a), &tree_of(self.b), None)
+            .expect("Should be able to merge A and B");
+        let union = self.naive_merge(repo);
+        let m_tree = tree_of(self.m);
+        let mut resolutions = vec![];
+        for conflict in index.conflicts().expect("Failed to read conflicts") {
+            let conflict = conflict.expect("Failed to read conflict");
+            let Some(path) = [&conflict.ancestor, &conflict.our, &conflict.their]
+                .into_iter()
+                .find_map(|entry| entry.as_ref())
+                .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+            else {
+                continue;
+            };
+            // None stands for a file deleted on that side, or in M.
+            let resolved = m_tree
+                .get_path(Path::new(&path))
+                .ok()
+                .map(|entry| entry.id());
+            let ours = conflict.our.as_ref().map(|entry| entry.id);
+            let theirs = conflict.their.as_ref().map(|entry| entry.id);
+            let both = union.get_path(Path::new(&path), 0).map(|entry| entry.id);
+            let resolution = if resolved == ours {
+                Resolution::Ours
+            } else if resolved == theirs {
+                Resolution::Theirs
+            } else if resolved.is_some() && resolved == both {
+                Resolution::Both
+            } else {
+                Resolution::Custom
+            };
+            resolutions.push((path, resolution));
+        }
+        resolutions.sort();
+        resolutions
+    }
+
+    /// How many of the conflicting files M resolved in each way, see `conflict_resolutions`.
+    pub fn resolution_counts(&self, repo: &git2::Repository) -> ResolutionCounts {
+        let mut counts = ResolutionCounts::default();
+        for (_path, resolution) in self.conflict_resolutions(repo) {
+            match resolution {
+                Resolution::Ours => counts.ours += 1,
+                Resolution::Theirs => counts.theirs += 1,
+                Resolution::Both => counts.both += 1,
+                Resolution::Custom => counts.custom += 1,
+            }
+        }
+        counts
+    }
+
+    /// Quantifies the manual work that went into the merge. A and B are merged textually with O
+    /// as base, naively keeping both sides of every conflicting hunk. The result is the number of
+    /// lines added or removed to get from that naive merge to M.
+    pub fn resolution_churn(&self, repo: &git2::Repository) -> usize {
+        let naive_merge = self.naive_merge(repo);
+        self.churn_from(repo, &naive_merge, false)
+    }
+
+    /// Check whether the resolution (see `resolution_churn`) only changed whitespace: the naive
+    /// merge and M differ, but not when whitespace is ignored. Think of formatting wars.
+    pub fn only_whitespace_resolution(&self, repo: &git2::Repository) -> bool {
+        let naive_merge = self.naive_merge(repo);
+        self.churn_from(repo, &naive_merge, false) > 0
+            && self.churn_from(repo, &naive_merge, true) == 0
+    }
+
+    /// Whether M brings in content of its own, lines neither branch wrote: an "evil merge".
+    ///
+    /// The heuristic compares M to the naive merge, A and B merged textually with both sides of
+    /// every conflicting hunk kept (see `naive_merge`). Resolving a conflict from there means
+    /// dropping lines, or keeping, moving, and reindenting lines of A and B. So only the lines M
+    /// has but the naive merge lacks are looked at. If one of them is found in neither A's nor
+    /// B's version of its file, the merge is evil. Lines are compared without leading and
+    /// trailing whitespace, and blank lines are ignored. Files only M has count in full.
+    ///
+    /// Removed content is not considered, nor are binary files. A line moved over from another
+    /// file, or a single edited line within a conflict, does count as new.
+    pub fn is_evil_merge(&self, repo: &git2::Repository) -> bool {
+        let tree_of = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .and_then(|commit| commit.tree())
+                .expect("Failed to find tree for commit")
+        };
+        let naive_merge = self.naive_merge(repo);
+        let sides = [tree_of(self.a), tree_of(self.b)];
+        // M is the old side, so its own lines show up as deletions.
+        let diff = repo
+            .diff_tree_to_index(Some(&tree_of(self.m)), Some(&naive_merge), None)
+            .expect("Should be able to diff M to the naive merge");
+        let mut side_lines: HashMap<std::path::PathBuf, HashSet<Vec<u8>>> = HashMap::new();
+        let mut evil = false;
+        diff.foreach(
+            &mut |_, _| true,
+            None,
+            None,
+            Some(&mut |delta, _, line| {
+                // Returning false would make the foreach fail, so skip the rest once found.
+                if evil || line.origin_value() != git2::DiffLineType::Deletion {
+                    return true;
+                }
+                let content = line.content().trim_ascii();
+                let Some(path) = delta.old_file().path() else {
+                    return true;
+                };
+                if content.is_empty() {
+                    return true;
+                }
+                let lines = side_lines.entry(path.to_path_buf()).or_insert_with(|| {
+                    sides
+                        .iter()
+                        .filter_map(|tree| tree.get_path(path).ok())
+                        .filter_map(|entry| repo.find_blob(entry.id()).ok())
+                        .flat_map(|blob| {
+                            blob.content()
+                                .split(|byte| *byte == b'\n')
+                                .map(|line| line.trim_ascii().to_vec())
+                                .collect::<Vec<_>>()
+                        })
+                        .collect()
+                });
+                evil = !lines.contains(content);
+                true
+            }),
+        )
+        .expect("diff.foreach went oopsy");
+        evil
+    }
+
+    /// Merges A and B textually with O as base, keeping both sides of every conflicting hunk.
+    fn naive_merge(&self, repo: &git2::Repository) -> git2::Index {
+        let tree_of = |oid: git2::Oid| {
+            repo.find_commit(oid)
+                .and_then(|commit| commit.tree())
+                .expect("Failed to find tree for commit")
+        };
+        let mut mergeoptions = git2::MergeOptions::new();
+        mergeoptions.file_favor(git2::FileFavor::Union);
+        repo.merge_trees(
+            &tree_of(self.o),
+            &tree_of(self.a),
+            &tree_of(self.b),
+            Some(&mergeoptions),
+        )
+        .expect("Should be able to merge A and B")
+    }
+
+    /// Lines added or removed to get from the naive merge to M.
+    fn churn_from(
+        &self,
+        repo: &git2::Repository,
+        naive_merge: &git2::Index,
+        ignore_whitespace: bool,
+    ) -> usize {
+        let m_tree = repo
+            .find_commit(self.m)
+            .and_then(|commit| commit.tree())
+            .expect("Failed to find tree for commit M");
+        let mut diffoptions = git2::DiffOptions::new();
+        diffoptions
+            .minimal(true)
+            .ignore_whitespace(ignore_whitespace);
+        let diff = repo
+            .diff_tree_to_index(Some(&m_tree), Some(naive_merge), Some(&mut diffoptions))
+            .expect("Should be able to diff M to the naive merge");
+        (0..diff.deltas().len())
+            // Conflicts that even a union merge cannot solve (e.g., modify/delete) have no patch.
+            .filter_map(|idx| git2::Patch::from_diff(&diff, idx).ok().flatten())
+            .filter_map(|patch| patch.line_stats().ok())
+            .map(|(_context, additions, deletions)| additions + deletions)
+            .sum()
+    }
+
     /// Returns a list of files that were changed in O→A AND in O→B
     pub fn files_changed_in_both_branches(&self, repo: &git2::Repository) -> HashSet<String> {
         let o_to_a = git_utils::changed_filenames(repo, &self.o, &self.a);
@@ -228,4 +1894,22 @@ impl ThreeWayMerge {
             .map(|filename| filename.to_owned())
             .collect()
     }
+
+    /// As `files_changed_in_both_branches`, but only counting the non-merge commits on the
+    /// first-parent chains from O to A and from O to B, see
+    /// `git_utils::changed_filenames_ignoring_merges`. Changes a branch pulled in through merges
+    /// of its own do not count. Costs one diff per commit on either branch.
+    pub fn files_changed_in_both_branches_ignoring_merges(
+        &self,
+        repo: &git2::Repository,
+    ) -> HashSet<String> {
+        let o_to_a = git_utils::changed_filenames_ignoring_merges(repo, &self.o, &self.a)
+            .expect("Should be able to walk O..A");
+        let o_to_b = git_utils::changed_filenames_ignoring_merges(repo, &self.o, &self.b)
+            .expect("Should be able to walk O..B");
+        o_to_a
+            .intersection(&o_to_b)
+            .map(|filename| filename.to_owned())
+            .collect()
+    }
 }
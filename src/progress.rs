@@ -0,0 +1,60 @@
+//! Progress reporting on STDERR for long runs, so it does not mix with the CSV on STDOUT.
+
+use std::time::{Duration, Instant};
+
+/// How often the count is updated at most.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counts processed items, out of a total if it is known, and reports the count now and then.
+pub struct Progress {
+    what: &'static str,
+    total: Option<usize>,
+    done: usize,
+    enabled: bool,
+    last_report: Option<Instant>,
+}
+
+impl Progress {
+    /// A disabled Progress counts, but never prints anything.
+    pub fn new(what: &'static str, total: Option<usize>, enabled: bool) -> Self {
+        Self {
+            what,
+            total,
+            done: 0,
+            enabled,
+            last_report: None,
+        }
+    }
+
+    /// Marks one more item as processed.
+    pub fn tick(&mut self) {
+        self.done += 1;
+        let due = self
+            .last_report
+            .is_none_or(|last_report| last_report.elapsed() >= INTERVAL);
+        if due || Some(self.done) == self.total {
+            self.report();
+        }
+    }
+
+    /// Prints the final count and ends the line.
+    pub fn finish(&self) {
+        if self.enabled {
+            eprintln!("\r{}", self.message());
+        }
+    }
+
+    fn report(&mut self) {
+        if self.enabled {
+            eprint!("\r{}", self.message());
+            self.last_report = Some(Instant::now());
+        }
+    }
+
+    fn message(&self) -> String {
+        match self.total {
+            Some(total) => format!("Processed {} / {} {}", self.done, total, self.what),
+            None => format!("Processed {} {}", self.done, self.what),
+        }
+    }
+}
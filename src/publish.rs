@@ -2,178 +2,1147 @@
 
 use crate::git_utils;
 use crate::merge;
-use std::collections::HashSet;
+use crate::progress::Progress;
+use crate::sink::{BlobSink, FolderSink, TarSink};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 
-pub fn print_csv_of_merges(
+/// Version of the CSV layout written by `write_csv_of_merges`, see `CsvOptions::schema_header`.
+/// Bump this whenever the columns written for a given set of options change: a column is removed,
+/// renamed, or reordered, or one is added that was not opt-in.
+///
+/// Version 1: O,A,B,M,changed_files,timestamp, followed by the optional columns in the order of
+/// the fields of `CsvOptions`.
+pub const CSV_SCHEMA_VERSION: u32 = 1;
+
+/// Options for `write_csv_of_merges`. Most add a column, appended in the order of the fields.
+#[derive(Default)]
+pub struct CsvOptions {
+    /// Swap A and B where needed so A is the side with the smaller change. Adds an a_b_swapped
+    /// column. See `ThreeWayMerge::order_by_change_size`.
+    pub order_by_change_size: bool,
+    /// See `ThreeWayMerge::resolution_churn`
+    pub churn: bool,
+    /// How M resolved the conflicting files, see `ThreeWayMerge::resolution_counts`. Adds four
+    /// columns.
+    pub resolutions: bool,
+    /// See `ThreeWayMerge::is_signed`
+    pub signed: bool,
+    /// See `ThreeWayMerge::parent_count`
+    pub parent_count: bool,
+    /// Whole days between the first commit of the repository and the merge, at its `time_source`
+    pub relative_time: bool,
+    /// Number of files changed in both O→A and O→B, see
+    /// `ThreeWayMerge::files_changed_in_both_branches`. Costs two extra diffs per merge.
+    pub both_branch_count: bool,
+    /// Seconds from O to M and from the later of A and B to M, see
+    /// `ThreeWayMerge::integration_lag`. Adds two columns.
+    pub lag: bool,
+    /// Commits only on A and only on B, see `ThreeWayMerge::branch_unique_counts`. Adds two
+    /// columns.
+    pub unique_counts: bool,
+    /// Number of distinct authors on the branches and of M, see
+    /// `ThreeWayMerge::contributing_authors`
+    pub author_count: bool,
+    /// Whether O equals A or B, i.e., the negation of `ThreeWayMerge::has_distinct_o_by` with
+    /// `distinct_o_mode`
+    pub trivial: bool,
+    /// How the trivial column compares O with A and B. Does not add a column.
+    pub distinct_o_mode: merge::DistinctOMode,
+    /// Lines added and removed in O→A and in O→B, see `ThreeWayMerge::side_stats`. Adds four
+    /// columns.
+    pub side_stats: bool,
+    /// Number of diff hunks from O to M, see `ThreeWayMerge::hunk_count`
+    pub hunks: bool,
+    /// Whether a changed file has a custom merge attribute, see
+    /// `ThreeWayMerge::uses_merge_driver`
+    pub merge_driver: bool,
+    /// How alike the O→A and O→B changes are, see `ThreeWayMerge::branch_change_similarity`
+    pub similarity: bool,
+    /// Unambiguous short hashes of O, A, B, and M, see `git_utils::short_sha`. Adds four columns.
+    pub short_sha: bool,
+    /// If given, the share of changed files that are tests, with these patterns on top of the
+    /// usual names, see `ThreeWayMerge::test_file_ratio`
+    pub test_ratio: Option<Vec<glob::Pattern>>,
+    /// The pull or merge request the merge came from, see `ThreeWayMerge::pull_request_number`.
+    /// Empty if there is none.
+    pub pull_request: bool,
+    /// Number of merges on the branches, see `ThreeWayMerge::ancestor_merge_count`
+    pub nested_merges: bool,
+    /// Which time of M the timestamp and relative_days columns hold, see `ThreeWayMerge::time_by`.
+    /// Does not add a column.
+    pub time_source: merge::TimeSource,
+    /// Offset in minutes from UTC of the timezone the timestamp was recorded in, e.g., 120 for
+    /// +0200
+    pub timezone: bool,
+    /// If given, only write this page of the merges that pass the filter, newest first. See
+    /// `merge::page_newest_first`.
+    pub page: Option<merge::Page>,
+    /// Leave out the column header (and schema line), e.g., when appending to an earlier CSV
+    pub skip_header: bool,
+    /// Start with a `# schema=N` line before the column header, N being `CSV_SCHEMA_VERSION`.
+    pub schema_header: bool,
+    /// If given, add a `# run_meta={...}` line (the `RunMeta` as JSON) before the column header,
+    /// after the schema line if any
+    pub run_meta: Option<RunMeta>,
+}
+
+/// Writes a CSV of the merges that pass the filter. One line per merge, starting with the
+/// O,A,B,M commits, followed by the number of changed files, the time of the merge, and the
+/// requested extra options. `out` can be anything from `std::io::stdout().lock()` to a file or a
+/// `Vec<u8>`.
+pub fn write_csv_of_merges(
+    out: &mut dyn Write,
     repo: &git2::Repository,
     revwalk: git2::Revwalk,
     before: Option<i64>,
-    distinct_o: bool,
-    should_touch_same_file: bool,
-) {
-    let merges = merge::find_merges(repo, revwalk, before);
-    println!("O,A,B,M,changed_files,timestamp");
-    let java_extensions = vec![".java"];
-    for merge in merges {
-        if distinct_o && !merge.has_distinct_o() {
-            continue;
+    base_cache: Option<&mut merge::MergeBaseCache>,
+    filter: &merge::MergeFilter,
+    options: &CsvOptions,
+) -> std::io::Result<()> {
+    let merges = find_merges_reporting(repo, revwalk, before, base_cache);
+    let first_commit_time = if options.relative_time {
+        Some(git_utils::first_commit_time(repo).expect("Could not find the first commit"))
+    } else {
+        None
+    };
+    let mut header = String::from("O,A,B,M,changed_files,timestamp");
+    if options.order_by_change_size {
+        header.push_str(",a_b_swapped");
+    }
+    if options.churn {
+        header.push_str(",churn");
+    }
+    if options.resolutions {
+        header.push_str(",took_ours,took_theirs,took_both,custom_resolution");
+    }
+    if options.signed {
+        header.push_str(",signed");
+    }
+    if options.parent_count {
+        header.push_str(",parents");
+    }
+    if options.relative_time {
+        header.push_str(",relative_days");
+    }
+    if options.both_branch_count {
+        header.push_str(",both_branch_files");
+    }
+    if options.lag {
+        header.push_str(",lag_from_o,lag_from_tip");
+    }
+    if options.unique_counts {
+        header.push_str(",a_unique,b_unique");
+    }
+    if options.author_count {
+        header.push_str(",author_count");
+    }
+    if options.trivial {
+        header.push_str(",trivial");
+    }
+    if options.side_stats {
+        header.push_str(",a_ins,a_del,b_ins,b_del");
+    }
+    if options.hunks {
+        header.push_str(",hunks");
+    }
+    if options.merge_driver {
+        header.push_str(",merge_driver");
+    }
+    if options.similarity {
+        header.push_str(",similarity");
+    }
+    if options.short_sha {
+        header.push_str(",o_short,a_short,b_short,m_short");
+    }
+    if options.test_ratio.is_some() {
+        header.push_str(",test_ratio");
+    }
+    if options.pull_request {
+        header.push_str(",pr");
+    }
+    if options.nested_merges {
+        header.push_str(",nested_merges");
+    }
+    if options.timezone {
+        header.push_str(",tz_offset");
+    }
+    if !options.skip_header {
+        if options.schema_header {
+            writeln!(out, "# schema={}", CSV_SCHEMA_VERSION)?;
         }
-        if should_touch_same_file && !merge.a_b_change_same_file(repo, &java_extensions) {
-            continue;
+        if let Some(run_meta) = &options.run_meta {
+            let json = serde_json::to_string(run_meta).map_err(std::io::Error::other)?;
+            writeln!(out, "# run_meta={}", json)?;
         }
-        let file_count = merge.files_to_consider(repo).len();
-        println!(
-            "{},{},{}",
-            merge.to_csv_line(),
-            file_count,
-            merge.time(repo)
-        );
+        writeln!(out, "{}", header)?;
+    }
+    let mut file_counts = HashMap::new();
+    let merges = merges.into_iter().filter(|merge| {
+        let file_count = filter.keep_counting_files(merge, repo);
+        if let Some(file_count) = file_count {
+            file_counts.insert(merge.m, file_count);
+        }
+        file_count.is_some()
+    });
+    let merges: Vec<_> = match options.page {
+        Some(page) => merge::page_newest_first(repo, merges, page),
+        None => merges.collect(),
+    };
+    for mut merge in merges {
+        if options.order_by_change_size {
+            merge.order_by_change_size(repo);
+        }
+        let file_count = file_counts[&merge.m];
+        let time = merge.time_by(repo, options.time_source);
+        let mut line = format!("{},{},{}", merge.to_csv_line(), file_count, time.seconds());
+        if options.order_by_change_size {
+            line.push_str(&format!(",{}", merge.a_b_swapped));
+        }
+        if options.churn {
+            line.push_str(&format!(",{}", merge.resolution_churn(repo)));
+        }
+        if options.resolutions {
+            let counts = merge.resolution_counts(repo);
+            line.push_str(&format!(
+                ",{},{},{},{}",
+                counts.ours, counts.theirs, counts.both, counts.custom
+            ));
+        }
+        if options.signed {
+            line.push_str(&format!(",{}", merge.is_signed(repo)));
+        }
+        if options.parent_count {
+            line.push_str(&format!(",{}", merge.parent_count(repo)));
+        }
+        if let Some(first_commit_time) = first_commit_time {
+            let days = (time.seconds() - first_commit_time).div_euclid(24 * 60 * 60);
+            line.push_str(&format!(",{}", days));
+        }
+        if options.both_branch_count {
+            let count = merge.files_changed_in_both_branches(repo).len();
+            line.push_str(&format!(",{}", count));
+        }
+        if options.lag {
+            let (from_o, from_tip) = merge.integration_lag(repo);
+            line.push_str(&format!(",{},{}", from_o, from_tip));
+        }
+        if options.unique_counts {
+            let (a_unique, b_unique) = merge.branch_unique_counts(repo);
+            line.push_str(&format!(",{},{}", a_unique, b_unique));
+        }
+        if options.author_count {
+            line.push_str(&format!(",{}", merge.contributing_authors(repo).len()));
+        }
+        if options.trivial {
+            line.push_str(&format!(
+                ",{}",
+                !merge.has_distinct_o_by(repo, options.distinct_o_mode)
+            ));
+        }
+        if options.side_stats {
+            let ((a_ins, a_del), (b_ins, b_del)) = merge.side_stats(repo);
+            line.push_str(&format!(",{},{},{},{}", a_ins, a_del, b_ins, b_del));
+        }
+        if options.hunks {
+            line.push_str(&format!(",{}", merge.hunk_count(repo)));
+        }
+        if options.merge_driver {
+            line.push_str(&format!(",{}", merge.uses_merge_driver(repo)));
+        }
+        if options.similarity {
+            line.push_str(&format!(",{:.3}", merge.branch_change_similarity(repo)));
+        }
+        if options.short_sha {
+            for oid in [merge.o, merge.a, merge.b, merge.m] {
+                line.push_str(&format!(",{}", git_utils::short_sha(repo, oid)));
+            }
+        }
+        if let Some(patterns) = &options.test_ratio {
+            line.push_str(&format!(",{:.3}", merge.test_file_ratio(repo, patterns)));
+        }
+        if options.pull_request {
+            let pr = merge.pull_request_number(repo);
+            line.push_str(&format!(
+                ",{}",
+                pr.map(|pr| pr.to_string()).unwrap_or_default()
+            ));
+        }
+        if options.nested_merges {
+            line.push_str(&format!(",{}", merge.ancestor_merge_count(repo)));
+        }
+        if options.timezone {
+            line.push_str(&format!(",{}", time.offset_minutes()));
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Finds the merges, then reports on STDERR how many were examined and how many of them had no or
+/// several merge bases, rather than warning about each one. See `merge::MergeStats`. Nothing is
+/// reported when all merges had a single merge base.
+fn find_merges_reporting(
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    before: Option<i64>,
+    base_cache: Option<&mut merge::MergeBaseCache>,
+) -> Vec<merge::ThreeWayMerge> {
+    let (merges, stats) = merge::find_merges_with_stats(repo, revwalk, before, base_cache);
+    if stats.no_base > 0 || stats.multiple_bases > 0 {
+        warning!("{}", stats);
     }
+    merges
+}
+
+/// Name of the file `folder_dump` and `tar_dump` write a `RunMeta` to, at the root of the dump.
+pub const RUN_META_FILE: &str = "run_meta.json";
+
+/// Where an output came from, so it can be reproduced: written as `RUN_META_FILE` by the dumps
+/// (see `DumpOptions::run_meta`) and as a `# run_meta=` line at the top of the CSV of merges (see
+/// `CsvOptions::run_meta`).
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct RunMeta {
+    /// Version of this tool
+    pub version: String,
+    /// The command line, starting with the program
+    pub args: Vec<String>,
+    /// The commit HEAD of the analysed repository pointed to, None for an empty repository
+    pub head: Option<String>,
+    /// When the run started, RFC 3339 in UTC
+    pub timestamp: String,
+}
+
+impl RunMeta {
+    /// The metadata of a run over `repo` started right now with the given command line.
+    pub fn new(repo: &git2::Repository, args: Vec<String>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            args,
+            head: repo
+                .head()
+                .and_then(|head| head.peel_to_commit())
+                .map(|commit| commit.id().to_string())
+                .ok(),
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        }
+    }
+}
+
+/// Options for `folder_dump` and `tar_dump`.
+#[derive(Default)]
+pub struct DumpOptions {
+    /// Write every file present in O, A, B, or M rather than only the changed ones
+    pub all_files: bool,
+    /// Leave files identical to their version in M out of the o, a, and b folders
+    pub only_differing: bool,
+    /// Link the o folder to the a or b folder when O is the same commit, see
+    /// `merge::WriteOptions::dedup_identical_parts`
+    pub dedup_identical_parts: bool,
+    /// Swap A and B where needed so the a folder holds the side with the smaller change. See
+    /// `ThreeWayMerge::order_by_change_size`.
+    pub order_by_change_size: bool,
+    /// Allow the output folder of `folder_dump` to already contain merges, for adding new merges
+    /// to an earlier dump
+    pub incremental: bool,
+    /// Also write the files that conflict when merging A and B, see
+    /// `ThreeWayMerge::write_conflicts`
+    pub conflicts: bool,
+    /// How the changed files are written in `merge.json`
+    pub path_style: merge::PathStyle,
+    /// Continue an interrupted `folder_dump`: merges whose folder has a `DONE_SENTINEL` are
+    /// skipped, all others are (re)written. `folder_dump` always adds the sentinel once all files
+    /// of a merge are written.
+    pub resume: bool,
+    /// Rewrite the line endings of dumped text files, see
+    /// `git_utils::write_files_from_commit_to_disk`. The dump then differs from git's bytes.
+    pub normalize_eol: Option<git_utils::LineEnding>,
+    /// Skip dumped files larger than this many bytes, see `git_utils::BlobOptions::max_bytes`
+    pub max_file_bytes: Option<usize>,
+    /// Leave files over `max_file_bytes` in any part out of the changed files altogether: out
+    /// of `merge.json` and out of all four folders, see `ThreeWayMerge::files_within_size`. Has
+    /// no effect on the folders with `all_files`.
+    pub exclude_large_files: bool,
+    /// If given, written to `RUN_META_FILE` before any merge. An earlier one is replaced, e.g.,
+    /// when resuming.
+    pub run_meta: Option<RunMeta>,
 }
 
 /// Finds the merges of a given git repository, dumps the changed files for each of them into
 /// the provided folder. Final structure of that folder will be:
 /// folder/mergehash/mergepart/path/to/file
 ///
+/// Next to the mergepart folders, every mergehash folder gets a `merge.json` describing the merge.
+///
 /// Folder needs to be empty, may or may not exist.
 pub fn folder_dump<P: AsRef<std::path::Path>>(
     folder: P,
     repo: &git2::Repository,
     revwalk: git2::Revwalk,
     before: Option<i64>,
-    all_files: bool,
-    distinct_o: bool,
+    base_cache: Option<&mut merge::MergeBaseCache>,
+    filter: &merge::MergeFilter,
+    options: &DumpOptions,
 ) {
     let folder = folder.as_ref();
     // Create folder if needed and check it is empty
     std::fs::create_dir_all(folder).expect("Could not create output-folder");
     let mut dir_contents = std::fs::read_dir(folder).expect("Could not read output-folder");
-    if dir_contents.next().is_some() {
+    if !options.incremental && !options.resume && dir_contents.next().is_some() {
         panic!("Specified output-folder is not empty. Aborting.");
     }
+    let completed = if options.resume {
+        completed_merges(folder)
+    } else {
+        HashSet::new()
+    };
+
+    let mut sink = FolderSink::new(folder);
+    dump(
+        &mut sink,
+        repo,
+        find_merges_reporting(repo, revwalk, before, base_cache),
+        filter,
+        options,
+        Some(&completed),
+    );
+    // TODO? Create a csv file of all merges in the folder
+    // TODO? Place detailed diff "overview" in a text file there
+}
+
+/// Name of the empty file marking a merge folder as complete, see `DumpOptions::resume`.
+pub const DONE_SENTINEL: &str = ".done";
+
+/// The merges of an earlier dump into `folder` that have their `DONE_SENTINEL`.
+fn completed_merges(folder: &std::path::Path) -> HashSet<git2::Oid> {
+    std::fs::read_dir(folder)
+        .expect("Could not read output-folder")
+        .flatten()
+        .filter(|entry| entry.path().join(DONE_SENTINEL).is_file())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            git2::Oid::from_str(name.to_str()?).ok()
+        })
+        .collect()
+}
+
+/// Same as `folder_dump`, but streams everything into a tar archive instead of loose files. The
+/// paths inside the archive are the same: mergehash/mergepart/path/to/file. If the filename ends
+/// in `.gz`, the archive is gzip compressed.
+///
+/// An existing file is overwritten.
+pub fn tar_dump<P: AsRef<std::path::Path>>(
+    archive: P,
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    before: Option<i64>,
+    base_cache: Option<&mut merge::MergeBaseCache>,
+    filter: &merge::MergeFilter,
+    options: &DumpOptions,
+) -> std::io::Result<()> {
+    let archive = archive.as_ref();
+    let merges = find_merges_reporting(repo, revwalk, before, base_cache);
+    let writer = std::io::BufWriter::new(std::fs::File::create(archive)?);
+    if archive.extension().is_some_and(|ext| ext == "gz") {
+        let encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        let mut sink = TarSink::new(encoder);
+        dump(&mut sink, repo, merges, filter, options, None);
+        sink.finish()?.finish()?.flush()
+    } else {
+        let mut sink = TarSink::new(writer);
+        dump(&mut sink, repo, merges, filter, options, None);
+        sink.finish()?.flush()
+    }
+}
 
-    let merges = merge::find_merges(repo, revwalk, before);
+/// Writes the merges that pass the filter into the sink, each below a folder named after the
+/// merge commit.
+///
+/// `done` is for folder dumps: merges in it are skipped, and every merge written gets a
+/// `DONE_SENTINEL`. Archives pass `None`, as they are never resumed.
+fn dump(
+    sink: &mut dyn BlobSink,
+    repo: &git2::Repository,
+    merges: Vec<merge::ThreeWayMerge>,
+    filter: &merge::MergeFilter,
+    options: &DumpOptions,
+    done: Option<&HashSet<git2::Oid>>,
+) {
+    if let Some(run_meta) = &options.run_meta {
+        let json = serde_json::to_vec_pretty(run_meta)
+            .unwrap_or_else(|err| panic!("Failed to serialise {}: {}", RUN_META_FILE, err));
+        sink.put(std::path::Path::new(RUN_META_FILE), &json, false)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", RUN_META_FILE, err));
+    }
 
     // Create merge-hash folder and its o, a, b, and m subfolders.
-    if all_files {
-        for merge in merges {
-            if distinct_o && !merge.has_distinct_o() {
-                continue;
+    for mut merge in merges {
+        if done.is_some_and(|done| done.contains(&merge.m)) || !filter.keep(&merge, repo) {
+            continue;
+        }
+        if options.order_by_change_size {
+            merge.order_by_change_size(repo);
+        }
+        let merge_path = std::path::PathBuf::from(merge.m.to_string());
+        let write_options = merge::WriteOptions {
+            only_differing: options.only_differing,
+            dedup_identical_parts: options.dedup_identical_parts,
+            blob_options: git_utils::BlobOptions {
+                eol: options.normalize_eol,
+                max_bytes: options.max_file_bytes,
+            },
+        };
+        let excluded_size = options
+            .max_file_bytes
+            .filter(|_| options.exclude_large_files);
+        let o_same_as = if options.all_files {
+            merge.write_all_files(sink, &merge_path, repo, write_options)
+        } else {
+            let mut files = merge.files_to_consider(repo);
+            if let Some(max_bytes) = excluded_size {
+                files = merge.files_within_size(repo, files, max_bytes);
             }
-            let merge_path = folder.join(merge.m.to_string());
-            merge.write_all_files_to_disk(merge_path, repo);
+            merge.write_files(sink, &merge_path, files, repo, write_options)
+        };
+        if options.conflicts {
+            merge.write_conflicts(sink, &merge_path, repo);
         }
-    } else {
-        for merge in merges {
-            if distinct_o && !merge.has_distinct_o() {
+        merge.write_record(
+            sink,
+            &merge_path,
+            repo,
+            options.path_style,
+            excluded_size,
+            o_same_as,
+        );
+        if done.is_some() {
+            sink.put(&merge_path.join(DONE_SENTINEL), &[], false)
+                .unwrap_or_else(|err| panic!("Failed to mark {} as done: {}", merge.m, err));
+        }
+    }
+}
+
+/// Writes the merges that pass the filter to a Parquet file, one row per merge. Typed, so it loads
+/// into a DataFrame much faster than the CSV. Columns: o, a, b, m (strings), timestamp (i64, epoch
+/// seconds), changed_files (u32, a count), distinct_o and a_b_swapped (booleans). See
+/// `merge::MergeRecord`.
+///
+/// Only available with the `parquet` feature.
+#[cfg(feature = "parquet")]
+pub fn parquet_dump<P: AsRef<std::path::Path>>(
+    path: P,
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    before: Option<i64>,
+    base_cache: Option<&mut merge::MergeBaseCache>,
+    filter: &merge::MergeFilter,
+    order_by_change_size: bool,
+) -> Result<(), parquet::errors::ParquetError> {
+    use arrow_array::{ArrayRef, BooleanArray, Int64Array, RecordBatch, StringArray, UInt32Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    /// Rows are written in batches of this many, so memory use does not grow with the history.
+    const BATCH_SIZE: usize = 8192;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("o", DataType::Utf8, false),
+        Field::new("a", DataType::Utf8, false),
+        Field::new("b", DataType::Utf8, false),
+        Field::new("m", DataType::Utf8, false),
+        Field::new("timestamp", DataType::Int64, false),
+        Field::new("changed_files", DataType::UInt32, false),
+        Field::new("distinct_o", DataType::Boolean, false),
+        Field::new("a_b_swapped", DataType::Boolean, false),
+    ]));
+    let to_batch = |records: &[merge::MergeRecord]| {
+        let strings = |field: fn(&merge::MergeRecord) -> &str| -> ArrayRef {
+            Arc::new(records.iter().map(field).map(Some).collect::<StringArray>())
+        };
+        let columns: Vec<ArrayRef> = vec![
+            strings(|record| &record.o),
+            strings(|record| &record.a),
+            strings(|record| &record.b),
+            strings(|record| &record.m),
+            Arc::new(Int64Array::from_iter_values(
+                records.iter().map(|record| record.timestamp),
+            )),
+            Arc::new(UInt32Array::from_iter_values(
+                records
+                    .iter()
+                    .map(|record| record.changed_files.len() as u32),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                records.iter().map(|record| Some(record.distinct_o)),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                records.iter().map(|record| Some(record.a_b_swapped)),
+            )),
+        ];
+        RecordBatch::try_new(schema.clone(), columns)
+    };
+
+    let file = std::fs::File::create(path)?;
+    let mut writer = parquet::arrow::ArrowWriter::try_new(file, schema.clone(), None)?;
+    let mut records = Vec::with_capacity(BATCH_SIZE);
+    for mut merge in find_merges_reporting(repo, revwalk, before, base_cache) {
+        if !filter.keep(&merge, repo) {
+            continue;
+        }
+        if order_by_change_size {
+            merge.order_by_change_size(repo);
+        }
+        records.push(merge.to_record(repo));
+        if records.len() == BATCH_SIZE {
+            writer.write(&to_batch(&records)?)?;
+            records.clear();
+        }
+    }
+    if !records.is_empty() {
+        writer.write(&to_batch(&records)?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+// TODO Might want to move some of the following logic to find_bug_fix.
+
+/// Options for `write_bug_fix_csv`, `write_bug_fix_csv_overlapping_lines`, and
+/// `write_analysis_csv`.
+#[derive(Default)]
+pub struct BugFixOptions {
+    /// How many generations away from the merge a fix may be
+    pub fix_distance: u32,
+    /// If given, a fix may be at most this many days younger than the merge
+    pub fix_window_days: Option<u32>,
+    /// Add three columns telling for each fix whether its author is the committer of the merge
+    pub with_same_author: bool,
+    /// Never take merge commits as fixes, see `BugFixFinder::exclude_merges`
+    pub exclude_merge_fixes: bool,
+    /// Patterns the message of a fix has to match all of, see `BugFixFinder::msg_contains`. The
+    /// usual keywords if empty.
+    pub required: Vec<regex::Regex>,
+    /// If given, fixes are picked by `find_bug_fix::fix_score` instead of by keywords: the score
+    /// has to be at least this. Patterns in `required` still have to match.
+    pub fix_threshold: Option<f64>,
+    /// Add three columns with the `find_bug_fix::fix_score` of each fix
+    pub with_score: bool,
+    /// Which files of the merge a fix has to change. Only used by `write_bug_fix_csv`.
+    pub file_scope: crate::find_bug_fix::FixFileScope,
+    /// Report on STDERR which line made each fix overlap. Only used by
+    /// `write_bug_fix_csv_overlapping_lines`.
+    pub explain_overlap: bool,
+    /// Unchanged lines around the changes of a fix that count for the overlap, see
+    /// `git_utils::OverlapLines`. Only used by `write_bug_fix_csv_overlapping_lines`.
+    pub context_lines: u32,
+    /// Extensions (without the dot, e.g., `java`) of the files whose lines count for the overlap.
+    /// None takes those of the files the merge changed, see `ThreeWayMerge::changed_extensions`.
+    /// Only used by `write_bug_fix_csv_overlapping_lines`.
+    pub extensions: Option<Vec<String>>,
+    /// Report on STDERR how many merges have been processed, out of how many if the broken commits
+    /// tell their number up front (an exact `size_hint`)
+    pub show_progress: bool,
+    /// Number of merges to process in parallel. 0 and 1 both mean one at a time, on the calling
+    /// thread.
+    pub jobs: usize,
+    /// If given, also attach the result for each merge as a git note under this ref, see
+    /// `write_merge_note`
+    pub notes_ref: Option<String>,
+    /// Stop looking for fixes of a merge once this many are found. There are three fix columns,
+    /// so anything above three means three.
+    pub max_fixes: usize,
+    /// If given, a fix has to reference one of these issues, see `BugFixFinder::references_bug`
+    pub bug_issues: Option<HashSet<u32>>,
+}
+
+impl BugFixOptions {
+    /// Finds the candidate fixes of a merge within the distance and time window, looking the
+    /// descendants up in `children` if given. The checks only run as the fixes are taken, see
+    /// `LazyFixes`.
+    fn find<'a>(
+        &'a self,
+        repo: &'a git2::Repository,
+        twm: &crate::merge::ThreeWayMerge,
+        children: Option<&git_utils::ChildMap>,
+    ) -> Result<crate::find_bug_fix::LazyFixes<'a>, git2::Error> {
+        let max_time = self
+            .fix_window_days
+            .map(|days| twm.time(repo) + i64::from(days) * 24 * 60 * 60);
+        let finder = match children {
+            Some(children) => crate::find_bug_fix::BugFixFinder::find_in(
+                repo,
+                children,
+                twm.m,
+                self.fix_distance,
+                max_time,
+            )?,
+            None => crate::find_bug_fix::BugFixFinder::find_within_until(
+                repo,
+                &twm.m.to_string(),
+                self.fix_distance,
+                max_time,
+            )?,
+        };
+        let mut fixes = finder.lazy();
+        match self.fix_threshold {
+            Some(threshold) => {
+                if !self.required.is_empty() {
+                    fixes = fixes.msg_contains(&self.required);
+                }
+                fixes = fixes.min_score(threshold, twm.files_to_consider(repo));
+            }
+            None => fixes = fixes.msg_contains(&self.required),
+        }
+        if self.exclude_merge_fixes {
+            fixes = fixes.exclude_merges();
+        }
+        if let Some(issues) = &self.bug_issues {
+            fixes = fixes.references_bug(issues);
+        }
+        Ok(fixes)
+    }
+}
+
+/// Finds the merges and their bug fixes in one pass and writes a line per merge that passes the
+/// filter to `out`, after a header:
+///
+/// ```text
+/// O,A,B,M,changed_files,timestamp,fix1,fix2,fix3
+/// ```
+///
+/// The first six columns are those of `write_csv_of_merges`, the fixes those of
+/// `write_bug_fix_csv`, picked by the same `options`. Their children are collected once up front
+/// (see `git_utils::ChildMap`), so the history is not walked again for every merge. Merges are
+/// processed one at a time, `jobs`, `show_progress`, `notes_ref`, and the extra fix columns are
+/// not used.
+pub fn write_analysis_csv(
+    out: &mut dyn Write,
+    repo: &git2::Repository,
+    revwalk: git2::Revwalk,
+    before: Option<i64>,
+    filter: &merge::MergeFilter,
+    options: &BugFixOptions,
+) -> std::io::Result<()> {
+    let merges = find_merges_reporting(repo, revwalk, before, None);
+    let children = git_utils::ChildMap::from_head(repo).map_err(std::io::Error::other)?;
+    writeln!(out, "O,A,B,M,changed_files,timestamp,fix1,fix2,fix3")?;
+    for merge in merges {
+        if !filter.keep(&merge, repo) {
+            continue;
+        }
+        let mut candidates = match options.find(repo, &merge, Some(&children)) {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                recoverable_error!(
+                    "Failed to find bug fixing commit for {}.\nError: {}",
+                    merge.m,
+                    e
+                );
                 continue;
             }
-            let files = merge.files_to_consider(repo);
-            let merge_path = folder.join(merge.m.to_string());
-            merge.write_files_to_disk(&merge_path, files, repo);
+        };
+        if let Some(merge_changes) = options.file_scope.files(repo, &merge) {
+            candidates = candidates.changed_files(merge_changes);
         }
+        let fixes: Vec<_> = candidates.take(options.max_fixes.min(3)).collect();
+        let fix = |i: usize| fixes.get(i).map(|fix| fix.to_string()).unwrap_or_default();
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            merge.to_csv_line(),
+            merge.files_to_consider(repo).len(),
+            merge.time(repo),
+            fix(0),
+            fix(1),
+            fix(2)
+        )?;
     }
-    // TODO? Create a csv file of all merges in the folder
-    // TODO? Place detailed diff "overview" in a text file there
+    Ok(())
 }
 
+/// Reads a commitlist, a CSV of O,A,B,M hashes such as `write_csv_of_merges` writes, a line at a
+/// time. Further columns are ignored, as are empty lines, the `O,A,B,M` header, and lines starting
+/// with `#` (e.g., the `# run_meta=` line). Lines with fewer than four columns are reported on
+/// STDERR and skipped, or panic under `verbosity::ErrorPolicy::FailFast`.
+pub fn read_commitlist<R: std::io::BufRead>(
+    reader: R,
+) -> impl Iterator<Item = (String, String, String, String)> {
+    reader.lines().filter_map(|line| {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                recoverable_error!("Failed to read a line of the commitlist: {}", e);
+                return None;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("O,A,B,M") {
+            return None;
+        }
+        match line.split(',').collect::<Vec<_>>()[..] {
+            [o, a, b, m, ..] => Some((o.to_owned(), a.to_owned(), b.to_owned(), m.to_owned())),
+            _ => {
+                recoverable_error!("Skipping {:?}, expected a line of O,A,B,M hashes", line);
+                None
+            }
+        }
+    })
+}
 
-// TODO Might want to move some of the following logic to find_bug_fix.
+/// For every given broken commit, checks for fixing descendants and writes a line to `out` of the
+/// form
+///
+/// ```text
+/// brokencommit,bugfix1,bugfix2,bugfix3
+/// ```
+///
+/// The latter three may be empty. With `with_same_author`, three more columns follow telling for
+/// each fix whether its author is the committer of the merge. With `with_score`, three more with
+/// the score of each fix.
+///
+/// Bug fixing commit must be within fix_distance (and fix_window_days, if given) of the merge and
+/// change one of the files picked by `file_scope`.
+///
+/// The broken commits are taken from the iterator as they are needed, so the list does not have to
+/// be read into memory first. See `BugFixOptions::jobs` for processing merges in parallel.
+pub fn write_bug_fix_csv<I>(
+    out: &mut dyn Write,
+    repo: &git2::Repository,
+    broken_commits: I,
+    options: &BugFixOptions,
+) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = (String, String, String, String)>,
+    I::IntoIter: Send,
+{
+    write_bug_fix_lines(out, repo, broken_commits, options, false)
+}
 
-/// For every given broken commit, checks for fixing descendants and prints a line of the form
+/// For every given broken commit, checks for fixing descendants and writes a line to `out` of the
+/// form
 ///
 /// ```text
 /// brokencommit,bugfix1,bugfix2,bugfix3
 /// ```
 ///
-/// The latter three may be empty.
-pub fn print_bug_fix_csv(
+/// The latter three may be empty. With `with_same_author`, three more columns follow telling for
+/// each fix whether its author is the committer of the merge. With `with_score`, three more with
+/// the score of each fix.
+///
+/// Bug fixing commit must be within fix_distance (and fix_window_days, if given) of the merge. At
+/// least one line it changes must be changed in O→M, in a file with one of the
+/// `BugFixOptions::extensions`.
+///
+/// Streams and parallelises like `write_bug_fix_csv`.
+pub fn write_bug_fix_csv_overlapping_lines<I>(
+    out: &mut dyn Write,
     repo: &git2::Repository,
-    broken_commit_list: &[(String, String, String, String)],
-    fix_distance: u32,
-) {
-    for commit in broken_commit_list {
-        let (o_commit, a_commit, b_commit, m_commit) = commit;
-        let twm = crate::merge::ThreeWayMerge::from_oid_str(o_commit, a_commit, b_commit, m_commit).unwrap();
-        let merge_changes = twm.files_changed_in_both_branches(repo);
-
-        match crate::find_bug_fix::BugFixFinder::find(repo, m_commit) {
-            Ok(mut bff) => {
-                // The argument is currently not used.
-                bff.msg_contains(vec![]);
-                bff.within_n_generations(repo, &twm.m, fix_distance);
-                bff.changed_files(repo, merge_changes);
-                let fixes = bff.collect();
-                print_merge_bugfix_csv_line(
-                    m_commit,
-                    fixes.get(0),
-                    fixes.get(1),
-                    fixes.get(2),
+    broken_commits: I,
+    options: &BugFixOptions,
+) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = (String, String, String, String)>,
+    I::IntoIter: Send,
+{
+    write_bug_fix_lines(out, repo, broken_commits, options, true)
+}
+
+/// Writes the `bug_fix_line` of every broken commit to `out`, in the order they were given.
+///
+/// With more than one job, every worker thread opens the repository itself (a `Repository` cannot
+/// be shared between threads) and takes the next broken commit when it is done with the previous
+/// one. Finished lines are held back until all lines before them have been written. A failed
+/// write stops the workers.
+fn write_bug_fix_lines<I>(
+    out: &mut dyn Write,
+    repo: &git2::Repository,
+    broken_commits: I,
+    options: &BugFixOptions,
+    overlapping: bool,
+) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = (String, String, String, String)>,
+    I::IntoIter: Send,
+{
+    let broken_commits = broken_commits.into_iter();
+    let total = match broken_commits.size_hint() {
+        (lower, Some(upper)) if lower == upper => Some(upper),
+        _ => None,
+    };
+    let mut progress = Progress::new("merges", total, options.show_progress);
+    let mut print = |found: Option<BugFixLine>| -> std::io::Result<()> {
+        if let Some(found) = found {
+            writeln!(out, "{}", found.line)?;
+            if let Some(notes_ref) = &options.notes_ref {
+                if let Err(e) = write_merge_note(repo, notes_ref, &found.merge, &found.fixes) {
+                    recoverable_error!("Failed to write a note on {}: {}", found.merge.m, e);
+                }
+            }
+        }
+        progress.tick();
+        Ok(())
+    };
+
+    if options.jobs <= 1 {
+        for commit in broken_commits {
+            print(bug_fix_line(repo, &commit, options, overlapping))?;
+        }
+    } else {
+        let work = std::sync::Mutex::new(broken_commits.into_iter().enumerate());
+        let (sender, receiver) = std::sync::mpsc::sync_channel(options.jobs * 4);
+        let path = repo.path();
+        std::thread::scope(|scope| -> std::io::Result<()> {
+            for _ in 0..options.jobs {
+                let sender = sender.clone();
+                let work = &work;
+                scope.spawn(move || {
+                    let repo = git2::Repository::open(path)
+                        .expect("Failed to open the repository in a worker thread");
+                    loop {
+                        let next = work.lock().unwrap().next();
+                        let Some((idx, commit)) = next else {
+                            break;
+                        };
+                        let line = bug_fix_line(&repo, &commit, options, overlapping);
+                        if sender.send((idx, line)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            // Only the workers hold a sender now, so the loop ends when they are all done.
+            drop(sender);
+            let mut finished = std::collections::BTreeMap::new();
+            let mut next_idx = 0;
+            for (idx, line) in receiver {
+                finished.insert(idx, line);
+                while let Some(line) = finished.remove(&next_idx) {
+                    // Returning drops the receiver, so the workers stop at their next line.
+                    print(line)?;
+                    next_idx += 1;
+                }
+            }
+            Ok(())
+        })?;
+    }
+    progress.finish();
+    Ok(())
+}
+
+/// What `bug_fix_line` found for one broken commit.
+struct BugFixLine {
+    merge: merge::ThreeWayMerge,
+    fixes: Vec<git2::Oid>,
+    line: String,
+}
+
+/// Finds the fixes of one broken commit and builds its CSV line, see `write_bug_fix_csv` and
+/// `write_bug_fix_csv_overlapping_lines`. Errors are reported on STDERR and give no line, or
+/// panic under `verbosity::ErrorPolicy::FailFast`.
+fn bug_fix_line(
+    repo: &git2::Repository,
+    commit: &(String, String, String, String),
+    options: &BugFixOptions,
+    overlapping: bool,
+) -> Option<BugFixLine> {
+    let (o_commit, a_commit, b_commit, m_commit) = commit;
+    let twm = match merge::ThreeWayMerge::from_oid_str(o_commit, a_commit, b_commit, m_commit) {
+        Ok(twm) => twm,
+        Err(e) => {
+            recoverable_error!(
+                "Skipping {},{},{},{}, not four commit hashes.\nError: {}",
+                o_commit,
+                a_commit,
+                b_commit,
+                m_commit,
+                e.message()
+            );
+            return None;
+        }
+    };
+    if let Err(e) = twm.validate(repo) {
+        recoverable_error!(
+            "Skipping {}, not a valid merge.\nError: {}",
+            m_commit,
+            e.message()
+        );
+        return None;
+    }
+
+    let extensions = match (&options.extensions, overlapping) {
+        (Some(extensions), true) => extensions.clone(),
+        (None, true) => twm.changed_extensions(repo),
+        (_, false) => vec![],
+    };
+    let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+    match options.find(repo, &twm, None) {
+        Ok(mut candidates) => {
+            if overlapping {
+                candidates = candidates.changed_same_line_in_ext(
+                    &twm,
+                    &extensions,
+                    options.context_lines,
+                    options.explain_overlap,
                 );
+            } else if let Some(merge_changes) = options.file_scope.files(repo, &twm) {
+                candidates = candidates.changed_files(merge_changes);
             }
-            Err(e) => eprintln!(
+            // There are only three fix columns, so checking further candidates is wasted work.
+            let fixes: Vec<_> = candidates.take(options.max_fixes.min(3)).collect();
+            let mut line =
+                merge_bugfix_csv_line(m_commit, fixes.first(), fixes.get(1), fixes.get(2));
+            if options.with_same_author {
+                line.push_str(&same_author_columns(repo, &twm.m, &fixes));
+            }
+            if options.with_score {
+                line.push_str(&score_columns(repo, &twm, &fixes));
+            }
+            Some(BugFixLine {
+                merge: twm,
+                fixes,
+                line,
+            })
+        }
+        Err(e) => {
+            recoverable_error!(
                 "Failed to find bug fixing commit for {}.\nError: {}",
-                m_commit, e
-            ),
+                m_commit,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// The content of the note `write_merge_note` attaches to a merge commit.
+#[derive(serde::Serialize)]
+struct MergeNote {
+    o: String,
+    a: String,
+    b: String,
+    m: String,
+    fixes: Vec<String>,
+}
+
+/// Attaches the merge and its fixes as a JSON note to the merge commit, under `notes_ref` (e.g.,
+/// `refs/notes/merges`), so they show up in `git log --notes=merges`. An existing note is
+/// replaced, unless it already says the same, so a re-run does not add to the history of the
+/// notes ref. Returns whether a note was written.
+///
+/// The note is signed by the user configured in the repository, or by the tool itself if there is
+/// none.
+pub fn write_merge_note(
+    repo: &git2::Repository,
+    notes_ref: &str,
+    merge: &merge::ThreeWayMerge,
+    fixes: &[git2::Oid],
+) -> Result<bool, git2::Error> {
+    let note = MergeNote {
+        o: merge.o.to_string(),
+        a: merge.a.to_string(),
+        b: merge.b.to_string(),
+        m: merge.m.to_string(),
+        fixes: fixes.iter().map(|fix| fix.to_string()).collect(),
+    };
+    let note = serde_json::to_string_pretty(&note)
+        .map_err(|err| git2::Error::from_str(&err.to_string()))?;
+    if let Ok(existing) = repo.find_note(Some(notes_ref), merge.m) {
+        if existing.message() == Some(note.as_str()) {
+            return Ok(false);
         }
     }
+    let signature = repo.signature().or_else(|_| {
+        git2::Signature::now("three-way-merge-finder", "three-way-merge-finder@localhost")
+    })?;
+    repo.note(
+        &signature,
+        &signature,
+        Some(notes_ref),
+        merge.m,
+        &note,
+        true,
+    )?;
+    Ok(true)
 }
 
-/// Quick helper function for print_bug_fix_csv.
-fn print_merge_bugfix_csv_line(
+/// Quick helper function for bug_fix_line.
+fn merge_bugfix_csv_line(
     m_commit: &str,
     bugfix1: Option<&git2::Oid>,
     bugfix2: Option<&git2::Oid>,
     bugfix3: Option<&git2::Oid>,
-) {
-    println!(
+) -> String {
+    format!(
         "{},{},{},{}",
         m_commit,
         bugfix1.map(|oid| oid.to_string()).unwrap_or_default(),
         bugfix2.map(|oid| oid.to_string()).unwrap_or_default(),
         bugfix3.map(|oid| oid.to_string()).unwrap_or_default(),
-    );
+    )
 }
 
-/// For every given broken commit, checks for fixing descendants and prints a line of the form
+/// Three extra columns (each starting with a comma) telling for each of the first three fixes
+/// whether it was authored by the merge's committer. Empty if there is no such fix or an email is
+/// missing.
+fn same_author_columns(repo: &git2::Repository, merge: &git2::Oid, fixes: &[git2::Oid]) -> String {
+    (0..3)
+        .map(|idx| {
+            let same = fixes
+                .get(idx)
+                .and_then(|fix| crate::find_bug_fix::same_author(repo, merge, fix));
+            format!(",{}", same.map(|same| same.to_string()).unwrap_or_default())
+        })
+        .collect()
+}
+
+/// Three extra columns (each starting with a comma) with the `find_bug_fix::fix_score` of each of
+/// the first three fixes. Empty if there is no such fix.
+fn score_columns(
+    repo: &git2::Repository,
+    twm: &crate::merge::ThreeWayMerge,
+    fixes: &[git2::Oid],
+) -> String {
+    let scorers = crate::find_bug_fix::default_scorers(twm.files_to_consider(repo));
+    (0..3)
+        .map(|idx| match fixes.get(idx) {
+            Some(fix) => format!(
+                ",{:.2}",
+                crate::find_bug_fix::score_with(repo, fix, &scorers)
+            ),
+            None => String::from(","),
+        })
+        .collect()
+}
+
+/// Options for `write_bug_fix_files`.
+#[derive(Default)]
+pub struct FixFolderOptions {
+    /// Also write the patch of every bug fix, limited to the files of the merge
+    pub write_fix_diffs: bool,
+    /// Only list which fixes were found for each merge, see `write_fix_plan`. Nothing is written.
+    pub dry_run: bool,
+    /// Rewrite the line endings of the fixes' text files, as `DumpOptions::normalize_eol`
+    pub normalize_eol: Option<git_utils::LineEnding>,
+}
+
+/// Writes to `out` what `write_bug_fix_files` would write for a merge: the number of candidate
+/// fixes and which of them become bf1, bf2, and bf3. With more than three candidates, a last line
+/// tells how many are left out.
 ///
 /// ```text
-/// brokencommit,bugfix1,bugfix2,bugfix3
+/// 4069672d2ac0f1aceb9ac2a54484d8b04c573b13: 2 candidate fixes
+///   bf1 9025149089f09b281e63810cffe8802a4526b243
+///   bf2 9d8ceb3b4d7b5fdf2e43ce4c1bba9eb00f1dca37
 /// ```
-///
-/// The latter three may be empty.
-///
-/// Bug fixing commit must be within fix_distance of the merge. At least one line it changes must
-/// be changed in O→M.
-pub fn print_bug_fix_csv_overlapping_lines(
-    repo: &git2::Repository,
-    broken_commit_list: &[(String, String, String, String)],
-    fix_distance: u32,
-) {
-    for commit in broken_commit_list {
-        let (o_commit, a_commit, b_commit, m_commit) = commit;
-        let twm = crate::merge::ThreeWayMerge::from_oid_str(o_commit, a_commit, b_commit, m_commit).unwrap();
-
-        match crate::find_bug_fix::BugFixFinder::find(repo, m_commit) {
-            Ok(mut bff) => {
-                // The argument is currently not used.
-                bff.msg_contains(vec![]);
-                bff.within_n_generations(repo, &twm.m, fix_distance);
-                bff.changed_same_line_in_ext(repo, &twm, &vec!["java"]);
-                let fixes = bff.collect();
-                print_merge_bugfix_csv_line(
-                    m_commit,
-                    fixes.get(0),
-                    fixes.get(1),
-                    fixes.get(2),
-                );
-            }
-            Err(e) => eprintln!(
-                "Failed to find bug fixing commit for {}.\nError: {}",
-                m_commit, e
-            ),
-        }
+fn write_fix_plan(out: &mut dyn Write, merge: &str, fixes: &[git2::Oid]) -> std::io::Result<()> {
+    writeln!(out, "{}: {} candidate fixes", merge, fixes.len())?;
+    for (idx, fix) in fixes.iter().take(3).enumerate() {
+        writeln!(out, "  bf{} {}", idx + 1, fix)?;
+    }
+    if fixes.len() > 3 {
+        writeln!(out, "  ({} more not written)", fixes.len() - 3)?;
     }
+    Ok(())
 }
 
 /// Expects a folder that is the result of the merge commit search. Thus this folder has several
@@ -191,38 +1160,66 @@ pub fn print_bug_fix_csv_overlapping_lines(
 /// └── d0c8a79c92c4e770a28604569a1e0860a4a0320c
 /// ```
 ///
-/// For each of the folders, uses the name as a commit to find a bug fix for. If bug fixes are
-/// found, they are added as subfolders in that folder. The bug fix folder is thus a sibling to the
-/// existing o, a, b, m folders. Files present in m are used as the basis of what files to write
-/// out from the bug fixing commit.
+/// For each of the folders, uses the name as a commit to find a bug fix for. Anything else in the
+/// folder, such as the `RUN_META_FILE`, is skipped. If bug fixes are found, they are added as
+/// subfolders in that folder. The bug fix folder is thus a sibling to the existing o, a, b, m
+/// folders. Files present in m are used as the basis of what files to write out from the bug
+/// fixing commit.
+///
+/// Writes a CSV line per merge to `out`: the merge and its fixes. With `dry_run`, writes which
+/// fixes would be written instead, see `write_fix_plan`.
+///
+/// With `write_fix_diffs`, each bug fix also gets a `bfN.diff` next to its folder: the patch of
+/// the fix, limited to the files in m. See `git_utils::scoped_patch`.
 ///
 /// If the folders already exist, the files it finds in this run will be overriden. Nothing else
 /// will be touched.
-pub fn write_bug_fix_files<P>(folder: P, repo: &git2::Repository)
+pub fn write_bug_fix_files<P>(
+    out: &mut dyn Write,
+    folder: P,
+    repo: &git2::Repository,
+    options: &FixFolderOptions,
+) -> std::io::Result<()>
 where
     P: AsRef<std::path::Path>,
 {
     let folder = folder.as_ref();
     for commit_folder in folder.read_dir().unwrap().flatten() {
         let commit_folder = commit_folder.path();
-        if let Some(commit_name) = commit_folder.file_name().and_then(|osstr| osstr.to_str()) {
+        if !commit_folder.is_dir() {
+            continue;
+        }
+        if let Some(commit_name) = commit_folder
+            .file_name()
+            .and_then(|osstr| osstr.to_str())
+            .filter(|name| is_full_hash(name))
+        {
             match crate::find_bug_fix::BugFixFinder::find(repo, commit_name) {
                 Ok(mut bff) => {
-                    bff.msg_contains(vec![]);
+                    bff.msg_contains(&[]);
                     let descendants = bff.collect();
+                    if options.dry_run {
+                        write_fix_plan(out, commit_name, &descendants)?;
+                        continue;
+                    }
 
+                    let blob_options = git_utils::BlobOptions {
+                        eol: options.normalize_eol,
+                        ..Default::default()
+                    };
                     let files_to_consider: HashSet<String> =
-                        crate::relative_files::RelativeFiles::open(&commit_folder.join("m"))
+                        crate::relative_files::RelativeFiles::open(commit_folder.join("m"))
                             .filter_map(|path| path.to_str().map(|s| s.to_owned()))
                             .collect();
 
-                    if let Some(bug_fix_1) = descendants.get(0) {
+                    if let Some(bug_fix_1) = descendants.first() {
                         git_utils::write_files_from_commit_to_disk(
                             commit_folder.join("bf1"),
                             *bug_fix_1,
                             repo,
                             &files_to_consider,
                             "BF1",
+                            blob_options,
                         );
                     }
                     if let Some(bug_fix_2) = descendants.get(1) {
@@ -232,6 +1229,7 @@ where
                             repo,
                             &files_to_consider,
                             "BF2",
+                            blob_options,
                         );
                     }
                     if let Some(bug_fix_3) = descendants.get(2) {
@@ -241,22 +1239,47 @@ where
                             repo,
                             &files_to_consider,
                             "BF3",
+                            blob_options,
                         );
                     }
+                    if options.write_fix_diffs {
+                        for (idx, bug_fix) in descendants.iter().take(3).enumerate() {
+                            let path = commit_folder.join(format!("bf{}.diff", idx + 1));
+                            let written =
+                                git_utils::scoped_patch(repo, bug_fix, &files_to_consider)
+                                    .map_err(|err| err.to_string())
+                                    .and_then(|patch| {
+                                        std::fs::write(&path, patch).map_err(|err| err.to_string())
+                                    });
+                            if let Err(err) = written {
+                                recoverable_error!("Failed to write {:?}: {}", path, err);
+                            }
+                        }
+                    }
 
-                    // Output a CSV to STDOUT
-                    print_merge_bugfix_csv_line(
-                        commit_name,
-                        descendants.get(0),
-                        descendants.get(1),
-                        descendants.get(2),
-                    );
-                },
-                Err(e) => eprintln!(
+                    writeln!(
+                        out,
+                        "{}",
+                        merge_bugfix_csv_line(
+                            commit_name,
+                            descendants.first(),
+                            descendants.get(1),
+                            descendants.get(2),
+                        )
+                    )?;
+                }
+                Err(e) => recoverable_error!(
                     "Failed to find bug fixing commit for {}.\nError: {}",
-                    commit_name, e
+                    commit_name,
+                    e
                 ),
             }
         }
     }
+    Ok(())
+}
+
+/// Whether `name` is a full commit hash, as the merge folders of a dump are named.
+fn is_full_hash(name: &str) -> bool {
+    name.len() == 40 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
 }
@@ -1,5 +1,6 @@
 /// Iterator over all files in a given folder. Returns paths relative to that given folder.
-/// Directories are _not_ returned, only the files they contain.
+/// Directories are _not_ returned, only the files they contain. Symlinks are followed. Dangling
+/// symlinks and folders that cannot be read are reported and skipped, the walk goes on.
 pub struct RelativeFiles {
     root: std::path::PathBuf,
     worklist: Vec<std::fs::DirEntry>,
@@ -28,20 +29,28 @@ impl Iterator for RelativeFiles {
     type Item = std::path::PathBuf;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let last_element = self.worklist.pop()?.path();
-        if last_element.is_file() {
-            match last_element.strip_prefix(&self.root) {
-                Ok(last_element) => return Some(last_element.to_path_buf()),
-                Err(e) => eprintln!("Boo boo in RelativeFiles {}", e),
+        loop {
+            let last_element = self.worklist.pop()?.path();
+            if last_element.is_file() {
+                match last_element.strip_prefix(&self.root) {
+                    Ok(last_element) => return Some(last_element.to_path_buf()),
+                    Err(e) => eprintln!("Boo boo in RelativeFiles {}", e),
+                }
+            } else if last_element.is_dir() {
+                match last_element.read_dir() {
+                    Ok(read_dir) => self.worklist.extend(read_dir.flatten()),
+                    Err(e) => recoverable_error!(
+                        "Skipping {}, which cannot be read: {}",
+                        last_element.display(),
+                        e
+                    ),
+                }
+            } else {
+                warning!(
+                    "Skipping {}, which is neither a file nor a folder, e.g., a dangling symlink",
+                    last_element.display()
+                );
             }
         }
-        if last_element.is_dir() {
-            if let Ok(read_dir) = last_element.read_dir() {
-                let mut contents: Vec<_> = read_dir.flatten().collect();
-                self.worklist.append(&mut contents);
-                return self.next();
-            }
-        }
-        None
     }
 }
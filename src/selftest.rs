@@ -0,0 +1,174 @@
+//! Builds a small synthetic repository with a known three way merge and a known bug fix, then
+//! checks the tool finds exactly those. Doubles as a smoke test of the git2 version in use; the
+//! single features are checked by the integration tests under `tests/`.
+//!
+//! The history looks like this, with HEAD at the fix:
+//!
+//! ```text
+//! o ── a ──┐
+//!  \       m ── fix
+//!   └─ b ──┘
+//! ```
+
+use crate::find_bug_fix::BugFixFinder;
+use crate::merge::ThreeWayMerge;
+use git2::{Oid, Repository, Signature};
+
+const FILE: &str = "Main.java";
+
+/// Creates the repository in a fresh temporary folder, runs the checks, and removes the folder
+/// again. Returns a description of the first mismatch.
+pub fn run() -> Result<(), String> {
+    let folder = std::env::temp_dir().join(format!(
+        "three-way-merge-finder-selftest-{}",
+        std::process::id()
+    ));
+    if folder.exists() {
+        std::fs::remove_dir_all(&folder).map_err(|err| err.to_string())?;
+    }
+    let result = Repository::init(&folder)
+        .map_err(|err| err.to_string())
+        .and_then(|repo| check(&repo));
+    if let Err(err) = std::fs::remove_dir_all(&folder) {
+        eprintln!("Could not remove {:?}: {}", folder, err);
+    }
+    result
+}
+
+/// The commits in the synthetic repository.
+struct History {
+    o: Oid,
+    a: Oid,
+    b: Oid,
+    m: Oid,
+    fix: Oid,
+}
+
+fn check(repo: &Repository) -> Result<(), String> {
+    let history = build_history(repo).map_err(|err| err.to_string())?;
+
+    let revwalk = crate::git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL)
+        .map_err(|err| err.to_string())?;
+    let merges = crate::merge::find_merges(repo, revwalk, None);
+    if merges.len() != 1 {
+        return Err(format!("Expected 1 merge, found {}", merges.len()));
+    }
+    let merge = &merges[0];
+    expect("O", history.o, merge.o)?;
+    expect("A", history.a, merge.a)?;
+    expect("B", history.b, merge.b)?;
+    expect("M", history.m, merge.m)?;
+    println!("find-merge: OK ({})", merge.to_csv_line());
+
+    let fixes = find_fixes(repo, merge, false)?;
+    expect_fixes("find-bug-fix", &[history.fix], &fixes)?;
+    let fixes = find_fixes(repo, merge, true)?;
+    expect_fixes("find-bug-fix --touches-same-line", &[history.fix], &fixes)?;
+    Ok(())
+}
+
+/// Follows what `publish::write_bug_fix_csv` (or its overlapping lines variant) does.
+fn find_fixes(
+    repo: &Repository,
+    merge: &ThreeWayMerge,
+    touches_same_line: bool,
+) -> Result<Vec<Oid>, String> {
+    let mut bff =
+        BugFixFinder::find_within(repo, &merge.m.to_string(), 10).map_err(|err| err.to_string())?;
+    bff.msg_contains(&[]);
+    if touches_same_line {
+        bff.changed_same_line_in_ext(repo, merge, &["java"], 0, false);
+    } else {
+        bff.changed_files(repo, merge.files_changed_in_both_branches(repo));
+    }
+    Ok(bff.collect())
+}
+
+fn expect(what: &str, expected: Oid, found: Oid) -> Result<(), String> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected {} to be {}, found {}",
+            what, expected, found
+        ))
+    }
+}
+
+fn expect_fixes(what: &str, expected: &[Oid], found: &[Oid]) -> Result<(), String> {
+    if expected == found {
+        println!("{}: OK", what);
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: expected fixes {:?}, found {:?}",
+            what, expected, found
+        ))
+    }
+}
+
+fn build_history(repo: &Repository) -> Result<History, git2::Error> {
+    let o = commit(
+        repo,
+        "Initial version",
+        "a\nb\nc\nd\ne\n",
+        &[],
+        1_600_000_000,
+    )?;
+    let a = commit(
+        repo,
+        "Change the first line",
+        "A\nb\nc\nd\ne\n",
+        &[o],
+        1_600_000_100,
+    )?;
+    let b = commit(
+        repo,
+        "Change the last line",
+        "a\nb\nc\nd\nE\n",
+        &[o],
+        1_600_000_200,
+    )?;
+    let m = commit(
+        repo,
+        "Merge b into a",
+        "A\nb\nc\nd\nE\n",
+        &[a, b],
+        1_600_000_300,
+    )?;
+    let fix = commit(
+        repo,
+        "Fix the first line",
+        "A!\nb\nc\nd\nE\n",
+        &[m],
+        1_600_000_400,
+    )?;
+    repo.reference("refs/heads/main", fix, true, "selftest")?;
+    repo.set_head("refs/heads/main")?;
+    Ok(History { o, a, b, m, fix })
+}
+
+/// Creates a commit whose tree only holds `FILE` with the given content.
+fn commit(
+    repo: &Repository,
+    summary: &str,
+    content: &str,
+    parents: &[Oid],
+    time: i64,
+) -> Result<Oid, git2::Error> {
+    let blob = repo.blob(content.as_bytes())?;
+    let mut builder = repo.treebuilder(None)?;
+    builder.insert(FILE, blob, 0o100644)?;
+    let tree = repo.find_tree(builder.write()?)?;
+    let parents = parents
+        .iter()
+        .map(|parent| repo.find_commit(*parent))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parents: Vec<_> = parents.iter().collect();
+    let signature = Signature::new(
+        "Self Test",
+        "selftest@example.com",
+        &git2::Time::new(time, 0),
+    )?;
+    repo.commit(None, &signature, &signature, summary, &tree, &parents)
+}
@@ -0,0 +1,206 @@
+//! Places the files of a dump end up in. Either loose files in a folder, entries in a tar archive,
+//! or a map in memory. Other storage (a bucket, a database, ...) only needs to implement
+//! `BlobSink`; every write function of the crate takes a `&mut dyn BlobSink`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Something files can be written to. Paths are relative to the root of the dump, e.g.,
+/// `mergehash/o/path/to/file`.
+pub trait BlobSink {
+    /// Stores `content` at `path`. Parent folders are created as needed.
+    fn put(&mut self, path: &Path, content: &[u8], executable: bool) -> std::io::Result<()>;
+
+    /// Makes sure a (possibly empty) folder exists. Does nothing by default.
+    fn create_dir(&mut self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Makes `path` a symbolic link to the folder `target`, which is relative to the folder
+    /// containing `path`. Sinks that cannot hold links return an `Unsupported` error, which is
+    /// the default.
+    fn symlink_dir(&mut self, _path: &Path, _target: &Path) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Writes loose files below a folder on disk.
+pub struct FolderSink {
+    root: PathBuf,
+}
+
+impl FolderSink {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl BlobSink for FolderSink {
+    fn put(&mut self, path: &Path, content: &[u8], executable: bool) -> std::io::Result<()> {
+        let fullfilepath = self.root.join(path);
+        if let Some(filefolder) = fullfilepath.parent() {
+            std::fs::create_dir_all(filefolder)?;
+        }
+        // Write to a temporary file next to the final one and rename it into place, so a crash
+        // halfway through never leaves a truncated file behind. Renames within one folder are
+        // atomic.
+        let tmppath = temporary_path(&fullfilepath);
+        let written = write_file(&tmppath, content, executable)
+            .and_then(|()| std::fs::rename(&tmppath, &fullfilepath));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&tmppath);
+        }
+        written
+    }
+
+    fn create_dir(&mut self, path: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(self.root.join(path))
+    }
+
+    fn symlink_dir(&mut self, path: &Path, target: &Path) -> std::io::Result<()> {
+        let fullpath = self.root.join(path);
+        if let Some(parent) = fullpath.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // A link left by an earlier, interrupted run is replaced.
+        if std::fs::symlink_metadata(&fullpath).is_ok_and(|metadata| metadata.is_symlink()) {
+            std::fs::remove_file(&fullpath)?;
+        }
+        symlink_dir(target, &fullpath)
+    }
+}
+
+#[cfg(unix)]
+fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Needs developer mode or the right privileges on Windows, without them this fails.
+#[cfg(windows)]
+fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink_dir(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// A hidden name in the same folder as `path`, unique to this process.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".tmp{}", std::process::id()));
+    path.with_file_name(name)
+}
+
+fn write_file(path: &Path, content: &[u8], executable: bool) -> std::io::Result<()> {
+    let mut writer = std::fs::File::create(path)?;
+    writer.write_all(content)?;
+    if executable {
+        make_executable(path)?;
+    }
+    Ok(())
+}
+
+/// Sets the executable bits (where the read bits are set) so scripts stay runnable. Only does
+/// something on Unix.
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    let mode = permissions.mode();
+    permissions.set_mode(mode | ((mode & 0o444) >> 2));
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Streams files straight into a tar archive, without touching the disk. Call `finish` when done
+/// to write the end of the archive.
+pub struct TarSink<W: Write> {
+    builder: tar::Builder<W>,
+    /// All entries get the time the archive was started as modification time.
+    mtime: u64,
+}
+
+impl<W: Write> TarSink<W> {
+    pub fn new(writer: W) -> Self {
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        Self {
+            builder: tar::Builder::new(writer),
+            mtime,
+        }
+    }
+
+    /// Finishes the archive and hands back the writer, e.g., to finish compression.
+    pub fn finish(self) -> std::io::Result<W> {
+        self.builder.into_inner()
+    }
+
+    fn header(&self, entry_type: tar::EntryType, size: u64, mode: u32) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(mode);
+        header.set_mtime(self.mtime);
+        header
+    }
+}
+
+impl<W: Write> BlobSink for TarSink<W> {
+    fn put(&mut self, path: &Path, content: &[u8], executable: bool) -> std::io::Result<()> {
+        let mode = if executable { 0o755 } else { 0o644 };
+        let mut header = self.header(tar::EntryType::Regular, content.len() as u64, mode);
+        self.builder.append_data(&mut header, path, content)
+    }
+
+    fn create_dir(&mut self, path: &Path) -> std::io::Result<()> {
+        let mut header = self.header(tar::EntryType::Directory, 0, 0o755);
+        self.builder
+            .append_data(&mut header, path, std::io::empty())
+    }
+
+    fn symlink_dir(&mut self, path: &Path, target: &Path) -> std::io::Result<()> {
+        let mut header = self.header(tar::EntryType::Symlink, 0, 0o777);
+        self.builder.append_link(&mut header, path, target)
+    }
+}
+
+/// Keeps every file in memory, e.g., to process a dump right away or to check what a write
+/// function produces.
+#[derive(Default)]
+pub struct MemorySink {
+    /// Content of every file put, by path
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+    /// Paths of the files put as executable
+    pub executables: BTreeSet<PathBuf>,
+    /// Folders created explicitly, see `BlobSink::create_dir`
+    pub dirs: BTreeSet<PathBuf>,
+}
+
+impl BlobSink for MemorySink {
+    fn put(&mut self, path: &Path, content: &[u8], executable: bool) -> std::io::Result<()> {
+        if executable {
+            self.executables.insert(path.to_path_buf());
+        } else {
+            self.executables.remove(path);
+        }
+        self.files.insert(path.to_path_buf(), content.to_vec());
+        Ok(())
+    }
+
+    fn create_dir(&mut self, path: &Path) -> std::io::Result<()> {
+        self.dirs.insert(path.to_path_buf());
+        Ok(())
+    }
+}
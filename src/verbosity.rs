@@ -0,0 +1,41 @@
+//! Global switches for how problems are reported. Routine warnings (skipped files, merges without
+//! a base, ...) can be silenced: on messy repositories there are many of those, drowning out the
+//! real errors. Errors about a single merge or fix either skip it or abort the run, see
+//! `ErrorPolicy`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+static FAIL_FAST: AtomicBool = AtomicBool::new(false);
+
+/// When set, routine warnings are no longer printed. Errors are still reported.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// What to do when a single merge, fix or file cannot be processed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Report the error on STDERR and go on with the next one
+    #[default]
+    ContinueOnError,
+    /// Panic with the error, ending the run. Useful in CI to catch data problems early.
+    FailFast,
+}
+
+pub fn set_error_policy(policy: ErrorPolicy) {
+    FAIL_FAST.store(policy == ErrorPolicy::FailFast, Ordering::Relaxed);
+}
+
+pub fn error_policy() -> ErrorPolicy {
+    if FAIL_FAST.load(Ordering::Relaxed) {
+        ErrorPolicy::FailFast
+    } else {
+        ErrorPolicy::ContinueOnError
+    }
+}
@@ -0,0 +1,664 @@
+//! Finding the fixes for a merge.
+
+mod common;
+
+use common::{
+    commit, commit_by, commit_files, expect_fixes, find_fixes, merge_at, scratch_repo, Fixture,
+    History, FILE,
+};
+use git2::{Oid, Repository};
+use std::path::Path;
+use three_way_merge_finder::find_bug_fix::{BugFixFinder, FixClassifier};
+use three_way_merge_finder::merge::ThreeWayMerge;
+use three_way_merge_finder::{find_bug_fix, git_utils, publish};
+
+#[test]
+fn find_bug_fix() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    let fixes = find_fixes(repo, merge, false)?;
+    expect_fixes("find-bug-fix", &[history.fix], &fixes)?;
+    Ok(())
+}
+
+#[test]
+fn find_bug_fix_touches_same_line() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    let fixes = find_fixes(repo, merge, true)?;
+    expect_fixes("find-bug-fix --touches-same-line", &[history.fix], &fixes)?;
+    Ok(())
+}
+
+#[test]
+fn custom_classifier() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let mut bff = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+        .map_err(|err| err.to_string())?;
+    bff.classify(&ParityClassifier);
+    let expected: Vec<Oid> = [history.fix]
+        .into_iter()
+        .filter(|fix| fix.as_bytes()[0].is_multiple_of(2))
+        .collect();
+    expect_fixes("custom classifier", &expected, &bff.collect())?;
+    Ok(())
+}
+
+#[test]
+fn non_utf_8_summary() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // A Latin-1 summary, which is not valid UTF-8: "Corrigé: fix the parser".
+    let latin1 = (|| -> Result<Oid, git2::Error> {
+        let fix = repo.find_commit(history.fix)?;
+        let buffer = repo.commit_create_buffer(
+            &fix.author(),
+            &fix.committer(),
+            "PLACEHOLDER",
+            &fix.tree()?,
+            &[&fix.parent(0)?],
+        )?;
+        let mut buffer = buffer.to_vec();
+        let start = buffer.len() - "PLACEHOLDER".len();
+        buffer.splice(start.., b"Corrig\xe9: fix the parser".iter().copied());
+        repo.odb()?.write(git2::ObjectType::Commit, &buffer)
+    })()
+    .map_err(|err| err.to_string())?;
+    let latin1 = repo.find_commit(latin1).map_err(|err| err.to_string())?;
+    let classifier = find_bug_fix::MessageClassifier { required: &[] };
+    if latin1.summary().is_some() || !classifier.is_fix(repo, &latin1) {
+        return Err("A fix with a summary that is not UTF-8 was not detected".to_owned());
+    }
+    Ok(())
+}
+
+#[test]
+fn fixes_referencing_a_bug() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // Two more fixes on top of HEAD for a while, one referencing a confirmed bug, one not.
+    let listed = (|| -> Result<Oid, git2::Error> {
+        let listed = commit(
+            repo,
+            "Fix the crash on empty input, closes #12",
+            "A!\nb\nc\nd\nE!\n",
+            &[history.fix],
+            1_600_000_500,
+        )?;
+        let unlisted = commit(
+            repo,
+            "Fix a typo (PROJ-34)",
+            "A!\nb!\nc\nd\nE!\n",
+            &[listed],
+            1_600_000_600,
+        )?;
+        repo.reference("refs/heads/main", unlisted, true, "selftest")?;
+        Ok(listed)
+    })()
+    .map_err(|err| err.to_string())?;
+    let issues = std::collections::HashSet::from([12, 56]);
+    let mut bff = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+        .map_err(|err| err.to_string())?;
+    bff.references_bug(&issues);
+    let lazy: Vec<_> = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+        .map_err(|err| err.to_string())?
+        .lazy()
+        .references_bug(&issues)
+        .collect();
+    repo.reference("refs/heads/main", history.fix, true, "selftest")
+        .map_err(|err| err.to_string())?;
+    if find_bug_fix::issue_numbers("Fix a typo (PROJ-34)") != std::collections::HashSet::from([34])
+    {
+        return Err("Expected PROJ-34 to reference issue 34".to_owned());
+    }
+    let fixes = bff.collect();
+    if lazy != fixes {
+        return Err(format!(
+            "Lazy fixes {:?} differ from eager fixes {:?}",
+            lazy, fixes
+        ));
+    }
+    expect_fixes("fixes referencing a bug", &[listed], &fixes)?;
+    Ok(())
+}
+
+/// The history of `build_history` with a Python file, in a repository of its own so the fix can
+/// be its HEAD. The overlap with the merge has to be found without naming the extension.
+#[test]
+fn python_overlap() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let python =
+        Repository::init(repo.path().join("selftest-python")).map_err(|err| err.to_string())?;
+    let history = (|| -> Result<History, git2::Error> {
+        let email = "selftest@example.com";
+        let commit_py = |summary: &str, content: &str, parents: &[Oid], time: i64| {
+            commit_files(
+                &python,
+                email,
+                summary,
+                &[("main.py", content)],
+                parents,
+                time,
+            )
+        };
+        let o = commit_py("Initial version", "a\nb\nc\nd\ne\n", &[], 1_600_000_000)?;
+        let a = commit_py(
+            "Change the first line",
+            "A\nb\nc\nd\ne\n",
+            &[o],
+            1_600_000_100,
+        )?;
+        let b = commit_py(
+            "Change the last line",
+            "a\nb\nc\nd\nE\n",
+            &[o],
+            1_600_000_200,
+        )?;
+        let m = commit_py("Merge b into a", "A\nb\nc\nd\nE\n", &[a, b], 1_600_000_300)?;
+        let fix = commit_py(
+            "Fix the first line",
+            "A!\nb\nc\nd\nE\n",
+            &[m],
+            1_600_000_400,
+        )?;
+        python.reference("refs/heads/main", fix, true, "selftest")?;
+        python.set_head("refs/heads/main")?;
+        Ok(History { o, a, b, m, fix })
+    })()
+    .map_err(|err| err.to_string())?;
+    let merge = ThreeWayMerge::from_oid_str(
+        &history.o.to_string(),
+        &history.a.to_string(),
+        &history.b.to_string(),
+        &history.m.to_string(),
+    )
+    .map_err(|err| err.to_string())?;
+    let extensions = merge.changed_extensions(&python);
+    if extensions != ["py"] {
+        return Err(format!(
+            "Expected only py as extension, found {:?}",
+            extensions
+        ));
+    }
+    for (extensions, expected_fix) in [
+        (None, history.fix.to_string()),
+        (Some(vec!["java".to_owned()]), String::new()),
+    ] {
+        let options = publish::BugFixOptions {
+            fix_distance: 10,
+            max_fixes: 3,
+            extensions,
+            ..Default::default()
+        };
+        let broken = [(
+            history.o.to_string(),
+            history.a.to_string(),
+            history.b.to_string(),
+            history.m.to_string(),
+        )];
+        let mut csv = vec![];
+        publish::write_bug_fix_csv_overlapping_lines(&mut csv, &python, broken, &options)
+            .map_err(|err| err.to_string())?;
+        let expected = format!("{},{},,\n", history.m, expected_fix);
+        if csv != expected.as_bytes() {
+            return Err(format!(
+                "Expected overlapping fix CSV {:?}, found {:?}",
+                expected,
+                String::from_utf8_lossy(&csv)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `reverted` is changed on both sides but M keeps O's version, `kept` is changed on both sides and
+/// in M, `single` only on A. Both takes the first two, om only `kept`, all any file.
+#[test]
+fn fix_file_scope() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    use find_bug_fix::FixFileScope;
+    let merge = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let email = "selftest@example.com";
+        let files =
+            |reverted, kept, single| [("reverted", reverted), ("kept", kept), ("single", single)];
+        let o = commit_files(
+            repo,
+            email,
+            "o",
+            &files("o\n", "o\n", "o\n"),
+            &[history.o],
+            1_600_040_000,
+        )?;
+        let a = commit_files(
+            repo,
+            email,
+            "a",
+            &files("a\n", "a\n", "a\n"),
+            &[o],
+            1_600_040_100,
+        )?;
+        let b = commit_files(
+            repo,
+            email,
+            "b",
+            &files("b\n", "b\n", "o\n"),
+            &[o],
+            1_600_040_200,
+        )?;
+        let m = commit_files(
+            repo,
+            email,
+            "m",
+            &files("o\n", "ab\n", "a\n"),
+            &[a, b],
+            1_600_040_300,
+        )?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let files = |scope: FixFileScope| {
+        scope.files(repo, &merge).map(|files| {
+            let mut files: Vec<_> = files.into_iter().collect();
+            files.sort();
+            files
+        })
+    };
+    let both = files(FixFileScope::Both);
+    let om = files(FixFileScope::Om);
+    let all = files(FixFileScope::All);
+    if both != Some(vec!["kept".to_string(), "reverted".to_string()])
+        || om != Some(vec!["kept".to_string()])
+        || all.is_some()
+    {
+        return Err(format!(
+            "Unexpected fix file scopes: both {:?}, om {:?}, all {:?}",
+            both, om, all
+        ));
+    }
+    Ok(())
+}
+
+/// A fix by the committer of the merge (in other case) counts as the same author, one by someone
+/// else does not.
+#[test]
+fn same_author() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "same-author")?;
+    let repo = &scratch;
+    let (m, self_fix, other_fix) = (|| -> Result<(Oid, Oid, Oid), git2::Error> {
+        let email = "merger@example.com";
+        let o = commit_by(repo, email, "o", "a\nb\n", &[], 1_600_000_000)?;
+        let a = commit_by(repo, email, "a", "A\nb\n", &[o], 1_600_000_100)?;
+        let b = commit_by(repo, email, "b", "a\nB\n", &[o], 1_600_000_200)?;
+        let m = commit_by(repo, email, "Merge b", "A\nB\n", &[a, b], 1_600_000_300)?;
+        let self_fix = commit_by(
+            repo,
+            "Merger@Example.com",
+            "Fix A",
+            "A!\nB\n",
+            &[m],
+            1_600_000_400,
+        )?;
+        let other_fix = commit_by(
+            repo,
+            "other@example.com",
+            "Fix B",
+            "A!\nB!\n",
+            &[self_fix],
+            1_600_000_500,
+        )?;
+        repo.reference("refs/heads/main", other_fix, true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok((m, self_fix, other_fix))
+    })()
+    .map_err(|err| err.to_string())?;
+    let same = |fix| find_bug_fix::same_author(repo, &m, &fix);
+    if same(self_fix) != Some(true) || same(other_fix) != Some(false) {
+        return Err(format!(
+            "Expected a self fix and a cross-author fix, found {:?} and {:?}",
+            same(self_fix),
+            same(other_fix)
+        ));
+    }
+    let merge = merge_at(repo, m).map_err(|err| err.to_string())?;
+    let broken = [(
+        merge.o.to_string(),
+        merge.a.to_string(),
+        merge.b.to_string(),
+        m.to_string(),
+    )];
+    let options = publish::BugFixOptions {
+        fix_distance: 10,
+        max_fixes: 3,
+        with_same_author: true,
+        ..Default::default()
+    };
+    let mut csv = vec![];
+    publish::write_bug_fix_csv(&mut csv, repo, broken, &options).map_err(|err| err.to_string())?;
+    let expected = format!("{},{},{},,true,false,\n", m, self_fix, other_fix);
+    if csv != expected.as_bytes() {
+        return Err(format!(
+            "Expected same author CSV {:?}, found {:?}",
+            expected,
+            String::from_utf8_lossy(&csv)
+        ));
+    }
+    Ok(())
+}
+
+/// E was written on B, so blaming along first parents takes it to M. A! was written by the fix and
+/// b was in O, neither of which is a merge.
+#[test]
+fn blame_to_merge() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let blame = |line| {
+        git_utils::blame_to_merge(repo, Path::new(FILE), line).map_err(|err| err.to_string())
+    };
+    let found = (blame(5)?, blame(1)?, blame(2)?);
+    if found != (Some(history.m), None, None) {
+        return Err(format!(
+            "Expected only line 5 to come from {}, found {:?}",
+            history.m, found
+        ));
+    }
+    Ok(())
+}
+
+/// A merge whose summary has the fix keywords is a candidate fix, unless merges are excluded.
+#[test]
+fn exclude_merge_fixes() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let fix_merge = (|| -> Result<Oid, git2::Error> {
+        let side = commit(
+            repo,
+            "Reword",
+            "A\nb\nc\nd\nE!\n",
+            &[history.m],
+            1_600_000_450,
+        )?;
+        let fix_merge = commit(
+            repo,
+            "Merge branch 'bugfix'",
+            "A!\nb\nc\nd\nE!\n",
+            &[history.fix, side],
+            1_600_000_500,
+        )?;
+        repo.reference("refs/heads/main", fix_merge, true, "selftest")?;
+        Ok(fix_merge)
+    })()
+    .map_err(|err| err.to_string())?;
+    let fixes = |exclude_merges| -> Result<Vec<Oid>, String> {
+        let mut bff = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+            .map_err(|err| err.to_string())?;
+        bff.msg_contains(&[]);
+        if exclude_merges {
+            bff.exclude_merges();
+        }
+        let mut fixes: Vec<Oid> = bff.collect();
+        fixes.sort();
+        Ok(fixes)
+    };
+    let (all, without_merges) = (fixes(false), fixes(true));
+    repo.reference("refs/heads/main", history.fix, true, "selftest")
+        .map_err(|err| err.to_string())?;
+    let mut expected = vec![history.fix, fix_merge];
+    expected.sort();
+    expect_fixes("keyword fixes with merges", &expected, &all?)?;
+    expect_fixes("exclude merge fixes", &[history.fix], &without_merges?)
+}
+
+/// Descendants come by generation, then time, then hash, and the same on every call. Of the three
+/// children, two were made at the same time.
+#[test]
+fn fix_ordering() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "fix-ordering")?;
+    let repo = &scratch;
+    let (root, expected) = (|| -> Result<(Oid, Vec<Oid>), git2::Error> {
+        let root = commit(repo, "root", "r\n", &[], 1_600_000_000)?;
+        let late = commit(repo, "late", "l\n", &[root], 1_600_000_300)?;
+        let mut tied = [
+            commit(repo, "tied", "t1\n", &[root], 1_600_000_100)?,
+            commit(repo, "tied", "t2\n", &[root], 1_600_000_100)?,
+        ];
+        tied.sort();
+        let grandchild = commit(repo, "grandchild", "g\n", &[tied[1]], 1_600_000_050)?;
+        let top = commit(
+            repo,
+            "top",
+            "x\n",
+            &[late, tied[0], grandchild],
+            1_600_000_500,
+        )?;
+        repo.reference("refs/heads/main", top, true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok((root, vec![tied[0], tied[1], late, grandchild, top]))
+    })()
+    .map_err(|err| err.to_string())?;
+    for _ in 0..3 {
+        let found =
+            git_utils::get_descendants_bounded(repo, root, 10).map_err(|err| err.to_string())?;
+        if found != expected {
+            return Err(format!(
+                "Expected descendants {:?}, found {:?}",
+                expected, found
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Several merges, each with a fix of its own: the bug fix CSV lists them in the order given, the
+/// same with four jobs as with one, even though the workers finish in any order.
+#[test]
+fn parallel_order() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "parallel-order")?;
+    let repo = &scratch;
+    let mut broken = (|| -> Result<Vec<(String, String, String, String)>, git2::Error> {
+        let mut o = commit(repo, "root", "a\nb\n", &[], 1_600_000_000)?;
+        let mut broken = vec![];
+        for round in 0..6 {
+            let time = 1_600_000_000 + 1000 * (round + 1);
+            let a = commit(repo, "a", &format!("a{}\nb\n", round), &[o], time)?;
+            let b = commit(repo, "b", &format!("a\nb{}\n", round), &[o], time + 100)?;
+            let content = format!("a{}\nb{}\n", round, round);
+            let m = commit(repo, "Merge b", &content, &[a, b], time + 200)?;
+            broken.push((o.to_string(), a.to_string(), b.to_string(), m.to_string()));
+            // The fix is the base of the next merge
+            o = commit(
+                repo,
+                "Fix the merge",
+                &format!("{}!\n", content),
+                &[m],
+                time + 300,
+            )?;
+        }
+        repo.reference("refs/heads/main", o, true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok(broken)
+    })()
+    .map_err(|err| err.to_string())?;
+    // Not in the order of the history
+    broken.swap(0, 4);
+    broken.swap(1, 3);
+    let csv_with = |jobs| -> Result<Vec<u8>, String> {
+        let options = publish::BugFixOptions {
+            fix_distance: 1,
+            max_fixes: 3,
+            file_scope: find_bug_fix::FixFileScope::All,
+            jobs,
+            ..Default::default()
+        };
+        let mut csv = vec![];
+        publish::write_bug_fix_csv(&mut csv, repo, broken.clone(), &options)
+            .map_err(|err| err.to_string())?;
+        Ok(csv)
+    };
+    let sequential = csv_with(1)?;
+    let parallel = csv_with(4)?;
+    let lines = String::from_utf8_lossy(&sequential).into_owned();
+    let expected: Vec<String> = broken.iter().map(|(_, _, _, m)| m.clone()).collect();
+    let found: Vec<String> = lines
+        .lines()
+        .map(|line| line.split(',').next().unwrap_or_default().to_string())
+        .collect();
+    let fixed = lines
+        .lines()
+        .all(|line| line.split(',').nth(1).is_some_and(|fix| !fix.is_empty()));
+    if parallel != sequential || found != expected || !fixed {
+        return Err(format!(
+            "Expected one line with a fix per merge, in the order given, found:\n{}\nand with four jobs:\n{}",
+            lines,
+            String::from_utf8_lossy(&parallel)
+        ));
+    }
+    Ok(())
+}
+
+/// Two --require groups both have to match: only the commit naming a fix and an issue is taken.
+#[test]
+fn require_all_groups() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let [_, both, _] = commit_messages(repo, history).map_err(|err| err.to_string())?;
+    let fixes = fixes_requiring(repo, history, &["(?i)fix|bug", r"#\d+"])?;
+    expect_fixes("fix AND issue", &[both], &fixes)
+}
+
+/// Alternatives within one --require group: every commit naming a fix or an issue is taken.
+#[test]
+fn require_any_alternative() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let [issue, both, typo] = commit_messages(repo, history).map_err(|err| err.to_string())?;
+    let fixes = fixes_requiring(repo, history, &[r"(?i)fix|bug|#\d+"])?;
+    expect_fixes("fix OR issue", &[history.fix, issue, both, typo], &fixes)
+}
+
+/// The fix scores 0.8 (keyword, small change, file of the merge), a commit referencing an issue
+/// 0.6 and a tidy up 0.4. Each threshold keeps those scoring at least that, eagerly and lazily. A
+/// scorer of our own only counts the issue reference.
+#[test]
+fn fix_threshold() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = fixture.merge()?;
+    let (issue, tidy) = (|| -> Result<(Oid, Oid), git2::Error> {
+        let issue = commit(
+            repo,
+            "Rename for #7",
+            "A!\nb\nc\nd\nE!\n",
+            &[history.fix],
+            1_600_000_500,
+        )?;
+        let tidy = commit(
+            repo,
+            "Tidy up",
+            "A!\nb\nc\nd\nE!!\n",
+            &[issue],
+            1_600_000_600,
+        )?;
+        repo.reference("refs/heads/main", tidy, true, "selftest")?;
+        Ok((issue, tidy))
+    })()
+    .map_err(|err| err.to_string())?;
+    let merge_files = merge.files_to_consider(repo);
+    let scores: Vec<_> = [history.fix, issue, tidy]
+        .iter()
+        .map(|fix| format!("{:.2}", find_bug_fix::fix_score(repo, fix, &merge_files)))
+        .collect();
+    if scores != ["0.80", "0.60", "0.40"] {
+        return Err(format!("Unexpected fix scores: {:?}", scores));
+    }
+    for (threshold, expected) in [
+        (0.7, vec![history.fix]),
+        (0.5, vec![history.fix, issue]),
+        (0.0, vec![history.fix, issue, tidy]),
+    ] {
+        let mut bff = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+            .map_err(|err| err.to_string())?;
+        bff.min_score(threshold, &merge_files);
+        let lazy: Vec<_> = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+            .map_err(|err| err.to_string())?
+            .lazy()
+            .min_score(threshold, merge_files.clone())
+            .collect();
+        let what = format!("threshold {}", threshold);
+        expect_fixes(&what, &expected, &bff.collect())?;
+        expect_fixes(&what, &expected, &lazy)?;
+    }
+    let mut bff = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+        .map_err(|err| err.to_string())?;
+    let scorers: Vec<Box<dyn find_bug_fix::FixScorer>> = vec![Box::new(find_bug_fix::Weighted {
+        weight: 1.0,
+        classifier: find_bug_fix::IssueReference,
+    })];
+    bff.min_score_with(0.5, &scorers);
+    expect_fixes("own scorer", &[issue], &bff.collect())
+}
+
+/// Takes commits whose hash starts with an even byte for fixes, to check custom classifiers are
+/// used.
+struct ParityClassifier;
+
+impl FixClassifier for ParityClassifier {
+    fn is_fix(&self, _repo: &Repository, commit: &git2::Commit) -> bool {
+        commit.id().as_bytes()[0].is_multiple_of(2)
+    }
+}
+
+/// After the fix, one commit that only references an issue, one that fixes and references one, and
+/// one that only fixes. HEAD is moved to the last of them.
+fn commit_messages(repo: &Repository, history: &History) -> Result<[Oid; 3], git2::Error> {
+    let issue = commit(
+        repo,
+        "Handle empty input, see #12",
+        "A!\nb\nc\nd\nE\n\n",
+        &[history.fix],
+        1_600_000_500,
+    )?;
+    let both = commit(
+        repo,
+        "Fix the crash of #12",
+        "A!\nb!\nc\nd\nE\n\n",
+        &[issue],
+        1_600_000_600,
+    )?;
+    let typo = commit(
+        repo,
+        "Fix a typo",
+        "A!\nb!\nc!\nd\nE\n\n",
+        &[both],
+        1_600_000_700,
+    )?;
+    repo.reference("refs/heads/main", typo, true, "selftest")?;
+    Ok([issue, both, typo])
+}
+
+/// The fixes of M whose message matches every one of the given patterns.
+fn fixes_requiring(
+    repo: &Repository,
+    history: &History,
+    patterns: &[&str],
+) -> Result<Vec<Oid>, String> {
+    let required = patterns
+        .iter()
+        .map(|pattern| regex::Regex::new(pattern))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+    let mut bff = BugFixFinder::find_within(repo, &history.m.to_string(), 10)
+        .map_err(|err| err.to_string())?;
+    bff.msg_contains(&required);
+    Ok(bff.collect())
+}
@@ -0,0 +1,544 @@
+//! What only the command line does, checked by running the binary.
+
+mod common;
+
+use common::{commit, run_cli, scratch_repo, Fixture};
+use git2::Oid;
+use three_way_merge_finder::{git_utils, merge};
+
+/// --before and --after filter the same whether given as a date or as epoch seconds. M was made at
+/// 1600000300, i.e., 2020-09-13T12:31:40Z.
+#[test]
+fn date_bounds() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let find_merge = |bound: &str, time: &str| -> Result<Vec<u8>, String> {
+        let output = run_cli(&[
+            "find-merge".as_ref(),
+            workdir.as_os_str(),
+            bound.as_ref(),
+            time.as_ref(),
+            "--quiet".as_ref(),
+        ])?;
+        if !output.status.success() {
+            return Err(format!(
+                "find-merge {} {} failed: {:?}",
+                bound, time, output
+            ));
+        }
+        Ok(output.stdout)
+    };
+    let mut line_counts = vec![];
+    for (bound, date, epoch) in [
+        ("--before", "2020-09-13T12:31:00Z", "1600000260"),
+        ("--before", "2020-09-13 12:32:00", "1600000320"),
+        ("--after", "2020-09-13T12:31:00", "1600000260"),
+        ("--after", "2020-09-14", "1600041600"),
+    ] {
+        let by_date = find_merge(bound, date)?;
+        if by_date != find_merge(bound, epoch)? {
+            return Err(format!(
+                "{} {} and {} {} filter differently",
+                bound, date, bound, epoch
+            ));
+        }
+        line_counts.push(
+            by_date
+                .split(|&byte| byte == b'\n')
+                .filter(|line| !line.is_empty())
+                .count(),
+        );
+    }
+    if line_counts != [1, 2, 2, 1] {
+        return Err(format!(
+            "Unexpected number of lines with --before/--after: {:?}",
+            line_counts
+        ));
+    }
+    Ok(())
+}
+
+/// With HEAD detached at the fix, the merge is still found, by the library and by the binary.
+#[test]
+fn detached_head() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let attached = run_cli(&[
+        "find-merge".as_ref(),
+        workdir.as_os_str(),
+        "--quiet".as_ref(),
+    ])?;
+    repo.set_head_detached(history.fix)
+        .map_err(|err| err.to_string())?;
+    let found = git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL)
+        .map(|revwalk| merge::find_merges(repo, revwalk, None));
+    let detached = run_cli(&[
+        "find-merge".as_ref(),
+        workdir.as_os_str(),
+        "--quiet".as_ref(),
+    ]);
+    repo.set_head("refs/heads/main")
+        .map_err(|err| err.to_string())?;
+    let found: Vec<Oid> = found
+        .map_err(|err| err.to_string())?
+        .iter()
+        .map(|merge| merge.m)
+        .collect();
+    let detached = detached?;
+    if found != [history.m] || !detached.status.success() || detached.stdout != attached.stdout {
+        return Err(format!(
+            "With a detached HEAD, found {:?} and {:?}",
+            found,
+            String::from_utf8_lossy(&detached.stdout)
+        ));
+    }
+    Ok(())
+}
+
+/// Run again with the CSV of the first run as manifest, find-merge only appends the merge made
+/// since, without repeating the header or the first merge.
+#[test]
+fn existing_manifest() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "existing-manifest")?;
+    let workdir = scratch.workdir().ok_or("The scratch repository is bare")?;
+    let csv = repo.path().join("selftest-manifest.csv");
+    let _ = std::fs::remove_file(&csv);
+    let find_merge = |incremental: bool| -> Result<String, String> {
+        let mut args = vec![
+            "find-merge".as_ref(),
+            workdir.as_os_str(),
+            "--quiet".as_ref(),
+            "--output-file".as_ref(),
+            csv.as_os_str(),
+        ];
+        if incremental {
+            args.extend(["--existing-manifest".as_ref(), csv.as_os_str()]);
+        }
+        let output = run_cli(&args)?;
+        if !output.status.success() {
+            return Err(format!("find-merge failed: {:?}", output));
+        }
+        std::fs::read_to_string(&csv).map_err(|err| err.to_string())
+    };
+    let merge_on = |parent: Oid, round: i64| -> Result<Oid, git2::Error> {
+        let repo = &scratch;
+        let time = 1_600_000_000 + 1000 * round;
+        let a = commit(repo, "a", &format!("a{}\n", round), &[parent], time + 100)?;
+        let b = commit(repo, "b", &format!("b{}\n", round), &[parent], time + 200)?;
+        let m = commit(
+            repo,
+            "Merge b",
+            &format!("m{}\n", round),
+            &[a, b],
+            time + 300,
+        )?;
+        repo.reference("refs/heads/main", m, true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok(m)
+    };
+    let root =
+        commit(&scratch, "root", "r\n", &[], 1_600_000_000).map_err(|err| err.to_string())?;
+    let first = merge_on(root, 1).map_err(|err| err.to_string())?;
+    let first_run = find_merge(false)?;
+    let second = merge_on(first, 2).map_err(|err| err.to_string())?;
+    let second_run = find_merge(true)?;
+    let m_column = |csv: &str| -> Vec<String> {
+        csv.lines()
+            .filter_map(|line| line.split(',').nth(3))
+            .map(str::to_string)
+            .collect()
+    };
+    let expected = ["M".to_string(), first.to_string(), second.to_string()];
+    if m_column(&first_run) != expected[..2] || m_column(&second_run) != expected {
+        return Err(format!("Unexpected CSV after two runs:\n{}", second_run));
+    }
+    Ok(())
+}
+
+/// A merge on a deleted branch that only a tag still points to is found with --include-tags or
+/// --include-all-refs, not without.
+#[test]
+fn include_tags() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "include-tags")?;
+    let tagged = (|| -> Result<Oid, git2::Error> {
+        let repo = &scratch;
+        let root = commit(repo, "root", "r\n", &[], 1_600_000_000)?;
+        let a = commit(repo, "a", "a\n", &[root], 1_600_000_100)?;
+        let b = commit(repo, "b", "b\n", &[root], 1_600_000_200)?;
+        let tagged = commit(repo, "Merge b", "ab\n", &[a, b], 1_600_000_300)?;
+        repo.tag_lightweight("release-1", &repo.find_object(tagged, None)?, true)?;
+        let main = commit(repo, "main", "m\n", &[root], 1_600_000_400)?;
+        repo.reference("refs/heads/main", main, true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok(tagged)
+    })()
+    .map_err(|err| err.to_string())?;
+    let workdir = scratch.workdir().ok_or("The scratch repository is bare")?;
+    for (flag, expect_found) in [
+        (None, false),
+        (Some("--include-tags"), true),
+        (Some("--include-all-refs"), true),
+    ] {
+        let mut args = vec![
+            "find-merge".as_ref(),
+            workdir.as_os_str(),
+            "--quiet".as_ref(),
+        ];
+        args.extend(flag.map(std::ffi::OsStr::new));
+        let output = run_cli(&args)?;
+        let found = String::from_utf8_lossy(&output.stdout).contains(&tagged.to_string());
+        if !output.status.success() || found != expect_found {
+            return Err(format!(
+                "With {:?}, expected the tagged merge found: {}, output {:?}",
+                flag, expect_found, output
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Pointed at a path that does not exist or at a folder that is no repository, the binary exits
+/// with code 1 and tells which of the two it is.
+#[test]
+fn not_a_repo() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let folder = repo.path().join("selftest-not-a-repo");
+    std::fs::create_dir_all(&folder).map_err(|err| err.to_string())?;
+    let missing = folder.join("missing");
+    for (path, message) in [
+        (&missing, "does not exist"),
+        (&folder, "is not a Git repository"),
+    ] {
+        let output = run_cli(&["find-merge".as_ref(), path.as_os_str()])?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.code() != Some(1) || !stderr.contains(message) {
+            return Err(format!(
+                "Expected exit code 1 and {:?} for {}, found {:?} and {:?}",
+                message,
+                path.display(),
+                output.status.code(),
+                stderr
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// With --output-file, find-merge writes to the file (creating its folder) what it would have
+/// written to STDOUT.
+#[test]
+fn output_file() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let output_file = repo.path().join("selftest-output-file").join("merges.csv");
+    let _ = std::fs::remove_dir_all(repo.path().join("selftest-output-file"));
+    let stdout = run_cli(&["find-merge".as_ref(), workdir.as_os_str()])?;
+    let to_file = run_cli(&[
+        "find-merge".as_ref(),
+        workdir.as_os_str(),
+        "--output-file".as_ref(),
+        output_file.as_os_str(),
+    ])?;
+    if !stdout.status.success() || !to_file.status.success() {
+        return Err(format!("find-merge failed: {:?} {:?}", stdout, to_file));
+    }
+    let written = std::fs::read(&output_file).map_err(|err| err.to_string())?;
+    if stdout.stdout.is_empty() || written != stdout.stdout || !to_file.stdout.is_empty() {
+        return Err(format!(
+            "Expected {:?} in {}, found {:?} and {:?} on STDOUT",
+            String::from_utf8_lossy(&stdout.stdout),
+            output_file.display(),
+            String::from_utf8_lossy(&written),
+            String::from_utf8_lossy(&to_file.stdout)
+        ));
+    }
+    Ok(())
+}
+
+/// find-bug-fix --progress reports the number of lines in the commitlist as the total.
+#[test]
+fn progress_total() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let commitlist = repo.path().join("selftest-progress.csv");
+    let line = format!("{},{},{},{}\n", history.o, history.a, history.b, history.m);
+    std::fs::write(&commitlist, line.repeat(3)).map_err(|err| err.to_string())?;
+    let output = run_cli(&[
+        "find-bug-fix".as_ref(),
+        workdir.as_os_str(),
+        "--commitlist".as_ref(),
+        commitlist.as_os_str(),
+        "--progress".as_ref(),
+    ])?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.contains("Processed 3 / 3 merges") {
+        return Err(format!("Expected a total of 3 merges, found {:?}", stderr));
+    }
+    Ok(())
+}
+
+/// find-merge reports how many merges it examined on STDERR once one of them has no merge base, a
+/// routine warning that --quiet silences. The CSV on STDOUT stays the same. Without such merges,
+/// there is nothing to report.
+#[test]
+fn quiet() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let find_merge = |quiet: bool| {
+        let mut args = vec!["find-merge".as_ref(), workdir.as_os_str()];
+        if quiet {
+            args.push("--quiet".as_ref());
+        }
+        run_cli(&args)
+    };
+    let clean = find_merge(false)?;
+    if !clean.status.success() || !clean.stderr.is_empty() {
+        return Err(format!(
+            "Expected nothing on STDERR, found {:?}",
+            String::from_utf8_lossy(&clean.stderr)
+        ));
+    }
+    commit(repo, "Unrelated root", "x\n", &[], 1_600_000_500)
+        .and_then(|root| {
+            commit(
+                repo,
+                "Merge unrelated histories",
+                "x\n",
+                &[history.fix, root],
+                1_600_000_600,
+            )
+        })
+        .and_then(|unrelated| repo.reference("refs/heads/main", unrelated, true, "test"))
+        .map_err(|err| err.to_string())?;
+    let loud = find_merge(false)?;
+    let quiet = find_merge(true)?;
+    if !loud.status.success() || !quiet.status.success() {
+        return Err(format!("find-merge failed: {:?} {:?}", loud, quiet));
+    }
+    if !String::from_utf8_lossy(&loud.stderr).contains("1 without a merge base")
+        || !quiet.stderr.is_empty()
+        || loud.stdout != quiet.stdout
+        || loud.stdout != clean.stdout
+    {
+        return Err(format!(
+            "Expected --quiet to only silence STDERR {:?}, found {:?}",
+            String::from_utf8_lossy(&loud.stderr),
+            String::from_utf8_lossy(&quiet.stderr)
+        ));
+    }
+    Ok(())
+}
+
+/// With --distinct-o, a merge of a branch into the unchanged commit it started from is dropped,
+/// or with --trivial-mode label, kept and labelled trivial.
+#[test]
+fn trivial_mode() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "trivial-mode")?;
+    let (trivial, real) = (|| -> Result<(Oid, Oid), git2::Error> {
+        let repo = &scratch;
+        let root = commit(repo, "root", "r\n", &[], 1_600_000_000)?;
+        let branch = commit(repo, "branch", "b\n", &[root], 1_600_000_100)?;
+        let trivial = commit(repo, "Merge branch", "b\n", &[root, branch], 1_600_000_200)?;
+        let a = commit(repo, "a", "a\n", &[trivial], 1_600_000_300)?;
+        let b = commit(repo, "b", "c\n", &[trivial], 1_600_000_400)?;
+        let real = commit(repo, "Merge b", "ac\n", &[a, b], 1_600_000_500)?;
+        repo.reference("refs/heads/main", real, true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok((trivial, real))
+    })()
+    .map_err(|err| err.to_string())?;
+    let workdir = scratch.workdir().ok_or("The scratch repository is bare")?;
+    let find_merge = |mode: &str| -> Result<Vec<String>, String> {
+        let output = run_cli(&[
+            "find-merge".as_ref(),
+            workdir.as_os_str(),
+            "--quiet".as_ref(),
+            "--distinct-o".as_ref(),
+            "--trivial-mode".as_ref(),
+            mode.as_ref(),
+        ])?;
+        if !output.status.success() {
+            return Err(format!(
+                "find-merge --trivial-mode {} failed: {:?}",
+                mode, output
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    };
+    let dropped = find_merge("drop")?;
+    let labelled = find_merge("label")?;
+    // The trivial merge is O of the other one, so only the M column tells them apart
+    let line_of = |lines: &[String], merge: Oid| {
+        lines
+            .iter()
+            .find(|line| line.split(',').nth(3) == Some(&merge.to_string()))
+            .cloned()
+    };
+    let kept_trivial = line_of(&labelled, trivial);
+    let kept_real = line_of(&labelled, real);
+    if dropped.len() != 2
+        || line_of(&dropped, trivial).is_some()
+        || line_of(&dropped, real).is_none()
+        || !labelled[0].ends_with(",trivial")
+        || !kept_trivial.is_some_and(|line| line.ends_with(",true"))
+        || !kept_real.is_some_and(|line| line.ends_with(",false"))
+    {
+        return Err(format!(
+            "Unexpected trivial merges, dropped: {:?}, labelled: {:?}",
+            dropped, labelled
+        ));
+    }
+    Ok(())
+}
+
+/// find-merge's own CSV, with its run metadata and header, works as commitlist for find-bug-fix.
+/// Lines that are no O,A,B,M hashes are skipped, or end the run with --fail-fast.
+#[test]
+fn commitlist_from_find_merge() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let merges = run_cli(&[
+        "find-merge".as_ref(),
+        workdir.as_os_str(),
+        "--with-run-meta".as_ref(),
+        "--quiet".as_ref(),
+    ])?;
+    let merges = String::from_utf8_lossy(&merges.stdout);
+    if !merges.starts_with("# run_meta=") {
+        return Err(format!("Expected run metadata, found {:?}", merges));
+    }
+    let commitlist = repo.path().join("selftest-commitlist.csv");
+    let find_bug_fix = |content: &str, policy: &str| {
+        std::fs::write(&commitlist, content).map_err(|err| err.to_string())?;
+        run_cli(&[
+            "find-bug-fix".as_ref(),
+            workdir.as_os_str(),
+            "--commitlist".as_ref(),
+            commitlist.as_os_str(),
+            policy.as_ref(),
+        ])
+    };
+    let expected = format!("{},{},,\n", history.m, history.fix);
+    let output = find_bug_fix(&merges, "--fail-fast")?;
+    if !output.status.success() || output.stdout != expected.as_bytes() {
+        return Err(format!(
+            "Unexpected fixes for find-merge's CSV: {:?}",
+            output
+        ));
+    }
+    let broken = format!("{}not,a,merge\nshort,line\n", merges);
+    let output = find_bug_fix(&broken, "--continue-on-error")?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success()
+        || output.stdout != expected.as_bytes()
+        || stderr.matches("Skipping").count() != 2
+    {
+        return Err(format!("Unexpected run over broken lines: {:?}", output));
+    }
+    let output = find_bug_fix(&broken, "--fail-fast")?;
+    if output.status.success() {
+        return Err("Expected --fail-fast to end the run on a broken line".to_owned());
+    }
+    Ok(())
+}
+
+/// A fresh dump comes with a run_meta.json next to the merge folders. find-bug-fix leaves it
+/// alone, even with --fail-fast, and adds the fix to the merge folder.
+#[test]
+fn commitfolder_of_fresh_dump() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let dump = repo.path().join("selftest-fresh-dump");
+    let output = run_cli(&[
+        "find-merge".as_ref(),
+        workdir.as_os_str(),
+        "--output-folder".as_ref(),
+        dump.as_os_str(),
+        "--quiet".as_ref(),
+    ])?;
+    if !output.status.success() || !dump.join("run_meta.json").is_file() {
+        return Err(format!("Expected a dump with run metadata: {:?}", output));
+    }
+    let output = run_cli(&[
+        "find-bug-fix".as_ref(),
+        workdir.as_os_str(),
+        "--commitfolder".as_ref(),
+        dump.as_os_str(),
+        "--fail-fast".as_ref(),
+    ])?;
+    let expected = format!("{},{},,\n", history.m, history.fix);
+    if !output.status.success()
+        || !output.stderr.is_empty()
+        || output.stdout != expected.as_bytes()
+        || !dump.join(history.m.to_string()).join("bf1").is_dir()
+    {
+        return Err(format!("Unexpected run over a fresh dump: {:?}", output));
+    }
+    Ok(())
+}
+
+/// The fix changes the first line, which A changed before the merge. --explain-overlap names that
+/// line and A, and without it nothing is reported.
+#[test]
+fn explain_overlap() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let workdir = repo.workdir().ok_or("The test repository is bare")?;
+    let commitlist = repo.path().join("selftest-explain.csv");
+    let merge = format!("{},{},{},{}\n", history.o, history.a, history.b, history.m);
+    std::fs::write(&commitlist, merge).map_err(|err| err.to_string())?;
+    let find_bug_fix = |explain: bool| {
+        let mut args = vec![
+            "find-bug-fix".as_ref(),
+            workdir.as_os_str(),
+            "--touches-same-line".as_ref(),
+            "--commitlist".as_ref(),
+            commitlist.as_os_str(),
+        ];
+        if explain {
+            args.push("--explain-overlap".as_ref());
+        }
+        run_cli(&args)
+    };
+    let expected = format!("{},{},,\n", history.m, history.fix);
+    let explanation = format!(
+        "Fix {} of merge {} changes Main.java:1, last changed in {}\n",
+        history.fix, history.m, history.a
+    );
+    let output = find_bug_fix(true)?;
+    if !output.status.success()
+        || output.stdout != expected.as_bytes()
+        || output.stderr != explanation.as_bytes()
+    {
+        return Err(format!(
+            "Unexpected explanation of the overlap: {:?}",
+            output
+        ));
+    }
+    let output = find_bug_fix(false)?;
+    if !output.status.success() || output.stdout != expected.as_bytes() || !output.stderr.is_empty()
+    {
+        return Err(format!(
+            "Expected no explanation without the flag: {:?}",
+            output
+        ));
+    }
+    Ok(())
+}
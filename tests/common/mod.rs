@@ -0,0 +1,346 @@
+//! A small synthetic repository with a known three way merge and a known bug fix, shared by the
+//! integration tests, and helpers for growing it.
+//!
+//! The history looks like this, with HEAD at the fix:
+//!
+//! ```text
+//! o ── a ──┐
+//!  \       m ── fix
+//!   └─ b ──┘
+//! ```
+
+#![allow(dead_code)]
+
+use git2::{Oid, Repository, Signature};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use three_way_merge_finder::find_bug_fix::BugFixFinder;
+use three_way_merge_finder::merge::{BaseStrategy, ThreeWayMerge};
+
+pub const FILE: &str = "Main.java";
+
+/// The commits in the synthetic repository.
+pub struct History {
+    pub o: Oid,
+    pub a: Oid,
+    pub b: Oid,
+    pub m: Oid,
+    pub fix: Oid,
+}
+
+/// The synthetic repository in a temporary folder of its own, removed again when dropped.
+pub struct Fixture {
+    folder: PathBuf,
+    pub repo: Repository,
+    pub history: History,
+}
+
+impl Fixture {
+    pub fn new() -> Result<Fixture, String> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let folder = std::env::temp_dir().join(format!(
+            "three-way-merge-finder-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        if folder.exists() {
+            std::fs::remove_dir_all(&folder).map_err(|err| err.to_string())?;
+        }
+        let repo = Repository::init(&folder).map_err(|err| err.to_string())?;
+        let history = build_history(&repo).map_err(|err| err.to_string())?;
+        Ok(Fixture {
+            folder,
+            repo,
+            history,
+        })
+    }
+
+    /// The merge M, with its merge base as O.
+    pub fn merge(&self) -> Result<ThreeWayMerge, String> {
+        merge_at(&self.repo, self.history.m).map_err(|err| err.to_string())
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.folder) {
+            eprintln!("Could not remove {:?}: {}", self.folder, err);
+        }
+    }
+}
+
+/// A merges a side branch that changes Side.java; only B's own commit touches it.
+pub fn side_branch_merge(
+    repo: &Repository,
+    history: &History,
+) -> Result<ThreeWayMerge, git2::Error> {
+    let email = "selftest@example.com";
+    let side = commit_files(
+        repo,
+        email,
+        "side",
+        &[(FILE, "0\n"), ("Side.java", "1\n")],
+        &[history.o],
+        1_600_003_000,
+    )?;
+    let a1 = commit(repo, "a1", "1\n", &[history.o], 1_600_003_100)?;
+    let a2 = commit_files(
+        repo,
+        email,
+        "merge side",
+        &[(FILE, "1\n"), ("Side.java", "1\n")],
+        &[a1, side],
+        1_600_003_200,
+    )?;
+    let b = commit_files(
+        repo,
+        email,
+        "b",
+        &[(FILE, "2\n"), ("Side.java", "2\n")],
+        &[history.o],
+        1_600_003_300,
+    )?;
+    let m = commit_files(
+        repo,
+        email,
+        "m",
+        &[(FILE, "3\n"), ("Side.java", "3\n")],
+        &[a2, b],
+        1_600_003_400,
+    )?;
+    merge_at(repo, m)
+}
+
+/// Both sides change the second line of every file. M takes B's X, blends Y, and keeps both
+/// sides of Z.
+pub fn conflicting_merge(repo: &Repository) -> Result<ThreeWayMerge, git2::Error> {
+    let email = "selftest@example.com";
+    let lines = |second: &str| format!("1\n{}\n3\n", second);
+    let (base, ours, theirs) = (lines("2"), lines("a"), lines("b"));
+    let o = commit_files(
+        repo,
+        email,
+        "o",
+        &[("X.java", &base), ("Y.java", &base), ("Z.java", &base)],
+        &[],
+        1_600_005_000,
+    )?;
+    let a = commit_files(
+        repo,
+        email,
+        "a",
+        &[("X.java", &ours), ("Y.java", &ours), ("Z.java", &ours)],
+        &[o],
+        1_600_005_100,
+    )?;
+    let b = commit_files(
+        repo,
+        email,
+        "b",
+        &[
+            ("X.java", &theirs),
+            ("Y.java", &theirs),
+            ("Z.java", &theirs),
+        ],
+        &[o],
+        1_600_005_200,
+    )?;
+    let m = commit_files(
+        repo,
+        email,
+        "m",
+        &[
+            ("X.java", &theirs),
+            ("Y.java", &lines("ab")),
+            ("Z.java", "1\na\nb\n3\n"),
+        ],
+        &[a, b],
+        1_600_005_300,
+    )?;
+    merge_at(repo, m)
+}
+
+/// Follows what `publish::write_bug_fix_csv` (or its overlapping lines variant) does.
+pub fn find_fixes(
+    repo: &Repository,
+    merge: &ThreeWayMerge,
+    touches_same_line: bool,
+) -> Result<Vec<Oid>, String> {
+    let mut bff =
+        BugFixFinder::find_within(repo, &merge.m.to_string(), 10).map_err(|err| err.to_string())?;
+    bff.msg_contains(&[]);
+    if touches_same_line {
+        bff.changed_same_line_in_ext(repo, merge, &["java"], 0, false);
+    } else {
+        bff.changed_files(repo, merge.files_changed_in_both_branches(repo));
+    }
+    let fixes = bff.collect();
+
+    // The lazy filters have to agree with the eager ones.
+    let mut lazy = BugFixFinder::find_within(repo, &merge.m.to_string(), 10)
+        .map_err(|err| err.to_string())?
+        .lazy()
+        .msg_contains(&[]);
+    if touches_same_line {
+        lazy = lazy.changed_same_line_in_ext(merge, &["java"], 0, false);
+    } else {
+        lazy = lazy.changed_files(merge.files_changed_in_both_branches(repo));
+    }
+    let lazy: Vec<_> = lazy.collect();
+    if lazy != fixes {
+        return Err(format!(
+            "Lazy fixes {:?} differ from eager fixes {:?}",
+            lazy, fixes
+        ));
+    }
+    Ok(fixes)
+}
+
+pub fn expect(what: &str, expected: Oid, found: Oid) -> Result<(), String> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected {} to be {}, found {}",
+            what, expected, found
+        ))
+    }
+}
+
+pub fn expect_fixes(what: &str, expected: &[Oid], found: &[Oid]) -> Result<(), String> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: expected fixes {:?}, found {:?}",
+            what, expected, found
+        ))
+    }
+}
+
+pub fn build_history(repo: &Repository) -> Result<History, git2::Error> {
+    let o = commit(
+        repo,
+        "Initial version",
+        "a\nb\nc\nd\ne\n",
+        &[],
+        1_600_000_000,
+    )?;
+    let a = commit(
+        repo,
+        "Change the first line",
+        "A\nb\nc\nd\ne\n",
+        &[o],
+        1_600_000_100,
+    )?;
+    let b = commit(
+        repo,
+        "Change the last line",
+        "a\nb\nc\nd\nE\n",
+        &[o],
+        1_600_000_200,
+    )?;
+    let m = commit(
+        repo,
+        "Merge b into a",
+        "A\nb\nc\nd\nE\n",
+        &[a, b],
+        1_600_000_300,
+    )?;
+    let fix = commit(
+        repo,
+        "Fix the first line",
+        "A!\nb\nc\nd\nE\n",
+        &[m],
+        1_600_000_400,
+    )?;
+    repo.reference("refs/heads/main", fix, true, "selftest")?;
+    repo.set_head("refs/heads/main")?;
+    Ok(History { o, a, b, m, fix })
+}
+
+/// Creates a commit whose tree only holds `FILE` with the given content.
+pub fn commit(
+    repo: &Repository,
+    summary: &str,
+    content: &str,
+    parents: &[Oid],
+    time: i64,
+) -> Result<Oid, git2::Error> {
+    commit_by(
+        repo,
+        "selftest@example.com",
+        summary,
+        content,
+        parents,
+        time,
+    )
+}
+
+/// Same as `commit`, with the given email address as author and committer.
+pub fn commit_by(
+    repo: &Repository,
+    email: &str,
+    summary: &str,
+    content: &str,
+    parents: &[Oid],
+    time: i64,
+) -> Result<Oid, git2::Error> {
+    commit_files(repo, email, summary, &[(FILE, content)], parents, time)
+}
+
+/// Same as `commit_by`, with a tree holding the given files and their contents. Paths may contain
+/// folders, e.g., `lib/util.rs`.
+pub fn commit_files(
+    repo: &Repository,
+    email: &str,
+    summary: &str,
+    files: &[(&str, &str)],
+    parents: &[Oid],
+    time: i64,
+) -> Result<Oid, git2::Error> {
+    let tree = repo.find_tree(write_tree(repo, files)?)?;
+    let parents = parents
+        .iter()
+        .map(|parent| repo.find_commit(*parent))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parents: Vec<_> = parents.iter().collect();
+    let signature = Signature::new("Self Test", email, &git2::Time::new(time, 0))?;
+    repo.commit(None, &signature, &signature, summary, &tree, &parents)
+}
+
+/// Runs the binary with the given arguments, for checking what only the command line does.
+pub fn run_cli<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> Result<std::process::Output, String> {
+    std::process::Command::new(env!("CARGO_BIN_EXE_three-way-merge-finder"))
+        .args(args)
+        .output()
+        .map_err(|err| err.to_string())
+}
+
+/// The merge M, with its merge base as O.
+pub fn merge_at(repo: &Repository, m: Oid) -> Result<ThreeWayMerge, git2::Error> {
+    ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+}
+
+/// A fresh repository for tests that need a history of their own as HEAD, next to the main one.
+pub fn scratch_repo(repo: &Repository, name: &str) -> Result<Repository, String> {
+    Repository::init(repo.path().join(format!("selftest-{}", name))).map_err(|err| err.to_string())
+}
+
+/// Writes a tree holding the given files, with subtrees for the folders in their paths.
+pub fn write_tree(repo: &Repository, files: &[(&str, &str)]) -> Result<Oid, git2::Error> {
+    let mut builder = repo.treebuilder(None)?;
+    let mut folders: std::collections::BTreeMap<&str, Vec<(&str, &str)>> = Default::default();
+    for &(path, content) in files {
+        match path.split_once('/') {
+            Some((folder, rest)) => folders.entry(folder).or_default().push((rest, content)),
+            None => {
+                builder.insert(path, repo.blob(content.as_bytes())?, 0o100644)?;
+            }
+        }
+    }
+    for (folder, files) in folders {
+        builder.insert(folder, write_tree(repo, &files)?, 0o040000)?;
+    }
+    builder.write()
+}
@@ -0,0 +1,40 @@
+//! The global error policy, in a test binary of its own so no other test sees it change.
+
+mod common;
+
+use common::Fixture;
+use three_way_merge_finder::verbosity::ErrorPolicy;
+use three_way_merge_finder::{publish, verbosity};
+
+#[test]
+fn error_policy() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // The fix is no merge, so this is a single bad line of a commitlist.
+    let bad_line = [(
+        history.o.to_string(),
+        history.a.to_string(),
+        history.b.to_string(),
+        history.fix.to_string(),
+    )];
+    let options = publish::BugFixOptions::default();
+    let mut aborted = vec![];
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    for policy in [ErrorPolicy::ContinueOnError, ErrorPolicy::FailFast] {
+        verbosity::set_error_policy(policy);
+        let run = std::panic::catch_unwind(|| {
+            publish::write_bug_fix_csv(&mut Vec::new(), repo, bad_line.clone(), &options)
+        });
+        aborted.push(run.is_err());
+    }
+    std::panic::set_hook(hook);
+    verbosity::set_error_policy(ErrorPolicy::ContinueOnError);
+    if aborted != [false, true] {
+        return Err(format!(
+            "Expected only fail-fast to abort on a bad commit, aborted: {:?}",
+            aborted
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,693 @@
+//! Finding merges: walks, ranges, filters on the commits, and the merge base cache.
+
+mod common;
+
+use common::{
+    commit, conflicting_merge, expect, merge_at, scratch_repo, side_branch_merge, Fixture, History,
+};
+use git2::{Oid, Repository, Signature};
+use three_way_merge_finder::merge::{BaseStrategy, ThreeWayMerge};
+use three_way_merge_finder::{git_utils, merge};
+
+#[test]
+fn find_merge() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    let merges = merge::find_merges(repo, revwalk, None);
+    if merges.len() != 1 {
+        return Err(format!("Expected 1 merge, found {}", merges.len()));
+    }
+    let merge = &merges[0];
+    expect("O", history.o, merge.o)?;
+    expect("A", history.a, merge.a)?;
+    expect("B", history.b, merge.b)?;
+    expect("M", history.m, merge.m)?;
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn find_merge_stream() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let streamed = stream_merges(repo)?;
+    if streamed.len() != 1 {
+        return Err(format!(
+            "Expected 1 streamed merge, found {}",
+            streamed.len()
+        ));
+    }
+    expect("streamed M", history.m, streamed[0].m)?;
+    Ok(())
+}
+
+#[test]
+fn find_merge_range() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    for (range, expected) in [
+        (format!("{}..{}", history.o, history.fix), 1),
+        (format!("{}..{}", history.m, history.fix), 0),
+        (format!("{}...{}", history.a, history.b), 0),
+        (format!("{}...{}", history.a, history.fix), 1),
+    ] {
+        let revwalk = git_utils::create_revwalk_spec(repo, git2::Sort::TOPOLOGICAL, &range)
+            .map_err(|err| err.to_string())?;
+        let found = merge::find_merges(repo, revwalk, None).len();
+        if found != expected {
+            return Err(format!(
+                "Expected {} merges in {}, found {}",
+                expected, range, found
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn merge_stats() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let unrelated = merge_unrelated(repo, history).map_err(|err| err.to_string())?;
+    let mut revwalk = repo.revwalk().map_err(|err| err.to_string())?;
+    revwalk.push(unrelated).map_err(|err| err.to_string())?;
+    let (merges, stats) = merge::find_merges_with_stats(repo, revwalk, None, None);
+    if merges.len() != 1 || stats.examined != 2 || stats.no_base != 1 || stats.multiple_bases != 0 {
+        return Err(format!("Unexpected merge stats: {}", stats));
+    }
+    Ok(())
+}
+
+/// x and y each merge the other's first commit, then M merges both: x1 and y1 are then both best
+/// common ancestors of M's parents. Counted the same with or without a cache.
+#[test]
+fn multiple_merge_bases() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let m = (|| -> Result<Oid, git2::Error> {
+        let x1 = commit(repo, "x1", "x\n", &[history.o], 1_600_001_000)?;
+        let y1 = commit(repo, "y1", "y\n", &[history.o], 1_600_001_100)?;
+        let x2 = commit(repo, "x2", "xy\n", &[x1, y1], 1_600_001_200)?;
+        let y2 = commit(repo, "y2", "yx\n", &[y1, x1], 1_600_001_300)?;
+        commit(repo, "m", "xyx\n", &[x2, y2], 1_600_001_400)
+    })()
+    .map_err(|err| err.to_string())?;
+    let mut cache = merge::MergeBaseCache::default();
+    for cache in [None, Some(&mut cache)] {
+        let revwalk = walk_from(repo, &[m]).map_err(|err| err.to_string())?;
+        let (merges, stats) = merge::find_merges_with_stats(repo, revwalk, None, cache);
+        if merges.len() != 3
+            || stats.examined != 3
+            || stats.no_base != 0
+            || stats.multiple_bases != 1
+        {
+            return Err(format!("Unexpected merge stats: {}", stats));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn merge_base_cache() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let unrelated = merge_unrelated(repo, history).map_err(|err| err.to_string())?;
+    // A second run with the saved cache finds the same merges without computing a single base.
+    // Entries for commits missing from the repository are dropped.
+    let cache_file = repo.path().join("selftest-merge-base-cache.csv");
+    let cached_run = |cache: &mut merge::MergeBaseCache| {
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(unrelated)?;
+        let merges = merge::find_merges_cached(repo, revwalk, None, cache);
+        Ok::<_, git2::Error>(
+            merges
+                .iter()
+                .map(ThreeWayMerge::to_csv_line)
+                .collect::<Vec<_>>(),
+        )
+    };
+    let mut cache =
+        merge::MergeBaseCache::load(repo, &cache_file).map_err(|err| err.to_string())?;
+    let first = cached_run(&mut cache).map_err(|err| err.to_string())?;
+    cache.save(&cache_file).map_err(|err| err.to_string())?;
+    let stale = format!("{0},{0},{0},false\n", "1".repeat(40));
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&cache_file)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, stale.as_bytes()))
+        .map_err(|err| err.to_string())?;
+    let mut cache =
+        merge::MergeBaseCache::load(repo, &cache_file).map_err(|err| err.to_string())?;
+    let loaded = cache.len();
+    let second = cached_run(&mut cache).map_err(|err| err.to_string())?;
+    if first.len() != 1 || second != first || loaded != 1 || cache.computed() != 0 {
+        return Err(format!(
+            "Merge base cache: {} cached, {} computed, {:?} vs {:?}",
+            loaded,
+            cache.computed(),
+            first,
+            second
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn merges_touching_a_file() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let resolved = conflicting_merge(repo).map_err(|err| err.to_string())?;
+    let internal = side_branch_merge(repo, history).map_err(|err| err.to_string())?;
+    let walk_both = || walk_from(repo, &[resolved.m, internal.m]);
+    let all = walk_both()
+        .map(|revwalk| merge::find_merges(repo, revwalk, None))
+        .map_err(|err| err.to_string())?;
+    let touching = walk_both()
+        .and_then(|revwalk| merge::find_merges_touching(repo, revwalk, "X.java", false))
+        .map_err(|err| err.to_string())?;
+    if all.len() < 2 || touching.len() != 1 {
+        return Err(format!(
+            "Expected 1 of {} merges to touch X.java, found {}",
+            all.len(),
+            touching.len()
+        ));
+    }
+    expect("merge touching X.java", resolved.m, touching[0].m)?;
+    Ok(())
+}
+
+#[test]
+fn pages_of_merges() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let resolved = conflicting_merge(repo).map_err(|err| err.to_string())?;
+    let internal = side_branch_merge(repo, history).map_err(|err| err.to_string())?;
+    let walk_both = || walk_from(repo, &[resolved.m, internal.m]);
+    let all = walk_both()
+        .map(|revwalk| merge::find_merges(repo, revwalk, None))
+        .map_err(|err| err.to_string())?;
+    let page_of = |page: merge::Page| -> Result<Vec<i64>, String> {
+        let merges = walk_both()
+            .map(|revwalk| merge::find_merges(repo, revwalk, None))
+            .map_err(|err| err.to_string())?;
+        Ok(merge::page_newest_first(repo, merges, page)
+            .iter()
+            .map(|merge| merge.time(repo))
+            .collect())
+    };
+    let newest_first = page_of(Default::default())?;
+    let page = page_of(merge::Page {
+        offset: 1,
+        limit: Some(1),
+    })?;
+    if newest_first.len() != all.len() || !newest_first.is_sorted_by(|newer, older| newer >= older)
+    {
+        return Err(format!(
+            "Merges not sorted newest first: {:?}",
+            newest_first
+        ));
+    }
+    if page != newest_first[1..2] {
+        return Err(format!(
+            "Expected only the second newest merge, found {:?}",
+            page
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn unique_branch_pairs() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let pairs: Vec<_> = [
+        "Merge branch 'dev'",
+        "Merge branch 'dev' of https://example.com/repo.git into release",
+        "Merge remote-tracking branch 'origin/dev' into 'main'",
+        "Merge pull request #12 from user/dev",
+        "Merge branches 'a' and 'b'",
+    ]
+    .iter()
+    .map(|summary| merge::branch_pair(summary))
+    .collect();
+    let pair = |merged: &str, into: &str| Some((merged.to_owned(), into.to_owned()));
+    if pairs
+        != [
+            pair("dev", ""),
+            pair("dev", "release"),
+            pair("dev", "main"),
+            pair("dev", ""),
+            None,
+        ]
+    {
+        return Err(format!("Unexpected branch pairs: {:?}", pairs));
+    }
+
+    // dev is synced into the default branch twice, feature once, and two merges name no branch.
+    // HEAD points to the last of them for a while.
+    let (syncs, expected) =
+        (|| -> Result<(Vec<Oid>, std::collections::HashSet<Oid>), git2::Error> {
+            let main = commit(repo, "main", "m\n", &[], 1_620_000_000)?;
+            let dev1 = commit(repo, "dev1", "d1\n", &[main], 1_620_000_100)?;
+            let sync1 = commit(
+                repo,
+                "Merge branch 'dev'",
+                "s1\n",
+                &[main, dev1],
+                1_620_000_200,
+            )?;
+            let dev2 = commit(repo, "dev2", "d2\n", &[dev1], 1_620_000_300)?;
+            let sync2 = commit(
+                repo,
+                "Merge branch 'dev'",
+                "s2\n",
+                &[sync1, dev2],
+                1_620_000_400,
+            )?;
+            let feature = commit(repo, "feature", "f\n", &[main], 1_620_000_500)?;
+            let merge_feature = commit(
+                repo,
+                "Merge branch 'feature'",
+                "f\n",
+                &[sync2, feature],
+                1_620_000_600,
+            )?;
+            let other1 = commit(repo, "Sync", "o1\n", &[merge_feature, dev2], 1_620_000_700)?;
+            let other2 = commit(repo, "Sync", "o2\n", &[other1, dev2], 1_620_000_800)?;
+            repo.reference("refs/heads/main", other2, true, "selftest")?;
+            Ok((
+                vec![sync1, sync2],
+                std::collections::HashSet::from([sync1, merge_feature, other1, other2]),
+            ))
+        })()
+        .map_err(|err| err.to_string())?;
+    let firsts = merge::first_merges_per_branch_pair(repo);
+    repo.reference("refs/heads/main", history.fix, true, "selftest")
+        .map_err(|err| err.to_string())?;
+    let firsts = firsts.map_err(|err| err.to_string())?;
+    if firsts != expected {
+        return Err(format!(
+            "Expected the first merges per branch pair {:?}, found {:?}",
+            expected, firsts
+        ));
+    }
+    let filter = merge::MergeFilter {
+        unique_branch_pairs: Some(firsts),
+        ..Default::default()
+    };
+    let kept = syncs
+        .iter()
+        .map(|sync| {
+            let merge = ThreeWayMerge::with_base(
+                repo,
+                &repo.find_commit(*sync)?,
+                &BaseStrategy::MergeBase,
+            )?;
+            Ok(filter.keep(&merge, repo))
+        })
+        .collect::<Result<Vec<_>, git2::Error>>()
+        .map_err(|err| err.to_string())?;
+    if kept != [true, false] {
+        return Err(format!("Unexpected syncs kept: {:?}", kept));
+    }
+    Ok(())
+}
+
+/// After the fix, a feature branch merges a helper branch of its own, and T then merges the
+/// feature branch. With `--mainline-only`, T and M on the first-parent chain are kept, while the
+/// feature merge, only reachable through T's second parent, is left out.
+#[test]
+fn mainline_only() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let (feature, t) = (|| -> Result<(Oid, Oid), git2::Error> {
+        let helper = commit(repo, "helper", "h\n", &[history.fix], 1_600_090_100)?;
+        let feature1 = commit(repo, "feature1", "f\n", &[history.fix], 1_600_090_200)?;
+        let feature = commit(repo, "feature", "fh\n", &[feature1, helper], 1_600_090_300)?;
+        let main1 = commit(repo, "main1", "m\n", &[history.fix], 1_600_090_400)?;
+        let t = commit(repo, "t", "mfh\n", &[main1, feature], 1_600_090_500)?;
+        repo.reference("refs/heads/main", t, true, "selftest")?;
+        Ok((feature, t))
+    })()
+    .map_err(|err| err.to_string())?;
+    let mainline = git_utils::first_parent_chain(repo);
+    repo.reference("refs/heads/main", history.fix, true, "selftest")
+        .map_err(|err| err.to_string())?;
+    let filter = merge::MergeFilter {
+        mainline: Some(mainline.map_err(|err| err.to_string())?),
+        ..Default::default()
+    };
+    let kept = [t, history.m, feature]
+        .iter()
+        .map(|m| Ok(filter.keep(&merge_at(repo, *m)?, repo)))
+        .collect::<Result<Vec<_>, git2::Error>>()
+        .map_err(|err| err.to_string())?;
+    if kept != [true, true, false] {
+        return Err(format!(
+            "Expected T and M kept and the feature merge left out, found {:?}",
+            kept
+        ));
+    }
+    Ok(())
+}
+
+/// A merge P with two children: a plain commit, and a merge C whose clock was an hour behind.
+/// Sorting by time reports P before C, as it looks newer, while sorting topologically keeps every
+/// child before its parents.
+#[test]
+fn sorting() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "sorting")?;
+    let repo = &scratch;
+    let (top, p, c) = (|| -> Result<(Oid, Oid, Oid), git2::Error> {
+        let root = commit(repo, "root", "r\n", &[], 1_600_000_000)?;
+        let p1 = commit(repo, "p1", "p1\n", &[root], 1_600_000_100)?;
+        let p2 = commit(repo, "p2", "p2\n", &[root], 1_600_000_200)?;
+        let p = commit(repo, "Merge p2", "p\n", &[p1, p2], 1_600_003_600)?;
+        let child = commit(repo, "child", "x\n", &[p], 1_600_007_200)?;
+        let side = commit(repo, "side", "s\n", &[root], 1_600_000_300)?;
+        let c = commit(repo, "Merge side", "c\n", &[p, side], 1_600_000_400)?;
+        let top = commit(repo, "Merge c", "t\n", &[child, c], 1_600_010_000)?;
+        repo.reference("refs/heads/main", top, true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok((top, p, c))
+    })()
+    .map_err(|err| err.to_string())?;
+    let merges_by = |sort| -> Result<Vec<Oid>, String> {
+        let revwalk = git_utils::create_revwalk(repo, sort).map_err(|err| err.to_string())?;
+        Ok(merge::find_merges(repo, revwalk, None)
+            .iter()
+            .map(|merge| merge.m)
+            .collect())
+    };
+    let topo = merges_by(git2::Sort::TOPOLOGICAL)?;
+    let time = merges_by(git2::Sort::TIME)?;
+    let reverse = merges_by(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    if topo != [top, c, p] || time != [top, p, c] || reverse != [p, c, top] {
+        return Err(format!(
+            "Unexpected merge order: topo {:?}, time {:?}, reverse topo {:?}",
+            topo, time, reverse
+        ));
+    }
+    Ok(())
+}
+
+/// The callback sees every merge the revwalk finds, in the same order as `find_merges`.
+#[test]
+fn for_each_merge() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "callback")?;
+    let repo = &scratch;
+    (|| -> Result<(), git2::Error> {
+        let root = commit(repo, "root", "r\n", &[], 1_600_000_000)?;
+        let a = commit(repo, "a", "a\n", &[root], 1_600_000_100)?;
+        let b = commit(repo, "b", "b\n", &[root], 1_600_000_200)?;
+        let m1 = commit(repo, "Merge b", "m1\n", &[a, b], 1_600_000_300)?;
+        let c = commit(repo, "c", "c\n", &[root], 1_600_000_400)?;
+        let m2 = commit(repo, "Merge c", "m2\n", &[m1, c], 1_600_000_500)?;
+        repo.reference("refs/heads/main", m2, true, "selftest")?;
+        repo.set_head("refs/heads/main")
+    })()
+    .map_err(|err| err.to_string())?;
+    let revwalk = || git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL);
+    let mut seen = vec![];
+    merge::for_each_merge(
+        repo,
+        revwalk().map_err(|err| err.to_string())?,
+        None,
+        |merge| seen.push(merge.m),
+    );
+    let found: Vec<Oid> = merge::find_merges(repo, revwalk().map_err(|err| err.to_string())?, None)
+        .iter()
+        .map(|merge| merge.m)
+        .collect();
+    if seen.len() != 2 || seen != found {
+        return Err(format!(
+            "The callback saw {:?}, find_merges found {:?}",
+            seen, found
+        ));
+    }
+    Ok(())
+}
+
+/// Between a lightweight tag v1 and an annotated tag v2, only the merge made after v1 up to v2 is
+/// found, not the one before v1 or the one after v2.
+#[test]
+fn tag_range() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "tag-range")?;
+    let repo = &scratch;
+    let merges = (|| -> Result<Vec<Oid>, git2::Error> {
+        let mut parent = commit(repo, "root", "r\n", &[], 1_600_000_000)?;
+        let mut merges = vec![];
+        for round in 0..3 {
+            let time = 1_600_000_000 + 1000 * (round + 1);
+            let a = commit(repo, "a", &format!("a{}\n", round), &[parent], time)?;
+            let b = commit(repo, "b", &format!("b{}\n", round), &[parent], time + 100)?;
+            parent = commit(
+                repo,
+                "Merge b",
+                &format!("m{}\n", round),
+                &[a, b],
+                time + 200,
+            )?;
+            merges.push(parent);
+        }
+        repo.tag_lightweight("v1", &repo.find_object(merges[0], None)?, true)?;
+        let signature = Signature::new(
+            "Self Test",
+            "selftest@example.com",
+            &git2::Time::new(1_600_005_000, 0),
+        )?;
+        repo.tag(
+            "v2",
+            &repo.find_object(merges[1], None)?,
+            &signature,
+            "Release 2",
+            true,
+        )?;
+        repo.reference("refs/heads/main", merges[2], true, "selftest")?;
+        repo.set_head("refs/heads/main")?;
+        Ok(merges)
+    })()
+    .map_err(|err| err.to_string())?;
+    let tag = |name| git_utils::resolve_tag(repo, name).map_err(|err| err.to_string());
+    let revwalk = git_utils::create_revwalk_range(
+        repo,
+        git2::Sort::TOPOLOGICAL,
+        Some(tag("v1")?),
+        Some(tag("v2")?),
+    )
+    .map_err(|err| err.to_string())?;
+    let found: Vec<Oid> = merge::find_merges(repo, revwalk, None)
+        .iter()
+        .map(|merge| merge.m)
+        .collect();
+    if found != [merges[1]] {
+        return Err(format!(
+            "Expected only {} between the tags, found {:?}",
+            merges[1], found
+        ));
+    }
+    Ok(())
+}
+
+/// Up to a horizon between B and M, the descendants of O are A and B only. M and the fix come
+/// after it.
+#[test]
+fn time_horizon() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let mut found = git_utils::get_descendants_until(repo, history.o, 1_600_000_250)
+        .map_err(|err| err.to_string())?;
+    found.sort();
+    let mut expected = vec![history.a, history.b];
+    expected.sort();
+    if found != expected {
+        return Err(format!(
+            "Expected only {:?} before the horizon, found {:?}",
+            expected, found
+        ));
+    }
+    Ok(())
+}
+
+/// A merge that took A's tree as is, e.g., with `-s ours`, is skipped with `distinct_m`. One
+/// combining both sides is kept.
+#[test]
+fn distinct_m() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let ours = commit(
+        repo,
+        "Merge b, keeping a",
+        "A\nb\nc\nd\ne\n",
+        &[history.a, history.b],
+        1_600_000_350,
+    )
+    .and_then(|m| merge_at(repo, m))
+    .map_err(|err| err.to_string())?;
+    let merge = merge_at(repo, history.m).map_err(|err| err.to_string())?;
+    let filter = merge::MergeFilter {
+        distinct_m: true,
+        ..Default::default()
+    };
+    if ours.m_differs_from_parents(repo) || filter.keep(&ours, repo) || !filter.keep(&merge, repo) {
+        return Err("Expected only the merge equal to A to be skipped".to_owned());
+    }
+    Ok(())
+}
+
+/// A normal merge has two parents, an octopus merge of three branches three.
+#[test]
+fn parent_count() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let octopus = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let c = commit(repo, "c", "a\nb\nC\nd\ne\n", &[history.o], 1_600_000_250)?;
+        let m = commit(
+            repo,
+            "Merge b and c into a",
+            "A\nb\nC\nd\nE\n",
+            &[history.a, history.b, c],
+            1_600_000_370,
+        )?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let merge = merge_at(repo, history.m).map_err(|err| err.to_string())?;
+    let counts = (merge.parent_count(repo), octopus.parent_count(repo));
+    if counts != (2, 3) {
+        return Err(format!("Unexpected parent counts: {:?}", counts));
+    }
+    Ok(())
+}
+
+/// The same merge once as is and once with a signature block. Signatures are detected, not
+/// verified, so a made-up one does.
+#[test]
+fn signed() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let signed = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let m = repo.find_commit(history.m)?;
+        let buffer = repo.commit_create_buffer(
+            &m.author(),
+            &m.committer(),
+            "Merge b into a, signed",
+            &m.tree()?,
+            &[&m.parent(0)?, &m.parent(1)?],
+        )?;
+        let signature = "-----BEGIN PGP SIGNATURE-----\n\nselftest\n-----END PGP SIGNATURE-----";
+        let signed = repo.commit_signed(
+            buffer.as_str().expect("A commit buffer is text"),
+            signature,
+            None,
+        )?;
+        merge_at(repo, signed)
+    })()
+    .map_err(|err| err.to_string())?;
+    let unsigned = merge_at(repo, history.m).map_err(|err| err.to_string())?;
+    if !signed.is_signed(repo) || unsigned.is_signed(repo) {
+        return Err("Expected only the merge with a signature to be signed".to_owned());
+    }
+    Ok(())
+}
+
+/// After M, A and B are integrated again through new commits on both sides. That re-merge is the
+/// nearest common descendant besides M, not the fix, which only descends from them through M.
+#[test]
+fn nearest_common_descendant() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let remerge = (|| -> Result<Oid, git2::Error> {
+        let a2 = commit(
+            repo,
+            "More on a",
+            "A\nB\nc\nd\ne\n",
+            &[history.a],
+            1_600_000_350,
+        )?;
+        let b2 = commit(
+            repo,
+            "More on b",
+            "a\nb\nc\nD\nE\n",
+            &[history.b],
+            1_600_000_360,
+        )?;
+        let remerge = commit(
+            repo,
+            "Merge again",
+            "A\nB\nc\nD\nE\n",
+            &[a2, b2],
+            1_600_000_370,
+        )?;
+        let top = commit(
+            repo,
+            "Merge both",
+            "A!\nB\nc\nD\nE\n",
+            &[history.fix, remerge],
+            1_600_000_500,
+        )?;
+        repo.reference("refs/heads/main", top, true, "selftest")?;
+        Ok(remerge)
+    })()
+    .map_err(|err| err.to_string())?;
+    let found = git_utils::nearest_common_descendant(repo, history.a, history.b, history.m);
+    repo.reference("refs/heads/main", history.fix, true, "selftest")
+        .map_err(|err| err.to_string())?;
+    match found.map_err(|err| err.to_string())? {
+        Some(found) if found == remerge => Ok(()),
+        found => Err(format!(
+            "Expected {} as nearest common descendant, found {:?}",
+            remerge, found
+        )),
+    }
+}
+
+/// Merges in an unrelated history, on a commit HEAD does not reach.
+fn merge_unrelated(repo: &Repository, history: &History) -> Result<Oid, git2::Error> {
+    let root = commit(repo, "Unrelated root", "x\n", &[], 1_600_000_500)?;
+    commit(
+        repo,
+        "Merge unrelated histories",
+        "x\n",
+        &[history.m, root],
+        1_600_000_600,
+    )
+}
+
+/// Walks from the given commits only.
+fn walk_from<'r>(repo: &'r Repository, tips: &[Oid]) -> Result<git2::Revwalk<'r>, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    for tip in tips {
+        revwalk.push(*tip)?;
+    }
+    Ok(revwalk)
+}
+
+/// Collects what `merge::find_merges_stream` yields, on a runtime of its own.
+#[cfg(feature = "async")]
+fn stream_merges(repo: &Repository) -> Result<Vec<ThreeWayMerge>, String> {
+    use futures_core::Stream;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .map_err(|err| err.to_string())?;
+    let repo = Repository::open(repo.path()).map_err(|err| err.to_string())?;
+    runtime.block_on(async {
+        let mut stream = merge::find_merges_stream(repo, git2::Sort::TOPOLOGICAL, None);
+        let mut merges = vec![];
+        while let Some(merge) =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut stream).poll_next(cx)).await
+        {
+            merges.push(merge);
+        }
+        Ok(merges)
+    })
+}
@@ -0,0 +1,291 @@
+//! The git helpers: short hashes, scoped patches, overlapping lines, blobs, and descendants.
+
+mod common;
+
+use common::{commit, commit_files, write_tree, Fixture, FILE};
+use git2::Oid;
+use std::path::Path;
+use three_way_merge_finder::{git_utils, sink};
+
+#[test]
+fn short_sha() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    for oid in [history.o, history.a, history.b, history.m, history.fix] {
+        let short = git_utils::short_sha(repo, oid);
+        let resolved = repo.revparse_single(&short).map(|object| object.id());
+        if !oid.to_string().starts_with(&short) || resolved.ok() != Some(oid) {
+            return Err(format!("{} is not an unambiguous prefix of {}", short, oid));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn fix_diffs() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge_files = std::collections::HashSet::from([FILE.to_owned()]);
+    let patch =
+        git_utils::scoped_patch(repo, &history.fix, &merge_files).map_err(|err| err.to_string())?;
+    let patch = String::from_utf8_lossy(&patch);
+    if !patch.contains("+A!") || !patch.contains(FILE) {
+        return Err(format!("Unexpected patch of the fix: {}", patch));
+    }
+    let other_files = std::collections::HashSet::from(["Other.java".to_owned()]);
+    let patch =
+        git_utils::scoped_patch(repo, &history.fix, &other_files).map_err(|err| err.to_string())?;
+    if !patch.is_empty() {
+        return Err("Expected an empty patch for files the fix does not change".to_owned());
+    }
+    Ok(())
+}
+
+#[test]
+fn overlap_context_lines() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // Changing the second line, right below the first one A changed, only overlaps with context.
+    let neighbour = commit(
+        repo,
+        "Fix the second line",
+        "A\nb!\nc\nd\nE\n",
+        &[history.m],
+        1_600_000_450,
+    )
+    .map_err(|err| err.to_string())?;
+    for (context_lines, expected) in [(0, false), (3, true)] {
+        let lines = git_utils::OverlapLines {
+            only_extensions: &["java"],
+            context_lines,
+        };
+        let overlap = git_utils::changed_same_line_cached(
+            repo,
+            &history.o,
+            &history.m,
+            &history.m,
+            &neighbour,
+            &lines,
+            &mut git_utils::BlameCache::new(),
+        );
+        if overlap != expected {
+            return Err(format!(
+                "Expected overlap {} with {} context lines",
+                expected, context_lines
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn normalize_eol() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let crlf = commit_files(
+        repo,
+        "selftest@example.com",
+        "CRLF",
+        &[("Crlf.java", "a\r\nb\r\n"), ("Binary.dat", "\0\r\n")],
+        &[],
+        1_600_004_000,
+    )
+    .map_err(|err| err.to_string())?;
+    let prefix = Path::new("merge");
+    let mut sink = sink::MemorySink::default();
+    let files = std::collections::HashSet::from(["Crlf.java".to_owned(), "Binary.dat".to_owned()]);
+    git_utils::write_files_from_commit(
+        &mut sink,
+        prefix,
+        crlf,
+        repo,
+        &files,
+        "CRLF",
+        git_utils::BlobOptions {
+            eol: Some(git_utils::LineEnding::Lf),
+            ..Default::default()
+        },
+    );
+    if sink.files[&prefix.join("Crlf.java")] != b"a\nb\n"
+        || sink.files[&prefix.join("Binary.dat")] != b"\0\r\n"
+    {
+        return Err(format!("Unexpected normalized files: {:?}", sink.files));
+    }
+    Ok(())
+}
+
+#[test]
+fn max_file_bytes() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let large = "x".repeat(100);
+    let sizes = commit_files(
+        repo,
+        "selftest@example.com",
+        "Sizes",
+        &[("Large.java", &large), ("Small.java", "x\n")],
+        &[],
+        1_600_004_100,
+    )
+    .map_err(|err| err.to_string())?;
+    let prefix = Path::new("merge");
+    let mut sink = sink::MemorySink::default();
+    let files = std::collections::HashSet::from(["Large.java".to_owned(), "Small.java".to_owned()]);
+    git_utils::write_files_from_commit(
+        &mut sink,
+        prefix,
+        sizes,
+        repo,
+        &files,
+        "Sizes",
+        git_utils::BlobOptions {
+            max_bytes: Some(10),
+            ..Default::default()
+        },
+    );
+    let written: Vec<_> = sink.files.keys().cloned().collect();
+    if written != [prefix.join("Small.java")] {
+        return Err(format!("Expected only the small file, found {:?}", written));
+    }
+    Ok(())
+}
+
+#[test]
+fn bounded_descendants() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // Four more commits on top of HEAD for a while, so M has descendants up to six generations
+    // away. A search two generations deep must not look at the rest.
+    let (full, bounded, children) = (|| {
+        let mut tip = history.fix;
+        for (i, time) in (1_600_010_000..).step_by(100).take(4).enumerate() {
+            tip = commit(repo, &format!("c{}", i), &format!("{}\n", i), &[tip], time)?;
+        }
+        repo.reference("refs/heads/main", tip, true, "selftest")?;
+        let full = git_utils::get_descendants(repo, history.m)?;
+        let bounded = git_utils::get_descendants_bounded(repo, history.m, 2)?;
+        let children = git_utils::ChildMap::descending_from(repo, history.m)?;
+        repo.reference("refs/heads/main", history.fix, true, "selftest")?;
+        Ok((full, bounded, children))
+    })()
+    .map_err(|err: git2::Error| err.to_string())?;
+    let expected: Vec<Oid> = full
+        .iter()
+        .filter(|oid| git_utils::within_n_generations(repo, &history.m, oid, 2))
+        .copied()
+        .collect();
+    if full.len() != 5 || bounded != expected {
+        return Err(format!(
+            "Expected the descendants {:?} of {:?} within two generations, found {:?}",
+            expected, full, bounded
+        ));
+    }
+    let (found, visited) = children
+        .descendants_counting_visits(repo, history.m, 2, None)
+        .map_err(|err| err.to_string())?;
+    if found != expected || visited != 2 {
+        return Err(format!(
+            "Expected {:?} after looking at two commits, found {:?} after {}",
+            expected, found, visited
+        ));
+    }
+    // The map holds no search state, so searches can share it across threads.
+    fn is_sync<T: Sync>(_: &T) {}
+    is_sync(&children);
+    Ok(())
+}
+
+/// O, A, B, and M only differ in their README, and share the `lib` folder. With one cache for all
+/// four, `lib` and `lib/sub` are only read once.
+#[test]
+fn paths_cache() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let mut lib: Vec<(String, String)> = (0..10)
+        .map(|i| (format!("lib/file{}.rs", i), format!("{}\n", i)))
+        .collect();
+    lib.push(("lib/sub/deep.rs".to_owned(), "deep\n".to_owned()));
+    let trees = ["o", "a", "b", "m"]
+        .iter()
+        .map(|version| {
+            let mut files: Vec<(&str, &str)> = lib
+                .iter()
+                .map(|(path, content)| (path.as_str(), content.as_str()))
+                .collect();
+            files.push(("README", version));
+            repo.find_tree(write_tree(repo, &files)?)
+        })
+        .collect::<Result<Vec<_>, git2::Error>>()
+        .map_err(|err| err.to_string())?;
+
+    let mut shared = std::collections::HashMap::new();
+    let mut separate_reads = 0;
+    for tree in &trees {
+        let mut own = std::collections::HashMap::new();
+        let paths = git_utils::get_all_paths_cached(tree, "", repo, &mut own);
+        separate_reads += own.len();
+        let cached = git_utils::get_all_paths_cached(tree, "", repo, &mut shared);
+        if paths.len() != 12 || cached != paths {
+            return Err(format!("Unexpected paths {:?}, cached {:?}", paths, cached));
+        }
+    }
+    // Every tree read ends up in the cache once: four roots, lib, and lib/sub.
+    if shared.len() != 6 || separate_reads != 12 {
+        return Err(format!(
+            "Expected 6 tree reads with a shared cache and 12 without, found {} and {}",
+            shared.len(),
+            separate_reads
+        ));
+    }
+    Ok(())
+}
+
+/// Checking candidates against one shared blame cache gives what checking each on its own does,
+/// whether the blame is already in the cache or not.
+#[test]
+fn blame_cache() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let unrelated = commit(
+        repo,
+        "Change c",
+        "A!\nb\nC\nd\nE\n",
+        &[history.fix],
+        1_600_000_500,
+    )
+    .map_err(|err| err.to_string())?;
+    let candidates = [(history.m, history.fix), (history.fix, unrelated)];
+    let lines = git_utils::OverlapLines {
+        only_extensions: &["java"],
+        context_lines: 0,
+    };
+    let mut blames = git_utils::BlameCache::new();
+    let mut cached = vec![];
+    for _ in 0..2 {
+        for (old, new) in candidates {
+            cached.push(git_utils::changed_same_line_cached(
+                repo,
+                &history.o,
+                &history.m,
+                &old,
+                &new,
+                &lines,
+                &mut blames,
+            ));
+        }
+    }
+    let uncached: Vec<_> = candidates
+        .iter()
+        .chain(candidates.iter())
+        .map(|(old, new)| {
+            git_utils::changed_same_line(repo, &history.o, &history.m, old, new, &["java"])
+        })
+        .collect();
+    if cached != uncached || cached[..2] != [true, false] {
+        return Err(format!(
+            "Cached blames gave {:?}, uncached {:?}",
+            cached, uncached
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,1134 @@
+//! Properties of a single three way merge, and the filters deciding which merges to keep.
+
+mod common;
+
+use common::{
+    commit, commit_by, commit_files, conflicting_merge, expect, merge_at, scratch_repo,
+    side_branch_merge, Fixture, FILE,
+};
+use git2::{Oid, Repository, Signature};
+use std::path::{Path, PathBuf};
+use three_way_merge_finder::merge::{BaseStrategy, PathStyle, Resolution, Side, ThreeWayMerge};
+use three_way_merge_finder::{git_utils, language, merge, publish, sink};
+
+#[test]
+fn branch_unique_counts() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // B merges A halfway, so a1 is on both branches and only counts for neither.
+    let divergence = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let a1 = commit(repo, "a1", "1\n", &[history.o], 1_600_001_000)?;
+        let a2 = commit(repo, "a2", "2\n", &[a1], 1_600_001_100)?;
+        let b1 = commit(repo, "b1", "3\n", &[history.o], 1_600_001_200)?;
+        let b2 = commit(repo, "b2", "4\n", &[b1, a1], 1_600_001_300)?;
+        let m = commit(repo, "m", "5\n", &[a2, b2], 1_600_001_400)?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    let unique = divergence.branch_unique_counts(repo);
+    if unique != (1, 2) {
+        return Err(format!(
+            "Expected 1 and 2 unique commits, found {:?}",
+            unique
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn contributing_authors() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    let authors = merge.contributing_authors(repo);
+    if authors.len() != 1 {
+        return Err(format!(
+            "Expected only the selftest author, found {:?}",
+            authors
+        ));
+    }
+    let other = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let b = commit_by(
+            repo,
+            "other@example.com",
+            "b",
+            "6\n",
+            &[history.o],
+            1_600_002_000,
+        )?;
+        let m = commit(repo, "m", "7\n", &[history.a, b], 1_600_002_100)?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    let authors = other.contributing_authors(repo);
+    let expected = std::collections::HashSet::from([
+        "selftest@example.com".to_owned(),
+        "other@example.com".to_owned(),
+    ]);
+    if authors != expected {
+        return Err(format!("Expected two authors, found {:?}", authors));
+    }
+    Ok(())
+}
+
+#[test]
+fn ignoring_branch_merges() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let internal = side_branch_merge(repo, history).map_err(|err| err.to_string())?;
+    let direct = internal.files_changed_in_both_branches(repo);
+    let own = internal.files_changed_in_both_branches_ignoring_merges(repo);
+    if !direct.contains("Side.java") || own != std::collections::HashSet::from([FILE.to_owned()]) {
+        return Err(format!(
+            "Expected Side.java only without ignoring merges, found {:?} and {:?}",
+            direct, own
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn conflict_resolutions() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let resolved = conflicting_merge(repo).map_err(|err| err.to_string())?;
+    let resolutions = resolved.conflict_resolutions(repo);
+    let expected = vec![
+        ("X.java".to_owned(), Resolution::Theirs),
+        ("Y.java".to_owned(), Resolution::Custom),
+        ("Z.java".to_owned(), Resolution::Both),
+    ];
+    if resolutions != expected {
+        return Err(format!("Unexpected resolutions: {:?}", resolutions));
+    }
+    Ok(())
+}
+
+#[test]
+fn build_file_changes() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let merge = &fixture.merge()?;
+    let build = build_merge(repo).map_err(|err| err.to_string())?;
+    let patterns = merge::build_file_patterns();
+    if !build.touches_build_files(repo, &patterns) || merge.touches_build_files(repo, &patterns) {
+        return Err("Expected only the merge changing Cargo.toml to touch build files".to_owned());
+    }
+    Ok(())
+}
+
+#[test]
+fn single_side_changes() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let merge = &fixture.merge()?;
+    // In the build merge, only A changed FILE and only B changed Cargo.toml. In the main merge
+    // both changed FILE.
+    let build = build_merge(repo).map_err(|err| err.to_string())?;
+    let sides = [
+        build.only_one_side_changed(repo, FILE),
+        build.only_one_side_changed(repo, "Cargo.toml"),
+        build.only_one_side_changed(repo, "Other.java"),
+        merge.only_one_side_changed(repo, FILE),
+    ];
+    if sides != [Some(Side::A), Some(Side::B), None, None] {
+        return Err(format!("Unexpected sides changing the files: {:?}", sides));
+    }
+    Ok(())
+}
+
+#[test]
+fn hunk_count() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    // A and B each change one end of a long file, too far apart to share a hunk.
+    let scattered = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let lines = |first: &str, last: &str| {
+            let middle: String = (2..20).map(|line| format!("{}\n", line)).collect();
+            format!("{}\n{}{}\n", first, middle, last)
+        };
+        let o = commit(repo, "o", &lines("1", "20"), &[], 1_600_006_000)?;
+        let a = commit(repo, "a", &lines("one", "20"), &[o], 1_600_006_100)?;
+        let b = commit(repo, "b", &lines("1", "twenty"), &[o], 1_600_006_200)?;
+        let m = commit(repo, "m", &lines("one", "twenty"), &[a, b], 1_600_006_300)?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    let hunks = scattered.hunk_count(repo);
+    if hunks != 2 {
+        return Err(format!("Expected 2 hunks, found {}", hunks));
+    }
+    Ok(())
+}
+
+#[test]
+fn alternative_o() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let m = repo.find_commit(history.m).map_err(|err| err.to_string())?;
+    let oldest = ThreeWayMerge::with_base(repo, &m, &BaseStrategy::OldestCommonAncestorWithin(1))
+        .map_err(|err| err.to_string())?;
+    expect("O within 1 generation", history.o, oldest.o)?;
+    let explicit = ThreeWayMerge::with_base(repo, &m, &BaseStrategy::Explicit(history.a))
+        .map_err(|err| err.to_string())?;
+    expect("explicit O", history.a, explicit.o)?;
+    expect("B with explicit O", history.b, explicit.b)?;
+    Ok(())
+}
+
+#[test]
+fn validate() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    merge.validate(repo).map_err(|err| err.to_string())?;
+    // A commitlist row with O and A mixed up: O is then no ancestor of B.
+    let swapped = ThreeWayMerge::from_oid_str(
+        &history.a.to_string(),
+        &history.o.to_string(),
+        &history.b.to_string(),
+        &history.m.to_string(),
+    )
+    .map_err(|err| err.to_string())?;
+    match swapped.validate(repo) {
+        Err(err) if err.message().contains("is not a parent of M") => {}
+        other => return Err(format!("Expected a parent error, got {:?}", other)),
+    }
+    let bad_o = ThreeWayMerge::from_oid_str(
+        &history.b.to_string(),
+        &history.a.to_string(),
+        &history.b.to_string(),
+        &history.m.to_string(),
+    )
+    .map_err(|err| err.to_string())?;
+    match bad_o.validate(repo) {
+        Err(err) if err.message().contains("is not an ancestor of A") => {}
+        other => return Err(format!("Expected an ancestor error, got {:?}", other)),
+    }
+    Ok(())
+}
+
+#[test]
+fn both_branches_changed() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    // With O as A, the A side changes nothing.
+    let one_sided = ThreeWayMerge::from_oid_str(
+        &history.o.to_string(),
+        &history.o.to_string(),
+        &history.b.to_string(),
+        &history.m.to_string(),
+    )
+    .map_err(|err| err.to_string())?;
+    let filter = merge::MergeFilter {
+        both_branches_changed: true,
+        ..Default::default()
+    };
+    if !filter.keep(merge, repo) || filter.keep(&one_sided, repo) {
+        return Err("Expected only the merge with two changed branches to be kept".to_owned());
+    }
+    Ok(())
+}
+
+/// M changes Main.java. It counts as using a merge driver once the working copy's .gitattributes
+/// gives Java files one, but not for the default text driver or a driver of other files only.
+#[test]
+fn uses_merge_driver() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let merge = fixture.merge()?;
+    let attributes = repo
+        .workdir()
+        .ok_or("The test repository is bare")?
+        .join(".gitattributes");
+    let mut found = vec![merge.uses_merge_driver(repo)];
+    for content in [
+        "*.txt merge=union\n",
+        "*.java merge=selftest-driver\n",
+        "*.java merge=text\n",
+        "*.java -merge\n",
+    ] {
+        std::fs::write(&attributes, content).map_err(|err| err.to_string())?;
+        found.push(merge.uses_merge_driver(repo));
+    }
+    if found != [false, false, true, false, true] {
+        return Err(format!("Unexpected merge driver use: {:?}", found));
+    }
+    Ok(())
+}
+
+#[test]
+fn branch_change_similarity() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    // The branches edit different lines, so share nothing; a merge of A with itself shares all.
+    let similarity = merge.branch_change_similarity(repo);
+    if similarity != 0.0 {
+        return Err(format!(
+            "Expected similarity 0 for disjoint edits, found {}",
+            similarity
+        ));
+    }
+    let identical = ThreeWayMerge::from_oid_str(
+        &history.o.to_string(),
+        &history.a.to_string(),
+        &history.a.to_string(),
+        &history.m.to_string(),
+    )
+    .map_err(|err| err.to_string())?;
+    let similarity = identical.branch_change_similarity(repo);
+    if similarity != 1.0 {
+        return Err(format!(
+            "Expected similarity 1 for identical edits, found {}",
+            similarity
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_file_ratio() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let merge = &fixture.merge()?;
+    // The only changed file is Main.java: no test by name, but by pattern.
+    let by_name = merge.test_file_ratio(repo, &[]);
+    let pattern = glob::Pattern::new("Main.*").map_err(|err| err.to_string())?;
+    let by_pattern = merge.test_file_ratio(repo, &[pattern]);
+    if by_name != 0.0 || by_pattern != 1.0 {
+        return Err(format!(
+            "Unexpected test ratios {} and {}",
+            by_name, by_pattern
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn path_style() -> Result<(), String> {
+    let native = PathStyle::Native.apply("src/main/Main.java");
+    let expected = ["src", "main", "Main.java"].join(std::path::MAIN_SEPARATOR_STR);
+    if native != expected || PathStyle::Posix.apply("src/main/Main.java") != "src/main/Main.java" {
+        return Err(format!(
+            "Expected native path {}, found {}",
+            expected, native
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn pull_request_numbers() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let messages = [
+        "Merge pull request #123 from user/topic\n\nFix the parser (#7)",
+        "Merge branch 'topic' into 'main'\n\nFix the parser\n\nSee merge request group/project!45",
+        "Merged in topic (pull request #6)",
+        "Fix the parser (#89)",
+        "Merge branch 'topic'",
+    ];
+    let numbers = messages
+        .iter()
+        .map(|message| {
+            let m = commit(repo, message, "x\n", &[history.a, history.b], 1_600_003_200)?;
+            let merge =
+                ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)?;
+            Ok(merge.pull_request_number(repo))
+        })
+        .collect::<Result<Vec<_>, git2::Error>>()
+        .map_err(|err| err.to_string())?;
+    if numbers != [Some(123), Some(45), Some(6), Some(89), None] {
+        return Err(format!("Unexpected pull request numbers: {:?}", numbers));
+    }
+    Ok(())
+}
+
+#[test]
+fn nested_merges() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    // B is itself the merge of two commits off O, A a plain commit.
+    let nested = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let b1 = commit(repo, "b1", "a\nb\nc\nd\nE\n", &[history.o], 1_600_003_300)?;
+        let b2 = commit(repo, "b2", "a\nB\nc\nd\ne\n", &[history.o], 1_600_003_400)?;
+        let b = commit(
+            repo,
+            "Merge b2",
+            "a\nB\nc\nd\nE\n",
+            &[b1, b2],
+            1_600_003_500,
+        )?;
+        let m = commit(
+            repo,
+            "Merge b",
+            "A\nB\nc\nd\nE\n",
+            &[history.a, b],
+            1_600_003_600,
+        )?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    expect("O of the nested merge", history.o, nested.o)?;
+    let counts = (
+        merge.ancestor_merge_count(repo),
+        nested.ancestor_merge_count(repo),
+    );
+    if counts != (0, 1) {
+        return Err(format!("Unexpected nested merge counts: {:?}", counts));
+    }
+    Ok(())
+}
+
+#[test]
+fn distinct_o_by_tree() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    // A is an empty commit on top of O.
+    let empty_side = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let a = commit(
+            repo,
+            "Nothing",
+            "a\nb\nc\nd\ne\n",
+            &[history.o],
+            1_600_003_700,
+        )?;
+        let m = commit(
+            repo,
+            "Merge b",
+            "a\nb\nc\nd\nE\n",
+            &[a, history.b],
+            1_600_003_800,
+        )?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    let filter = |mode| merge::MergeFilter {
+        distinct_o: true,
+        distinct_o_mode: mode,
+        ..Default::default()
+    };
+    let kept = (
+        filter(merge::DistinctOMode::Oid).keep(&empty_side, repo),
+        filter(merge::DistinctOMode::Tree).keep(&empty_side, repo),
+        filter(merge::DistinctOMode::Tree).keep(merge, repo),
+    );
+    if !empty_side.has_distinct_o() || kept != (true, false, true) {
+        return Err(format!(
+            "Unexpected merges kept by distinct O mode: {:?}",
+            kept
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn author_and_committer_time() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // Authored at +0200, committed an hour later at -0500.
+    let amended = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let author = Signature::new(
+            "Self Test",
+            "a@example.com",
+            &git2::Time::new(1_600_004_000, 120),
+        )?;
+        let committer = Signature::new(
+            "Self Test",
+            "c@example.com",
+            &git2::Time::new(1_600_007_600, -300),
+        )?;
+        let parents = [&repo.find_commit(history.a)?, &repo.find_commit(history.b)?];
+        let tree = repo.find_commit(history.m)?.tree()?;
+        let m = repo.commit(None, &author, &committer, "Merge b", &tree, &parents)?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    let time = |source| {
+        let time = amended.time_by(repo, source);
+        (time.seconds(), time.offset_minutes())
+    };
+    let times = (
+        time(merge::TimeSource::Committer),
+        time(merge::TimeSource::Author),
+    );
+    let (committed, _) = times;
+    if times != ((1_600_007_600, -300), (1_600_004_000, 120)) || amended.time(repo) != committed.0 {
+        return Err(format!(
+            "Unexpected committer and author times: {:?}",
+            times
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn minimum_branch_age() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    // Branches off a common root, merged a day, 40 days, and (by a skewed clock) -1 day later.
+    const DAY: i64 = 24 * 60 * 60;
+    let ages = (|| -> Result<Vec<ThreeWayMerge>, git2::Error> {
+        let root = commit(repo, "root", "r\n", &[], 1_610_000_000)?;
+        let a = commit(repo, "a", "a\n", &[root], 1_610_000_100)?;
+        let b = commit(repo, "b", "b\n", &[root], 1_610_000_200)?;
+        [1, 40, -1]
+            .into_iter()
+            .map(|days| {
+                let m = commit(repo, "m", "m\n", &[a, b], 1_610_000_000 + days * DAY)?;
+                ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+            })
+            .collect()
+    })()
+    .map_err(|err| err.to_string())?;
+    let filter = merge::MergeFilter {
+        min_branch_age_days: Some(30),
+        ..Default::default()
+    };
+    let kept: Vec<bool> = ages.iter().map(|merge| filter.keep(merge, repo)).collect();
+    if kept != [false, true, false] {
+        return Err(format!("Unexpected merges kept by branch age: {:?}", kept));
+    }
+    Ok(())
+}
+
+#[test]
+fn file_tree() -> Result<(), String> {
+    let tree = merge::FileTree::from_paths([
+        "README.md",
+        "src/main.rs",
+        "src/merge/mod.rs",
+        "src/merge",
+        "src/lib.rs",
+    ]);
+    let json = serde_json::to_string(&tree).map_err(|err| err.to_string())?;
+    let expected =
+        r#"{"README.md":null,"src":{"lib.rs":null,"main.rs":null,"merge":{"mod.rs":null}}}"#;
+    if json != expected {
+        return Err(format!("Expected file tree {}, found {}", expected, json));
+    }
+    Ok(())
+}
+
+#[test]
+fn maximum_changed_files() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    // A merge vendoring 50 files next to the usual one changing a single file.
+    let giant = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let email = "selftest@example.com";
+        let vendored: Vec<(String, &str)> = (0..50)
+            .map(|i| (format!("vendor{}.js", i), "x\n"))
+            .collect();
+        let mut files: Vec<(&str, &str)> = vendored
+            .iter()
+            .map(|(path, content)| (path.as_str(), *content))
+            .collect();
+        files.push((FILE, "a\nb\nc\nd\ne\n"));
+        let a = commit(repo, "a", "A\nb\nc\nd\ne\n", &[history.o], 1_600_005_000)?;
+        let b = commit(repo, "b", "a\nb\nc\nd\nE\n", &[history.o], 1_600_005_100)?;
+        let m = commit_files(repo, email, "Vendor", &files, &[a, b], 1_600_005_200)?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    let filter = merge::MergeFilter {
+        max_changed_files: Some(10),
+        ..Default::default()
+    };
+    let counts = [
+        filter.keep_counting_files(&giant, repo),
+        filter.keep_counting_files(merge, repo),
+    ];
+    if counts != [None, Some(1)] || filter.keep(&giant, repo) {
+        return Err(format!("Unexpected changed file counts: {:?}", counts));
+    }
+    Ok(())
+}
+
+#[test]
+fn evil_merges() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // A clean merge with a line slipped in, and a conflict on the first line resolved by taking
+    // B's side or by writing something else.
+    let evil = (|| -> Result<Vec<bool>, git2::Error> {
+        let sneaky = commit(
+            repo,
+            "Merge b into a",
+            "A\nb\nsneaky()\nc\nd\nE\n",
+            &[history.a, history.b],
+            1_600_006_000,
+        )?;
+        let x = commit(repo, "x", "X\nb\nc\nd\ne\n", &[history.o], 1_600_006_100)?;
+        let y = commit(repo, "y", "Y\nb\nc\nd\ne\n", &[history.o], 1_600_006_200)?;
+        let picked = commit(repo, "Take y", "Y\nb\nc\nd\ne\n", &[x, y], 1_600_006_300)?;
+        let rewritten = commit(repo, "Take z", "Z\nb\nc\nd\ne\n", &[x, y], 1_600_006_400)?;
+        [history.m, sneaky, picked, rewritten]
+            .into_iter()
+            .map(|m| {
+                let merge = ThreeWayMerge::with_base(
+                    repo,
+                    &repo.find_commit(m)?,
+                    &BaseStrategy::MergeBase,
+                )?;
+                Ok(merge.is_evil_merge(repo))
+            })
+            .collect()
+    })()
+    .map_err(|err| err.to_string())?;
+    if evil != [false, true, false, true] {
+        return Err(format!("Unexpected evil merges: {:?}", evil));
+    }
+    Ok(())
+}
+
+/// A clean merge needs no manual work, one with an extra line needs one, and a conflict resolved
+/// by hand needs both conflicting lines removed and the resolution added.
+#[test]
+fn churn() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let (extra_line, conflict) = (|| -> Result<(ThreeWayMerge, ThreeWayMerge), git2::Error> {
+        let base = "1\n2\n3\n4\n5\n";
+        let o = commit(repo, "five lines", base, &[], 1_600_020_000)?;
+        let a = commit(repo, "one", "one\n2\n3\n4\n5\n", &[o], 1_600_020_100)?;
+        let b = commit(repo, "five", "1\n2\n3\n4\nfive\n", &[o], 1_600_020_200)?;
+        let m = commit(
+            repo,
+            "Merge",
+            "one\n2\n3\n4\nfive\nsix\n",
+            &[a, b],
+            1_600_020_300,
+        )?;
+        let extra_line = merge_at(repo, m)?;
+        let o = commit(repo, "x", "x\n", &[], 1_600_020_400)?;
+        let a = commit(repo, "a", "a\n", &[o], 1_600_020_500)?;
+        let b = commit(repo, "b", "b\n", &[o], 1_600_020_600)?;
+        let m = commit(repo, "Merge", "ab\n", &[a, b], 1_600_020_700)?;
+        Ok((extra_line, merge_at(repo, m)?))
+    })()
+    .map_err(|err| err.to_string())?;
+    let churn = [
+        merge_at(repo, history.m)
+            .map_err(|err| err.to_string())?
+            .resolution_churn(repo),
+        extra_line.resolution_churn(repo),
+        conflict.resolution_churn(repo),
+    ];
+    if churn != [0, 1, 3] {
+        return Err(format!("Unexpected resolution churn: {:?}", churn));
+    }
+    Ok(())
+}
+
+/// A merge that only reindents a line of the naive merge is a whitespace-only resolution. The clean
+/// merge M changes nothing, and one adding a line changes more than whitespace, so neither is.
+#[test]
+fn only_whitespace_resolution() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let (reindented, extra_line) = (|| -> Result<(ThreeWayMerge, ThreeWayMerge), git2::Error> {
+        let o = commit(repo, "five lines", "1\n2\n3\n4\n5\n", &[], 1_600_021_000)?;
+        let a = commit(repo, "one", "one\n2\n3\n4\n5\n", &[o], 1_600_021_100)?;
+        let b = commit(repo, "five", "1\n2\n3\n4\nfive\n", &[o], 1_600_021_200)?;
+        let reindented = commit(
+            repo,
+            "Merge",
+            "one\n    2\n3\n4\nfive\n",
+            &[a, b],
+            1_600_021_300,
+        )?;
+        let extra_line = commit(
+            repo,
+            "Merge",
+            "one\n2\n3\n4\nfive\nsix\n",
+            &[a, b],
+            1_600_021_400,
+        )?;
+        Ok((merge_at(repo, reindented)?, merge_at(repo, extra_line)?))
+    })()
+    .map_err(|err| err.to_string())?;
+    let clean = fixture.merge()?;
+    let filter = merge::MergeFilter {
+        only_whitespace: true,
+        ..Default::default()
+    };
+    let found: Vec<_> = [&reindented, &clean, &extra_line]
+        .iter()
+        .map(|merge| {
+            (
+                merge.only_whitespace_resolution(repo),
+                filter.keep(merge, repo),
+            )
+        })
+        .collect();
+    if found != [(true, true), (false, false), (false, false)] {
+        return Err(format!(
+            "Expected only the reindenting merge to be whitespace-only, found {:?}",
+            found
+        ));
+    }
+    Ok(())
+}
+
+/// An extensionless script with a shell shebang is detected as shell, so a merge changing it is
+/// kept when filtering on shell, but not when filtering on Java.
+#[test]
+fn language_detection() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let script = "#!/bin/sh\necho hello\n";
+    if language::detect("bin/run", script.as_bytes()) != Some("shell") {
+        return Err("Expected an extensionless script with a sh shebang to be shell".to_owned());
+    }
+    let merge = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let email = "selftest@example.com";
+        let m = commit_files(
+            repo,
+            email,
+            "Merge b, adding a script",
+            &[(FILE, "A\nb\nc\nd\nE\n"), ("run", script)],
+            &[history.a, history.b],
+            1_600_000_360,
+        )?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let kept: Vec<bool> = ["shell", "python"]
+        .iter()
+        .map(|language| {
+            let filter = merge::MergeFilter {
+                languages: vec![language.to_string()],
+                ..Default::default()
+            };
+            filter.keep(&merge, repo)
+        })
+        .collect();
+    if kept != [true, false] {
+        return Err(format!("Unexpected merges kept by language: {:?}", kept));
+    }
+    Ok(())
+}
+
+/// Two files, each changed on one side only. Writing only the differing files leaves each out of
+/// the side that has M's version already, and writes both in o.
+#[test]
+fn only_differing() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let email = "selftest@example.com";
+        let files =
+            |first: &'static str, second: &'static str| [("first", first), ("second", second)];
+        let o = commit_files(
+            repo,
+            email,
+            "o",
+            &files("1\n", "2\n"),
+            &[history.o],
+            1_600_030_000,
+        )?;
+        let a = commit_files(repo, email, "a", &files("A\n", "2\n"), &[o], 1_600_030_100)?;
+        let b = commit_files(repo, email, "b", &files("1\n", "B\n"), &[o], 1_600_030_200)?;
+        let m = commit_files(
+            repo,
+            email,
+            "m",
+            &files("A\n", "B\n"),
+            &[a, b],
+            1_600_030_300,
+        )?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let written = |only_differing| {
+        let mut sink = sink::MemorySink::default();
+        let options = merge::WriteOptions {
+            only_differing,
+            ..Default::default()
+        };
+        merge.write_files(
+            &mut sink,
+            Path::new(""),
+            merge.files_to_consider(repo),
+            repo,
+            options,
+        );
+        sink.files.into_keys().collect::<Vec<_>>()
+    };
+    let expected: Vec<PathBuf> = [
+        "a/second", "b/first", "m/first", "m/second", "o/first", "o/second",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+    let all = written(false);
+    let differing = written(true);
+    if all.len() != 8 || differing != expected {
+        return Err(format!(
+            "Unexpected files written: {:?}, only differing {:?}",
+            all, differing
+        ));
+    }
+    Ok(())
+}
+
+/// A merge that only changes a lockfile is excluded, one that also changes a source file is kept.
+#[test]
+fn exclude_paths() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = |source: &'static str| {
+        (|| -> Result<ThreeWayMerge, git2::Error> {
+            let email = "selftest@example.com";
+            let files = |lock, source| [("Cargo.lock", lock), ("src/lib.rs", source)];
+            let o = commit_files(
+                repo,
+                email,
+                "o",
+                &files("o\n", "o\n"),
+                &[history.o],
+                1_600_060_000,
+            )?;
+            let a = commit_files(repo, email, "a", &files("a\n", "o\n"), &[o], 1_600_060_100)?;
+            let b = commit_files(repo, email, "b", &files("b\n", source), &[o], 1_600_060_200)?;
+            let m = commit_files(
+                repo,
+                email,
+                "m",
+                &files("ab\n", source),
+                &[a, b],
+                1_600_060_300,
+            )?;
+            merge_at(repo, m)
+        })()
+        .map_err(|err| err.to_string())
+    };
+    let filter = merge::MergeFilter {
+        exclude_paths: vec![glob::Pattern::new("*.lock").map_err(|err| err.to_string())?],
+        ..Default::default()
+    };
+    let lockfile_only = filter.keep(&merge("o\n")?, repo);
+    let with_source = filter.keep(&merge("b\n")?, repo);
+    if lockfile_only || !with_source {
+        return Err(format!(
+            "Expected only the lockfile merge to be excluded, kept {} and {}",
+            lockfile_only, with_source
+        ));
+    }
+    Ok(())
+}
+
+/// Merging B again after it was already merged changes nothing, so that merge is excluded while
+/// the first merge of B is kept.
+#[test]
+fn empty_merge() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let again = commit(
+        repo,
+        "Merge b again",
+        "A!\nb\nc\nd\nE\n",
+        &[history.fix, history.b],
+        1_600_000_500,
+    )
+    .and_then(|again| merge_at(repo, again))
+    .map_err(|err| err.to_string())?;
+    let first = merge_at(repo, history.m).map_err(|err| err.to_string())?;
+    let filter = merge::MergeFilter {
+        exclude_empty: true,
+        ..Default::default()
+    };
+    if !again.is_empty_merge(repo) || filter.keep(&again, repo) || !filter.keep(&first, repo) {
+        return Err(format!("Expected only {} to be an empty merge", again.m));
+    }
+    Ok(())
+}
+
+/// A file added while resolving the merge is new against O and against the parents. One added on
+/// A is only new against O. The merge of `build_history` adds none.
+#[test]
+fn new_files() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    use merge::NewFilesBase;
+    let merge = |on_a: &[(&str, &str)], in_m: &[(&str, &str)]| {
+        (|| -> Result<ThreeWayMerge, git2::Error> {
+            let email = "selftest@example.com";
+            let o = commit_files(
+                repo,
+                email,
+                "o",
+                &[("kept", "o\n")],
+                &[history.o],
+                1_600_090_000,
+            )?;
+            let a_files: Vec<_> = [("kept", "a\n")].iter().chain(on_a).copied().collect();
+            let a = commit_files(repo, email, "a", &a_files, &[o], 1_600_090_100)?;
+            let b = commit_files(repo, email, "b", &[("kept", "b\n")], &[o], 1_600_090_200)?;
+            let m_files: Vec<_> = [("kept", "ab\n")]
+                .iter()
+                .chain(on_a)
+                .chain(in_m)
+                .copied()
+                .collect();
+            let m = commit_files(repo, email, "m", &m_files, &[a, b], 1_600_090_300)?;
+            merge_at(repo, m)
+        })()
+        .map_err(|err| err.to_string())
+    };
+    let new_in = |merge: &ThreeWayMerge| {
+        (
+            merge.introduces_new_files(repo, NewFilesBase::O),
+            merge.introduces_new_files(repo, NewFilesBase::Parents),
+        )
+    };
+    let in_resolution = new_in(&merge(&[], &[("resolution", "m\n")])?);
+    let on_branch = new_in(&merge(&[("branch", "a\n")], &[])?);
+    let none = new_in(&merge_at(repo, history.m).map_err(|err| err.to_string())?);
+    if in_resolution != (true, true) || on_branch != (true, false) || none != (false, false) {
+        return Err(format!(
+            "Unexpected new files: in the resolution {:?}, on a branch {:?}, none {:?}",
+            in_resolution, on_branch, none
+        ));
+    }
+    Ok(())
+}
+
+/// A rewrites two lines, B only adds one.
+#[test]
+fn side_stats() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let a = commit(repo, "a", "A\nB\nc\nd\ne\n", &[history.o], 1_600_100_100)?;
+        let b = commit(repo, "b", "a\nb\nc\nd\ne\nf\n", &[history.o], 1_600_100_200)?;
+        let m = commit(repo, "m", "A\nB\nc\nd\ne\nf\n", &[a, b], 1_600_100_300)?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let stats = merge.side_stats(repo);
+    if stats != ((2, 2), (1, 0)) {
+        return Err(format!(
+            "Expected side stats ((2, 2), (1, 0)), found {:?}",
+            stats
+        ));
+    }
+    Ok(())
+}
+
+/// Both sides rewrite the same line, so the file conflicts and all four versions are written.
+#[test]
+fn conflicts() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let a = commit(repo, "a", "ours\nb\nc\nd\ne\n", &[history.o], 1_600_110_100)?;
+        let b = commit(
+            repo,
+            "b",
+            "theirs\nb\nc\nd\ne\n",
+            &[history.o],
+            1_600_110_200,
+        )?;
+        let m = commit(repo, "m", "both\nb\nc\nd\ne\n", &[a, b], 1_600_110_300)?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let mut sink = sink::MemorySink::default();
+    merge.write_conflicts(&mut sink, Path::new(""), repo);
+    let version = |suffix: &str| {
+        let path = Path::new("conflicts").join(format!("{}.{}", FILE, suffix));
+        sink.files.get(&path).map(|content| {
+            String::from_utf8_lossy(content)
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+    };
+    let found = ["base", "ours", "theirs", "resolved"].map(version);
+    if sink.files.len() != 4
+        || found
+            != [Some("a"), Some("ours"), Some("theirs"), Some("both")]
+                .map(|first| first.map(str::to_string))
+    {
+        return Err(format!(
+            "Expected the four versions of {}, found {:?}",
+            FILE,
+            sink.files.keys()
+        ));
+    }
+    Ok(())
+}
+
+/// O was made at 1600000000, A a thousand and B three thousand seconds later, and M (with a
+/// skewed clock) five hundred seconds before B. The lag from B is thus negative, not clamped.
+#[test]
+fn integration_lag() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let a = commit(repo, "a", "A\nb\nc\nd\ne\n", &[history.o], 1_600_001_000)?;
+        let b = commit(repo, "b", "a\nb\nc\nd\nE\n", &[history.o], 1_600_003_000)?;
+        let m = commit(repo, "m", "A\nb\nc\nd\nE\n", &[a, b], 1_600_002_500)?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let lag = merge.integration_lag(repo);
+    if lag != (2500, -500) {
+        return Err(format!(
+            "Expected an integration lag of (2500, -500), found {:?}",
+            lag
+        ));
+    }
+    Ok(())
+}
+
+/// A changes three lines and B one, so they are swapped, and swapping again changes nothing.
+#[test]
+fn order_by_change_size() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let (a, b, mut merge) = (|| -> Result<(Oid, Oid, ThreeWayMerge), git2::Error> {
+        let a = commit(repo, "a", "A\nB\nC\nd\ne\n", &[history.o], 1_600_070_100)?;
+        let b = commit(repo, "b", "a\nb\nc\nd\nE\n", &[history.o], 1_600_070_200)?;
+        let m = commit(repo, "m", "A\nB\nC\nd\nE\n", &[a, b], 1_600_070_300)?;
+        Ok((a, b, merge_at(repo, m)?))
+    })()
+    .map_err(|err| err.to_string())?;
+    merge.order_by_change_size(repo);
+    let swapped = (merge.a, merge.b, merge.a_b_swapped);
+    merge.order_by_change_size(repo);
+    if swapped != (b, a, true) || (merge.a, merge.b, merge.a_b_swapped) != swapped {
+        return Err(format!(
+            "Expected A and B swapped once, found {:?}",
+            swapped
+        ));
+    }
+    Ok(())
+}
+
+/// Of the three files each branch changes, two are changed by both.
+#[test]
+fn both_branch_count() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "both-branch-count")?;
+    let repo = &scratch;
+    (|| -> Result<(), git2::Error> {
+        let email = "selftest@example.com";
+        let files = |first, second, third| [("first", first), ("second", second), ("third", third)];
+        let o = commit_files(
+            repo,
+            email,
+            "o",
+            &files("o\n", "o\n", "o\n"),
+            &[],
+            1_600_000_000,
+        )?;
+        let a = commit_files(
+            repo,
+            email,
+            "a",
+            &files("a\n", "a\n", "a\n"),
+            &[o],
+            1_600_000_100,
+        )?;
+        let b = commit_files(
+            repo,
+            email,
+            "b",
+            &files("b\n", "b\n", "o\n"),
+            &[o],
+            1_600_000_200,
+        )?;
+        let m = commit_files(
+            repo,
+            email,
+            "m",
+            &files("ab\n", "ab\n", "a\n"),
+            &[a, b],
+            1_600_000_300,
+        )?;
+        repo.reference("refs/heads/main", m, true, "selftest")?;
+        repo.set_head("refs/heads/main")
+    })()
+    .map_err(|err| err.to_string())?;
+    let mut csv = vec![];
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    let options = publish::CsvOptions {
+        both_branch_count: true,
+        ..Default::default()
+    };
+    publish::write_csv_of_merges(
+        &mut csv,
+        repo,
+        revwalk,
+        None,
+        None,
+        &Default::default(),
+        &options,
+    )
+    .map_err(|err| err.to_string())?;
+    let csv = String::from_utf8_lossy(&csv);
+    let lines: Vec<_> = csv.lines().collect();
+    if lines.len() != 2 || !lines[0].ends_with(",both_branch_files") || !lines[1].ends_with(",2") {
+        return Err(format!(
+            "Expected two files changed in both branches, found:\n{}",
+            csv
+        ));
+    }
+    Ok(())
+}
+
+/// A merge where A changes FILE and B renames the package in Cargo.toml.
+fn build_merge(repo: &Repository) -> Result<ThreeWayMerge, git2::Error> {
+    let email = "selftest@example.com";
+    let manifest = "[package]\nname = \"selftest\"\n";
+    let o = commit_files(
+        repo,
+        email,
+        "o",
+        &[(FILE, "1\n"), ("Cargo.toml", manifest)],
+        &[],
+        1_600_007_000,
+    )?;
+    let a = commit_files(
+        repo,
+        email,
+        "a",
+        &[(FILE, "2\n"), ("Cargo.toml", manifest)],
+        &[o],
+        1_600_007_100,
+    )?;
+    let b = commit_files(
+        repo,
+        email,
+        "b",
+        &[
+            (FILE, "1\n"),
+            ("Cargo.toml", "[package]\nname = \"renamed\"\n"),
+        ],
+        &[o],
+        1_600_007_200,
+    )?;
+    let m = commit_files(
+        repo,
+        email,
+        "m",
+        &[
+            (FILE, "2\n"),
+            ("Cargo.toml", "[package]\nname = \"renamed\"\n"),
+        ],
+        &[a, b],
+        1_600_007_300,
+    )?;
+    merge_at(repo, m)
+}
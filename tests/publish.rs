@@ -0,0 +1,843 @@
+//! Writing merges and their fixes: CSVs, folder dumps, notes, and their metadata.
+
+mod common;
+
+use common::{commit, commit_files, merge_at, scratch_repo, Fixture, FILE};
+use git2::{Oid, Repository, Signature};
+use std::path::{Path, PathBuf};
+use three_way_merge_finder::merge::{BaseStrategy, PathStyle, ThreeWayMerge};
+use three_way_merge_finder::{compare, git_utils, merge, publish, sink};
+
+#[test]
+fn csv_of_merges() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let merge = &fixture.merge()?;
+    let mut csv = vec![];
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    publish::write_csv_of_merges(
+        &mut csv,
+        repo,
+        revwalk,
+        None,
+        None,
+        &Default::default(),
+        &Default::default(),
+    )
+    .map_err(|err| err.to_string())?;
+    let csv = String::from_utf8_lossy(&csv);
+    let lines: Vec<_> = csv.lines().collect();
+    if lines.len() != 2
+        || !lines[0].starts_with("O,A,B,M,")
+        || !lines[1].starts_with(&merge.to_csv_line())
+    {
+        return Err(format!("Unexpected CSV of merges:\n{}", csv));
+    }
+    Ok(())
+}
+
+#[test]
+fn csv_of_bug_fixes() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let mut csv = vec![];
+    let options = publish::BugFixOptions {
+        fix_distance: 10,
+        max_fixes: 3,
+        ..Default::default()
+    };
+    let broken = [(
+        history.o.to_string(),
+        history.a.to_string(),
+        history.b.to_string(),
+        history.m.to_string(),
+    )];
+    publish::write_bug_fix_csv(&mut csv, repo, broken, &options).map_err(|err| err.to_string())?;
+    let expected = format!("{},{},,\n", history.m, history.fix);
+    if csv != expected.as_bytes() {
+        return Err(format!(
+            "Expected bug fix CSV {:?}, found {:?}",
+            expected,
+            String::from_utf8_lossy(&csv)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn analyze() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    // Both in one pass: the merge, with its fix in the first fix column.
+    let options = publish::BugFixOptions {
+        fix_distance: 10,
+        max_fixes: 3,
+        ..Default::default()
+    };
+    let mut csv = vec![];
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    publish::write_analysis_csv(&mut csv, repo, revwalk, None, &Default::default(), &options)
+        .map_err(|err| err.to_string())?;
+    let expected = format!(
+        "O,A,B,M,changed_files,timestamp,fix1,fix2,fix3\n{},{},{},{},,\n",
+        merge.to_csv_line(),
+        merge.files_to_consider(repo).len(),
+        merge.time(repo),
+        history.fix
+    );
+    if csv != expected.as_bytes() {
+        return Err(format!(
+            "Expected analysis {:?}, found {:?}",
+            expected,
+            String::from_utf8_lossy(&csv)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn notes() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = &fixture.merge()?;
+    let fixes = [history.fix];
+    let notes_ref = "refs/notes/selftest";
+    let written =
+        publish::write_merge_note(repo, notes_ref, merge, &fixes).map_err(|err| err.to_string())?;
+    let rewritten =
+        publish::write_merge_note(repo, notes_ref, merge, &fixes).map_err(|err| err.to_string())?;
+    let note = repo
+        .find_note(Some(notes_ref), history.m)
+        .map_err(|err| err.to_string())?;
+    let message = note.message().unwrap_or_default();
+    if !written || rewritten || !message.contains(&history.fix.to_string()) {
+        return Err(format!("Unexpected note on the merge: {}", message));
+    }
+    Ok(())
+}
+
+#[test]
+fn memory_sink() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let merge = &fixture.merge()?;
+    let mut sink = sink::MemorySink::default();
+    let prefix = std::path::Path::new("merge");
+    merge.write_files(
+        &mut sink,
+        prefix,
+        merge.files_to_consider(repo),
+        repo,
+        Default::default(),
+    );
+    let written: Vec<_> = sink.files.keys().cloned().collect();
+    let expected: Vec<_> = ["a", "b", "m", "o"]
+        .iter()
+        .map(|part| prefix.join(part).join(FILE))
+        .collect();
+    if written != expected || sink.files[&prefix.join("m").join(FILE)] != b"A\nb\nc\nd\nE\n" {
+        return Err(format!("Unexpected files written to memory: {:?}", written));
+    }
+    Ok(())
+}
+
+#[test]
+fn deduplicated_parts() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let prefix = Path::new("merge");
+    // B branches off O and is merged into O itself, so O is A. With deduplication, o is a link to
+    // a on disk, and left out (as noted in merge.json) where links are not possible.
+    let trivial = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let b = commit(repo, "b", "a\nb\nc\nd\nB\n", &[history.o], 1_600_003_000)?;
+        let m = commit(repo, "m", "a\nb\nc\nd\nB\n", &[history.o, b], 1_600_003_100)?;
+        ThreeWayMerge::with_base(repo, &repo.find_commit(m)?, &BaseStrategy::MergeBase)
+    })()
+    .map_err(|err| err.to_string())?;
+    let options = merge::WriteOptions {
+        dedup_identical_parts: true,
+        ..Default::default()
+    };
+    let files = trivial.files_to_consider(repo);
+    let mut sink = sink::MemorySink::default();
+    let o_same_as = trivial.write_files(&mut sink, prefix, files.clone(), repo, options);
+    trivial.write_record(&mut sink, prefix, repo, PathStyle::Posix, None, o_same_as);
+    let record = String::from_utf8_lossy(&sink.files[&prefix.join("merge.json")]).into_owned();
+    if o_same_as != Some("a")
+        || sink
+            .files
+            .keys()
+            .any(|path| path.starts_with(prefix.join("o")))
+        || !record.contains(r#""o_same_as": "a""#)
+    {
+        return Err(format!(
+            "Unexpected deduplicated files in memory: {:?}",
+            sink.files.keys()
+        ));
+    }
+    #[cfg(unix)]
+    {
+        let folder = repo.path().join("selftest-dedup");
+        let o_same_as = trivial.write_files_to_disk(&folder, files, repo, options);
+        let link = std::fs::read_link(folder.join("o")).map_err(|err| err.to_string())?;
+        if o_same_as.is_some() || link != Path::new("a") || !folder.join("o").join(FILE).is_file() {
+            return Err(format!("Expected o to link to a, found {:?}", link));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn find_bug_fix_dry_run() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // A dump with just the merge folder: a dry run must not add bug fix folders to it.
+    let dump = repo.path().join("selftest-dump");
+    let merge_folder = dump.join(history.m.to_string());
+    std::fs::create_dir_all(merge_folder.join("m")).map_err(|err| err.to_string())?;
+    std::fs::write(merge_folder.join("m").join(FILE), "A\nb\nc\nd\nE\n")
+        .map_err(|err| err.to_string())?;
+    let options = publish::FixFolderOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    let mut plan = vec![];
+    publish::write_bug_fix_files(&mut plan, &dump, repo, &options)
+        .map_err(|err| err.to_string())?;
+    let expected = format!("{}: 1 candidate fixes\n  bf1 {}\n", history.m, history.fix);
+    if merge_folder.join("bf1").exists() || plan != expected.as_bytes() {
+        return Err(format!(
+            "Dry run wrote a bf1 folder or the plan {:?}",
+            String::from_utf8_lossy(&plan)
+        ));
+    }
+    // Without it, the fix is written, and its CSV line.
+    let mut csv = vec![];
+    publish::write_bug_fix_files(&mut csv, &dump, repo, &Default::default())
+        .map_err(|err| err.to_string())?;
+    let expected = format!("{},{},,\n", history.m, history.fix);
+    if !merge_folder.join("bf1").join(FILE).is_file() || csv != expected.as_bytes() {
+        return Err(format!(
+            "Expected bf1 and the CSV line {:?}, found {:?}",
+            expected,
+            String::from_utf8_lossy(&csv)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn find_merge_resume() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // An interrupted dump: the merge folder exists, but was never marked as done.
+    let partial = repo.path().join("selftest-resume");
+    let merge_folder = partial.join(history.m.to_string());
+    std::fs::create_dir_all(merge_folder.join("o")).map_err(|err| err.to_string())?;
+    let resume = || resume_dump(repo, &partial);
+    resume()?;
+    let done = merge_folder.join(publish::DONE_SENTINEL);
+    if !done.is_file() || !merge_folder.join("merge.json").is_file() {
+        return Err("Resuming did not complete the interrupted merge".to_owned());
+    }
+    // Completed merges are left alone.
+    std::fs::remove_file(merge_folder.join("merge.json")).map_err(|err| err.to_string())?;
+    resume()?;
+    if merge_folder.join("merge.json").exists() {
+        return Err("Resuming rewrote a completed merge".to_owned());
+    }
+    Ok(())
+}
+
+#[test]
+fn compare_dumps() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // An interrupted dump without merge.json, and a complete dump of the same merge. Then one
+    // where a file was changed.
+    let partial = repo.path().join("selftest-resume");
+    let merge_folder = partial.join(history.m.to_string());
+    std::fs::create_dir_all(merge_folder.join("o")).map_err(|err| err.to_string())?;
+    let resume = || resume_dump(repo, &partial);
+    resume()?;
+    std::fs::remove_file(merge_folder.join("merge.json")).map_err(|err| err.to_string())?;
+    let complete = repo.path().join("selftest-complete");
+    resume_dump(repo, &complete)?;
+    let differences = compare::compare_dumps(&partial, &complete).map_err(|err| err.to_string())?;
+    let json = PathBuf::from(history.m.to_string()).join("merge.json");
+    if differences != [compare::DumpDifference::OnlyInRight(json)] {
+        return Err(format!("Unexpected differences {:?}", differences));
+    }
+    let changed = PathBuf::from(history.m.to_string()).join("m").join(FILE);
+    std::fs::write(complete.join(&changed), "changed\n").map_err(|err| err.to_string())?;
+    std::fs::remove_file(merge_folder.join(publish::DONE_SENTINEL))
+        .map_err(|err| err.to_string())?;
+    resume()?;
+    let differences = compare::compare_dumps(&partial, &complete).map_err(|err| err.to_string())?;
+    if differences != [compare::DumpDifference::Differs(changed.clone())] {
+        return Err(format!("Unexpected differences {:?}", differences));
+    }
+    // Dangling symlinks all over the dumps are skipped, rather than ending the walk early.
+    #[cfg(unix)]
+    {
+        let merge = PathBuf::from(history.m.to_string());
+        for dump in [&partial, &complete] {
+            for folder in [
+                PathBuf::new(),
+                merge.clone(),
+                merge.join("m"),
+                merge.join("o"),
+            ] {
+                std::os::unix::fs::symlink("missing", dump.join(folder).join("dangling"))
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        let differences =
+            compare::compare_dumps(&partial, &complete).map_err(|err| err.to_string())?;
+        if differences != [compare::DumpDifference::Differs(changed)] {
+            return Err(format!(
+                "Unexpected differences with dangling symlinks {:?}",
+                differences
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn run_metadata() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    // A dump and a CSV recording how they were made.
+    let complete = repo.path().join("selftest-complete");
+    resume_dump(repo, &complete)?;
+    let args: Vec<String> = [
+        "three-way-merge-finder",
+        "find-merge",
+        "--with-run-meta",
+        ".",
+    ]
+    .map(str::to_owned)
+    .to_vec();
+    let run_meta = publish::RunMeta::new(repo, args.clone());
+    let meta_dump = repo.path().join("selftest-run-meta");
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    let options = publish::DumpOptions {
+        run_meta: Some(run_meta.clone()),
+        ..Default::default()
+    };
+    publish::folder_dump(
+        &meta_dump,
+        repo,
+        revwalk,
+        None,
+        None,
+        &Default::default(),
+        &options,
+    );
+    let written =
+        std::fs::read(meta_dump.join(publish::RUN_META_FILE)).map_err(|err| err.to_string())?;
+    let written: serde_json::Value =
+        serde_json::from_slice(&written).map_err(|err| err.to_string())?;
+    if written["version"] != env!("CARGO_PKG_VERSION")
+        || written["args"] != serde_json::json!(args)
+        || written["head"] != history.fix.to_string()
+        || !compare::compare_dumps(&meta_dump, &complete)
+            .map_err(|err| err.to_string())?
+            .iter()
+            .all(|difference| !difference.to_string().contains(publish::RUN_META_FILE))
+    {
+        return Err(format!(
+            "Unexpected {}: {}",
+            publish::RUN_META_FILE,
+            written
+        ));
+    }
+    let mut csv = vec![];
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    let options = publish::CsvOptions {
+        run_meta: Some(run_meta),
+        ..Default::default()
+    };
+    publish::write_csv_of_merges(
+        &mut csv,
+        repo,
+        revwalk,
+        None,
+        None,
+        &Default::default(),
+        &options,
+    )
+    .map_err(|err| err.to_string())?;
+    let csv = String::from_utf8_lossy(&csv);
+    if !csv.starts_with("# run_meta={\"version\":")
+        || !csv
+            .lines()
+            .nth(1)
+            .is_some_and(|line| line.starts_with("O,A,B,M"))
+    {
+        return Err(format!("Unexpected CSV with run metadata:\n{}", csv));
+    }
+    Ok(())
+}
+
+/// Every merge folder gets a merge.json describing the merge, with or without all files.
+#[test]
+fn merge_json() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    for all_files in [false, true] {
+        let dump = repo
+            .path()
+            .join(format!("selftest-merge-json-{}", all_files));
+        let revwalk = git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL)
+            .map_err(|err| err.to_string())?;
+        let options = publish::DumpOptions {
+            all_files,
+            ..Default::default()
+        };
+        publish::folder_dump(
+            &dump,
+            repo,
+            revwalk,
+            None,
+            None,
+            &Default::default(),
+            &options,
+        );
+        let json = std::fs::read(dump.join(history.m.to_string()).join("merge.json"))
+            .map_err(|err| err.to_string())?;
+        let record: serde_json::Value =
+            serde_json::from_slice(&json).map_err(|err| err.to_string())?;
+        let expected = serde_json::json!({
+            "o": history.o.to_string(),
+            "a": history.a.to_string(),
+            "b": history.b.to_string(),
+            "m": history.m.to_string(),
+            "timestamp": 1_600_000_300,
+            "changed_files": [FILE],
+            "changed_file_tree": { FILE: null },
+            "distinct_o": true,
+            "a_b_swapped": false,
+        });
+        if record != expected {
+            return Err(format!("Unexpected merge.json: {}", record));
+        }
+    }
+    Ok(())
+}
+
+/// The relative_days column counts whole days from the first commit, here three and a half.
+#[test]
+fn relative_time() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let scratch = scratch_repo(repo, "relative-time")?;
+    let repo = &scratch;
+    let start = 1_600_000_000;
+    (|| -> Result<(), git2::Error> {
+        let root = commit(repo, "root", "r\n", &[], start)?;
+        let a = commit(repo, "a", "a\n", &[root], start + 86_400)?;
+        let b = commit(repo, "b", "b\n", &[root], start + 2 * 86_400)?;
+        let m = commit(
+            repo,
+            "Merge b",
+            "ab\n",
+            &[a, b],
+            start + 3 * 86_400 + 43_200,
+        )?;
+        repo.reference("refs/heads/main", m, true, "selftest")?;
+        repo.set_head("refs/heads/main")
+    })()
+    .map_err(|err| err.to_string())?;
+    let mut csv = vec![];
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    let options = publish::CsvOptions {
+        relative_time: true,
+        ..Default::default()
+    };
+    publish::write_csv_of_merges(
+        &mut csv,
+        repo,
+        revwalk,
+        None,
+        None,
+        &Default::default(),
+        &options,
+    )
+    .map_err(|err| err.to_string())?;
+    let csv = String::from_utf8_lossy(&csv);
+    let lines: Vec<_> = csv.lines().collect();
+    if lines.len() != 2 || !lines[0].ends_with(",relative_days") || !lines[1].ends_with(",3") {
+        return Err(format!("Expected a merge 3 days in, found:\n{}", csv));
+    }
+    Ok(())
+}
+
+/// A merge authored a day after the first commit, but only committed ten days after it: the
+/// relative_days column follows the timestamp column in which of the two it counts to.
+#[test]
+fn relative_time_by_source() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let scratch = scratch_repo(&fixture.repo, "relative-time-by-source")?;
+    let repo = &scratch;
+    let start = 1_600_000_000;
+    (|| -> Result<(), git2::Error> {
+        let root = commit(repo, "root", "r\n", &[], start)?;
+        let a = repo.find_commit(commit(repo, "a", "a\n", &[root], start + 100)?)?;
+        let b = repo.find_commit(commit(repo, "b", "b\n", &[root], start + 200)?)?;
+        let author = Signature::new(
+            "Self Test",
+            "selftest@example.com",
+            &git2::Time::new(start + 86_400, 0),
+        )?;
+        let committer = Signature::new(
+            "Self Test",
+            "selftest@example.com",
+            &git2::Time::new(start + 10 * 86_400, 0),
+        )?;
+        let tree = b.tree()?;
+        let m = repo.commit(None, &author, &committer, "Merge b", &tree, &[&a, &b])?;
+        repo.reference("refs/heads/main", m, true, "selftest")?;
+        repo.set_head("refs/heads/main")
+    })()
+    .map_err(|err| err.to_string())?;
+    for (time_source, expected) in [
+        (merge::TimeSource::Committer, ",10"),
+        (merge::TimeSource::Author, ",1"),
+    ] {
+        let mut csv = vec![];
+        let revwalk = git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL)
+            .map_err(|err| err.to_string())?;
+        let options = publish::CsvOptions {
+            relative_time: true,
+            time_source,
+            ..Default::default()
+        };
+        publish::write_csv_of_merges(
+            &mut csv,
+            repo,
+            revwalk,
+            None,
+            None,
+            &Default::default(),
+            &options,
+        )
+        .map_err(|err| err.to_string())?;
+        let csv = String::from_utf8_lossy(&csv);
+        if !csv
+            .lines()
+            .nth(1)
+            .is_some_and(|line| line.ends_with(expected))
+        {
+            return Err(format!(
+                "Expected a merge {} days in, found:\n{}",
+                &expected[1..],
+                csv
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A tar archive (plain or compressed) holds the files of the merge below the same paths as a
+/// folder dump, with the same contents.
+#[test]
+fn tar_dump() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    for name in ["selftest.tar", "selftest.tar.gz"] {
+        let archive = repo.path().join(name);
+        let revwalk = git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL)
+            .map_err(|err| err.to_string())?;
+        publish::tar_dump(
+            &archive,
+            repo,
+            revwalk,
+            None,
+            None,
+            &Default::default(),
+            &Default::default(),
+        )
+        .map_err(|err| err.to_string())?;
+        let file = std::fs::File::open(&archive).map_err(|err| err.to_string())?;
+        let reader: Box<dyn std::io::Read> = if name.ends_with(".gz") {
+            Box::new(flate2::read::GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut entries = std::collections::BTreeMap::new();
+        let mut tar = tar::Archive::new(reader);
+        for entry in tar.entries().map_err(|err| err.to_string())? {
+            let mut entry = entry.map_err(|err| err.to_string())?;
+            let path = entry
+                .path()
+                .map_err(|err| err.to_string())?
+                .to_string_lossy()
+                .into_owned();
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut content)
+                .map_err(|err| err.to_string())?;
+            entries.insert(path, content);
+        }
+        for (part, content) in [
+            ("o", "a\nb\nc\nd\ne\n"),
+            ("a", "A\nb\nc\nd\ne\n"),
+            ("b", "a\nb\nc\nd\nE\n"),
+            ("m", "A\nb\nc\nd\nE\n"),
+        ] {
+            let path = format!("{}/{}/{}", history.m, part, FILE);
+            if entries.get(&path).map(String::as_str) != Some(content) {
+                return Err(format!(
+                    "{}: expected {:?} in {}, found entries {:?}",
+                    name,
+                    content,
+                    path,
+                    entries.keys()
+                ));
+            }
+        }
+        if !entries.contains_key(&format!("{}/merge.json", history.m)) {
+            return Err(format!("{}: no merge.json in {:?}", name, entries.keys()));
+        }
+    }
+    Ok(())
+}
+
+/// The files of a merge come sorted, whatever order git or the hash set has them in.
+#[test]
+fn sorted_files() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let merge = (|| -> Result<ThreeWayMerge, git2::Error> {
+        let email = "selftest@example.com";
+        let names = ["zeta", "Alpha", "src/b.rs", "src/a.rs", "beta", "README"];
+        let o = commit_files(
+            repo,
+            email,
+            "o",
+            &names.map(|name| (name, "o\n")),
+            &[history.o],
+            1_600_080_000,
+        )?;
+        let a = commit_files(
+            repo,
+            email,
+            "a",
+            &names.map(|name| (name, "a\n")),
+            &[o],
+            1_600_080_100,
+        )?;
+        let b = commit_files(
+            repo,
+            email,
+            "b",
+            &names.map(|name| (name, "b\n")),
+            &[o],
+            1_600_080_200,
+        )?;
+        let m = commit_files(
+            repo,
+            email,
+            "m",
+            &names.map(|name| (name, "ab\n")),
+            &[a, b],
+            1_600_080_300,
+        )?;
+        merge_at(repo, m)
+    })()
+    .map_err(|err| err.to_string())?;
+    let sorted = merge.files_to_consider_sorted(repo);
+    let expected = ["Alpha", "README", "beta", "src/a.rs", "src/b.rs", "zeta"];
+    if sorted != expected {
+        return Err(format!(
+            "Expected the files {:?}, found {:?}",
+            expected, sorted
+        ));
+    }
+    Ok(())
+}
+
+/// The schema line comes first and parses to the current version, the column header after it.
+#[test]
+fn schema_header() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    let mut csv = vec![];
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    let options = publish::CsvOptions {
+        schema_header: true,
+        ..Default::default()
+    };
+    publish::write_csv_of_merges(
+        &mut csv,
+        repo,
+        revwalk,
+        None,
+        None,
+        &Default::default(),
+        &options,
+    )
+    .map_err(|err| err.to_string())?;
+    let csv = String::from_utf8_lossy(&csv);
+    let mut lines = csv.lines();
+    let version = lines
+        .next()
+        .and_then(|line| line.strip_prefix("# schema="))
+        .and_then(|version| version.parse::<u32>().ok());
+    if version != Some(publish::CSV_SCHEMA_VERSION)
+        || !lines.next().is_some_and(|line| line.starts_with("O,A,B,M"))
+    {
+        return Err(format!(
+            "Expected a schema line before the header, found:\n{}",
+            csv
+        ));
+    }
+    Ok(())
+}
+
+/// A file that cannot be renamed into place (there is a folder of its name) leaves neither a
+/// partial file nor the temporary file behind. A successful write leaves only the final file.
+#[test]
+fn atomic_writes() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let repo = &fixture.repo;
+    use sink::BlobSink;
+    let folder = repo.path().join("selftest-atomic");
+    let _ = std::fs::remove_dir_all(&folder);
+    std::fs::create_dir_all(folder.join("blocked").join("inside"))
+        .map_err(|err| err.to_string())?;
+    let mut sink = sink::FolderSink::new(&folder);
+    if sink.put(Path::new("blocked"), b"content", false).is_ok() {
+        return Err("Expected writing over a folder to fail".to_string());
+    }
+    sink.put(Path::new("written"), b"content", false)
+        .map_err(|err| err.to_string())?;
+    let mut names: Vec<_> = std::fs::read_dir(&folder)
+        .map_err(|err| err.to_string())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    let written = std::fs::read(folder.join("written")).map_err(|err| err.to_string())?;
+    if names != ["blocked", "written"] || !folder.join("blocked").is_dir() || written != b"content"
+    {
+        return Err(format!(
+            "Expected only the folder and the written file, found {:?}",
+            names
+        ));
+    }
+    Ok(())
+}
+
+/// The Parquet file reads back with one row for the merge, with M in the m column.
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    let path = repo.path().join("selftest.parquet");
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    publish::parquet_dump(&path, repo, revwalk, None, None, &Default::default(), false)
+        .map_err(|err| err.to_string())?;
+    let file = std::fs::File::open(&path).map_err(|err| err.to_string())?;
+    let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file)
+        .and_then(|builder| builder.build())
+        .map_err(|err| err.to_string())?;
+    let mut rows = 0;
+    let mut merges = vec![];
+    for batch in reader {
+        let batch = batch.map_err(|err| err.to_string())?;
+        rows += batch.num_rows();
+        let column = batch
+            .column_by_name("m")
+            .and_then(|column| column.as_any().downcast_ref::<arrow_array::StringArray>())
+            .ok_or("No m column of strings")?;
+        merges.extend(column.iter().map(|m| m.unwrap_or_default().to_string()));
+    }
+    if rows != 1 || merges != [history.m.to_string()] {
+        return Err(format!(
+            "Expected one row for {}, found {} with {:?}",
+            history.m, rows, merges
+        ));
+    }
+    Ok(())
+}
+
+/// A blob stored as executable is written with the executable bits set, a regular one without.
+#[cfg(unix)]
+#[test]
+fn executable_bit() -> Result<(), String> {
+    let fixture = Fixture::new()?;
+    let (repo, history) = (&fixture.repo, &fixture.history);
+    use std::os::unix::fs::PermissionsExt;
+    let commit = (|| -> Result<Oid, git2::Error> {
+        let mut builder = repo.treebuilder(None)?;
+        builder.insert("run.sh", repo.blob(b"#!/bin/sh\necho run\n")?, 0o100755)?;
+        builder.insert("data.txt", repo.blob(b"data\n")?, 0o100644)?;
+        let tree = repo.find_tree(builder.write()?)?;
+        let signature = Signature::new(
+            "Self Test",
+            "selftest@example.com",
+            &git2::Time::new(1_600_050_000, 0),
+        )?;
+        let parent = repo.find_commit(history.o)?;
+        repo.commit(
+            None,
+            &signature,
+            &signature,
+            "Add a script",
+            &tree,
+            &[&parent],
+        )
+    })()
+    .map_err(|err| err.to_string())?;
+    let folder = repo.path().join("selftest-executable");
+    let _ = std::fs::remove_dir_all(&folder);
+    let files: std::collections::HashSet<String> = ["run.sh", "data.txt"]
+        .iter()
+        .map(|file| file.to_string())
+        .collect();
+    git_utils::write_files_from_commit_to_disk(
+        &folder,
+        commit,
+        repo,
+        &files,
+        "script",
+        Default::default(),
+    );
+    let mode = |file: &str| {
+        std::fs::metadata(folder.join(file))
+            .map(|metadata| metadata.permissions().mode() & 0o111)
+            .map_err(|err| err.to_string())
+    };
+    let (script, data) = (mode("run.sh")?, mode("data.txt")?);
+    if script == 0 || data != 0 {
+        return Err(format!(
+            "Unexpected executable bits: run.sh {:o}, data.txt {:o}",
+            script, data
+        ));
+    }
+    Ok(())
+}
+
+/// Dumps the merges reachable from HEAD to the folder, skipping those already done.
+fn resume_dump(repo: &Repository, folder: &Path) -> Result<(), String> {
+    let revwalk =
+        git_utils::create_revwalk(repo, git2::Sort::TOPOLOGICAL).map_err(|err| err.to_string())?;
+    let options = publish::DumpOptions {
+        resume: true,
+        ..Default::default()
+    };
+    let filter = merge::MergeFilter::default();
+    publish::folder_dump(folder, repo, revwalk, None, None, &filter, &options);
+    Ok(())
+}